@@ -0,0 +1,210 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use crate::data_structures::{AircraftBeacon, Observer};
+use crate::spatial_index::SpatialIndex;
+
+
+const DEFAULT_TIMEOUT: i64 = 5 * 60;        // [s]
+const DEFAULT_MAX_TRACK_LEN: usize = 1000;  // [fixes]
+const INDEX_CELL_SIZE: f64 = 0.25;          // [deg]
+
+#[derive(Debug, Clone)]
+pub enum TrackerEvent {
+    /// First beacon of an aircraft (or the first one after it had been lost).
+    AircraftAppeared(AircraftBeacon),
+    AircraftUpdated(AircraftBeacon),
+    /// Nothing heard from the aircraft for the timeout; carries its last known state.
+    AircraftLost(AircraftBeacon),
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackedAircraft {
+    pub id: String,         // callsign, e.g. FLRDDA5BA
+    pub first_seen_ts: i64,
+    pub current: AircraftBeacon,
+    pub track: VecDeque<AircraftBeacon>,    // oldest first, bounded
+}
+
+impl TrackedAircraft {
+    pub fn last_seen_ts(&self) -> i64 {
+        self.current.ts
+    }
+}
+
+/// Keeps the latest state and a bounded track history of every aircraft heard recently.
+/// The time is driven by the beacon timestamps so that it works the same on live and replayed data.
+pub struct AircraftTracker {
+    aircraft: HashMap<String, TrackedAircraft>,
+    index: SpatialIndex,
+    timeout: i64,           // [s]
+    max_track_len: usize,
+    clock_ts: i64,          // most recent beacon ts
+    last_expire_ts: i64,
+    event_listener: Option<Rc<RefCell<dyn Observer<TrackerEvent>>>>,
+}
+
+impl AircraftTracker {
+    pub fn new() -> Self {
+        Self {
+            aircraft: HashMap::new(),
+            index: SpatialIndex::new(INDEX_CELL_SIZE),
+            timeout: DEFAULT_TIMEOUT,
+            max_track_len: DEFAULT_MAX_TRACK_LEN,
+            clock_ts: 0,
+            last_expire_ts: 0,
+            event_listener: None,
+        }
+    }
+
+    /// An aircraft is lost when not heard for this long [s].
+    pub fn set_timeout(&mut self, timeout: i64) {
+        self.timeout = timeout;
+    }
+
+    /// Maximum number of fixes kept in the track of each aircraft.
+    pub fn set_max_track_len(&mut self, max_track_len: usize) {
+        self.max_track_len = max_track_len;
+    }
+
+    pub fn set_event_listener(&mut self, listener: impl Observer<TrackerEvent> + 'static) {
+        self.event_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    /// Ingests one beacon. Beacons not newer than the aircraft's current state are ignored.
+    pub fn update(&mut self, beacon: AircraftBeacon) {
+        if beacon.ts > self.clock_ts {
+            self.clock_ts = beacon.ts;
+        }
+
+        let id = beacon.callsign();
+        let (lat, lon) = (beacon.lat, beacon.lon);
+        let event = match self.aircraft.get_mut(&id) {
+            Some(aircraft) => {
+                if beacon.ts <= aircraft.current.ts {
+                    return;
+                }
+
+                aircraft.track.push_back(beacon.clone());
+                while aircraft.track.len() > self.max_track_len {
+                    aircraft.track.pop_front();
+                }
+                aircraft.current = beacon.clone();
+
+                TrackerEvent::AircraftUpdated(beacon)
+            },
+            None => {
+                let aircraft = TrackedAircraft {
+                    id: id.clone(),
+                    first_seen_ts: beacon.ts,
+                    current: beacon.clone(),
+                    track: VecDeque::from(vec![beacon.clone()]),
+                };
+                self.aircraft.insert(id.clone(), aircraft);
+
+                TrackerEvent::AircraftAppeared(beacon)
+            },
+        };
+        self.index.insert(&id, lat, lon);
+        self.notify_event_listener(event);
+
+        if self.clock_ts > self.last_expire_ts {
+            self.expire(self.clock_ts);
+        }
+    }
+
+    /// Removes aircraft not heard since `now_ts - timeout` and announces them as lost.
+    pub fn expire(&mut self, now_ts: i64) {
+        self.last_expire_ts = now_ts;
+
+        let lost_ids: Vec<String> = self.aircraft.values()
+            .filter(|a| a.last_seen_ts() < now_ts - self.timeout)
+            .map(|a| a.id.clone())
+            .collect();
+
+        for id in lost_ids {
+            self.index.remove(&id);
+            if let Some(aircraft) = self.aircraft.remove(&id) {
+                self.notify_event_listener(TrackerEvent::AircraftLost(aircraft.current));
+            }
+        }
+    }
+
+    /// Time of the most recent beacon seen [s].
+    pub fn clock_ts(&self) -> i64 {
+        self.clock_ts
+    }
+
+    pub fn len(&self) -> usize {
+        self.aircraft.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aircraft.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&TrackedAircraft> {
+        self.aircraft.get(id)
+    }
+
+    /// Current state of all tracked aircraft.
+    pub fn current_aircraft(&self) -> Vec<AircraftBeacon> {
+        self.aircraft.values().map(|a| a.current.clone()).collect()
+    }
+
+    /// Track of the aircraft since the given time (inclusive).
+    pub fn track_since(&self, id: &str, since_ts: i64) -> Vec<AircraftBeacon> {
+        match self.aircraft.get(id) {
+            Some(aircraft) => aircraft.track.iter().filter(|b| b.ts >= since_ts).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Iterates over the tracked aircraft as they are right now.
+    pub fn iter(&self) -> impl Iterator<Item = &TrackedAircraft> {
+        self.aircraft.values()
+    }
+
+    /// Aircraft within the box. When lon_w > lon_e the box spans across the antimeridian.
+    pub fn aircraft_in_bbox(&self, lat_s: f64, lon_w: f64, lat_n: f64, lon_e: f64) -> Vec<AircraftBeacon> {
+        self.index.in_bbox(lat_s, lon_w, lat_n, lon_e)
+            .iter()
+            .filter_map(|id| self.aircraft.get(id).map(|a| a.current.clone()))
+            .collect()
+    }
+
+    /// Aircraft within the radius [m] around the point with their distances [m], nearest first.
+    pub fn aircraft_within(&self, lat: f64, lon: f64, radius: f64) -> Vec<(AircraftBeacon, f64)> {
+        self.with_beacons(self.index.within_radius(lat, lon, radius))
+    }
+
+    /// The k aircraft nearest to the point with their distances [m], nearest first.
+    pub fn nearest_aircraft(&self, lat: f64, lon: f64, k: usize) -> Vec<(AircraftBeacon, f64)> {
+        self.with_beacons(self.index.nearest(lat, lon, k))
+    }
+
+    fn with_beacons(&self, ids: Vec<(String, f64)>) -> Vec<(AircraftBeacon, f64)> {
+        ids.into_iter()
+            .filter_map(|(id, dist)| self.aircraft.get(&id).map(|a| (a.current.clone(), dist)))
+            .collect()
+    }
+
+    fn notify_event_listener(&mut self, event: TrackerEvent) {
+        if let Some(listener) = self.event_listener.as_mut() {
+            listener.borrow_mut().notify(event);
+        }
+    }
+}
+
+impl Default for AircraftTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for AircraftTracker {
+    fn notify(&mut self, beacon: AircraftBeacon) {
+        self.update(beacon);
+    }
+}
@@ -0,0 +1,261 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use log::warn;
+use serde_json::Value;
+
+use crate::data_structures::{AircraftBeacon, Observer};
+use crate::flight_detector::FlightEvent;
+use crate::spatial_index::SpatialIndex;
+use crate::geodesy::haversine_distance;
+use crate::utils::split_csv_line;
+
+
+const FEET_TO_METERS: f64 = 0.3048;
+const DEFAULT_ANNOTATION_RADIUS: f64 = 20_000.0;    // [m]
+const CUP_TASKS_SECTION: &str = "-----Related Tasks-----";
+const CUP_AIRFIELD_STYLES: [u32; 3] = [2, 4, 5];    // grass, gliding and solid airfield
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Airfield {
+    pub code: String,       // ICAO code or any other unique short name
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub elevation: i32,     // [m]
+}
+
+impl Airfield {
+    pub fn new(code: &str, name: &str, lat: f64, lon: f64, elevation: i32) -> Self {
+        Self { code: code.to_string(), name: name.to_string(), lat, lon, elevation }
+    }
+
+    /// Distance from the airfield reference point [m].
+    pub fn distance_to(&self, lat: f64, lon: f64) -> f64 {
+        haversine_distance(self.lat, self.lon, lat, lon)
+    }
+}
+
+/// CUP coordinate, e.g. 4431.233N or 00541.800E -> decimal degrees.
+fn parse_cup_coordinate(s: &str) -> Option<f64> {
+    let hemisphere = s.chars().last()?;
+    let value = s.strip_suffix(hemisphere)?;
+    let dot = value.find('.').unwrap_or(value.len());
+    let min_start = dot.checked_sub(2).filter(|i| *i > 0)?;     // at least one digit of degrees
+
+    let deg: f64 = value.get(..min_start)?.parse().ok()?;
+    let min: f64 = value.get(min_start..)?.parse().ok()?;
+    let coord = deg + min / 60.0;
+
+    match hemisphere {
+        'N' | 'E' => Some(coord),
+        'S' | 'W' => Some(-coord),
+        _ => None,
+    }
+}
+
+/// CUP elevation, e.g. 829.0m or 2720ft -> [m]
+fn parse_cup_elevation(s: &str) -> Option<i32> {
+    let s = s.to_lowercase();
+    let (value, factor) = match s.strip_suffix("ft") {
+        Some(value) => (value, FEET_TO_METERS),
+        None => (s.strip_suffix('m').unwrap_or(&s), 1.0),
+    };
+
+    value.trim().parse::<f64>().ok().map(|v| (v * factor).round() as i32)
+}
+
+/// Set of airfields with proximity lookups.
+#[derive(Debug, Clone)]
+pub struct AirfieldDatabase {
+    airfields: Vec<Airfield>,
+    index: SpatialIndex,    // by the position in airfields
+}
+
+impl AirfieldDatabase {
+    pub fn new(airfields: Vec<Airfield>) -> Self {
+        let mut db = Self { airfields: Vec::new(), index: SpatialIndex::new(0.5) };
+        for airfield in airfields {
+            db.add(airfield);
+        }
+
+        db
+    }
+
+    /// Loads a SeeYou CUP waypoint file; only the airfield waypoints (styles 2, 4 and 5) are taken - outlanding fields
+    /// (style 3) are not airfields a flight departs from.
+    pub fn load_cup<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_cup_str(&fs::read_to_string(path)?))
+    }
+
+    pub fn from_cup_str(content: &str) -> Self {
+        let mut db = Self::new(Vec::new());
+
+        // column order of the original CUP format; newer files come with a header naming the columns:
+        let (mut i_name, mut i_code, mut i_lat, mut i_lon, mut i_elev, mut i_style) = (0, 1, 3, 4, 5, 6);
+
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim_start_matches('\u{feff}').trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with(CUP_TASKS_SECTION) {
+                break;
+            }
+
+            let fields = split_csv_line(line, '"');
+            if line_no == 0 && fields[0].eq_ignore_ascii_case("name") {
+                let col = |name: &str, default: usize| fields.iter().position(|f| f.eq_ignore_ascii_case(name)).unwrap_or(default);
+                (i_name, i_code, i_lat, i_lon, i_elev, i_style) = (col("name", i_name), col("code", i_code), col("lat", i_lat), col("lon", i_lon), col("elev", i_elev), col("style", i_style));
+                continue;
+            }
+
+            let field = |i: usize| fields.get(i).map(|f| f.as_str()).unwrap_or("");
+            let style: u32 = field(i_style).parse().unwrap_or(0);
+            if !CUP_AIRFIELD_STYLES.contains(&style) {
+                continue;
+            }
+
+            match (parse_cup_coordinate(field(i_lat)), parse_cup_coordinate(field(i_lon))) {
+                (Some(lat), Some(lon)) => {
+                    let name = field(i_name);
+                    let code = if field(i_code).is_empty() { name } else { field(i_code) };
+                    let elevation = parse_cup_elevation(field(i_elev)).unwrap_or(0);
+                    db.add(Airfield::new(code, name, lat, lon, elevation));
+                },
+                _ => warn!("Invalid CUP waypoint '{}'", line),
+            }
+        }
+
+        db
+    }
+
+    /// Loads an OpenAIP airport export (JSON list of airports, possibly wrapped in {"items": [..]}).
+    pub fn load_openaip<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_openaip_str(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_openaip_str(content: &str) -> io::Result<Self> {
+        let js: Value = serde_json::from_str(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let items = match js.get("items").unwrap_or(&js).as_array() {
+            Some(items) => items,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "not a list of airports")),
+        };
+
+        let mut db = Self::new(Vec::new());
+        for item in items {
+            let name = item["name"].as_str().unwrap_or("");
+            let code = item["icaoCode"].as_str()
+                .or_else(|| item["_id"].as_str())
+                .unwrap_or(name);
+            let coordinates = &item["geometry"]["coordinates"];     // GeoJSON order: lon, lat
+
+            match (coordinates[1].as_f64(), coordinates[0].as_f64()) {
+                (Some(lat), Some(lon)) => {
+                    let elevation = &item["elevation"];
+                    let factor = if elevation["unit"].as_u64() == Some(1) { FEET_TO_METERS } else { 1.0 };
+                    let elevation = (elevation["value"].as_f64().unwrap_or(0.0) * factor).round() as i32;
+                    db.add(Airfield::new(code, name, lat, lon, elevation));
+                },
+                _ => warn!("OpenAIP airport without position '{}'", name),
+            }
+        }
+
+        Ok(db)
+    }
+
+    pub fn add(&mut self, airfield: Airfield) {
+        self.index.insert(&self.airfields.len().to_string(), airfield.lat, airfield.lon);
+        self.airfields.push(airfield);
+    }
+
+    pub fn len(&self) -> usize {
+        self.airfields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.airfields.is_empty()
+    }
+
+    pub fn get(&self, code: &str) -> Option<&Airfield> {
+        self.airfields.iter().find(|a| a.code == code)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Airfield> {
+        self.airfields.iter()
+    }
+
+    /// Airfields within the radius [m] with their distances, nearest first.
+    pub fn within_radius(&self, lat: f64, lon: f64, radius: f64) -> Vec<(&Airfield, f64)> {
+        self.index.within_radius(lat, lon, radius).into_iter()
+            .filter_map(|(idx, dist)| idx.parse::<usize>().ok().map(|idx| (&self.airfields[idx], dist)))
+            .collect()
+    }
+
+    /// The nearest airfield with its distance [m], if any is within max_distance [m].
+    pub fn nearest(&self, lat: f64, lon: f64, max_distance: f64) -> Option<(&Airfield, f64)> {
+        self.within_radius(lat, lon, max_distance).into_iter().next()
+    }
+
+    /// Fills in the nearest airfield (within max_distance [m]) and the distance to it.
+    pub fn annotate(&self, beacon: &mut AircraftBeacon, max_distance: f64) {
+        let nearest = self.nearest(beacon.lat, beacon.lon, max_distance);
+        beacon.nearest_airfield = nearest.map(|(a, _)| a.code.clone());
+        beacon.airfield_distance = nearest.map(|(_, dist)| dist);
+    }
+
+    /// Annotates the beacon the flight event was raised at, e.g. the take-off or landing location.
+    pub fn annotate_event(&self, event: &mut FlightEvent, max_distance: f64) {
+        match event {
+            FlightEvent::TakeOff { beacon, .. }
+            | FlightEvent::Landing { beacon, .. }
+            | FlightEvent::SignalLostInAir { beacon, .. } => self.annotate(beacon, max_distance),
+            FlightEvent::FlightCompleted { .. } => (),
+        }
+    }
+}
+
+impl Default for AirfieldDatabase {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+/// Pipeline stage annotating the passing beacons with the nearest airfield.
+pub struct AirfieldAnnotator {
+    airfields: AirfieldDatabase,
+    max_distance: f64,  // [m]
+    beacon_listener: Option<Rc<RefCell<dyn Observer<AircraftBeacon>>>>,
+}
+
+impl AirfieldAnnotator {
+    pub fn new(airfields: AirfieldDatabase) -> Self {
+        Self { airfields, max_distance: DEFAULT_ANNOTATION_RADIUS, beacon_listener: None }
+    }
+
+    /// Farther than this [m] from any airfield the beacon is left without one.
+    pub fn set_max_distance(&mut self, max_distance: f64) {
+        self.max_distance = max_distance;
+    }
+
+    pub fn set_beacon_listener(&mut self, listener: impl Observer<AircraftBeacon> + 'static) {
+        self.beacon_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    pub fn airfields(&self) -> &AirfieldDatabase {
+        &self.airfields
+    }
+}
+
+impl Observer<AircraftBeacon> for AirfieldAnnotator {
+    fn notify(&mut self, mut beacon: AircraftBeacon) {
+        self.airfields.annotate(&mut beacon, self.max_distance);
+
+        if let Some(listener) = self.beacon_listener.as_mut() {
+            listener.borrow_mut().notify(beacon);
+        }
+    }
+}
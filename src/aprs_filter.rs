@@ -0,0 +1,120 @@
+use log::warn;
+
+use crate::data_structures::AircraftBeacon;
+use crate::geodesy::haversine_distance;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterPart {
+    /// r/lat/lon/dist - within range [km] of the point
+    Range { lat: f64, lon: f64, range: f64 },
+    /// a/latN/lonW/latS/lonE - within the box
+    Area { lat_n: f64, lon_w: f64, lat_s: f64, lon_e: f64 },
+    /// p/aa/bb/cc - callsign starts with any of the prefixes
+    Prefix(Vec<String>),
+    /// b/call1/call2 - callsign equals any of the calls (* wildcard allowed at the end)
+    Budlist(Vec<String>),
+}
+
+impl FilterPart {
+    fn parse(part: &str) -> Option<FilterPart> {
+        let mut items = part.split('/');
+        let kind = items.next()?;
+        let args: Vec<&str> = items.collect();
+
+        let numbers = || -> Option<Vec<f64>> {
+            args.iter().map(|a| a.parse::<f64>().ok()).collect()
+        };
+
+        match kind {
+            "r" => match numbers()?.as_slice() {
+                [lat, lon, range] => Some(FilterPart::Range { lat: *lat, lon: *lon, range: *range }),
+                _ => None,
+            },
+            "a" => match numbers()?.as_slice() {
+                [lat_n, lon_w, lat_s, lon_e] => Some(FilterPart::Area { lat_n: *lat_n, lon_w: *lon_w, lat_s: *lat_s, lon_e: *lon_e }),
+                _ => None,
+            },
+            "p" => Some(FilterPart::Prefix(args.iter().map(|a| a.to_uppercase()).collect())),
+            "b" => Some(FilterPart::Budlist(args.iter().map(|a| a.to_uppercase()).collect())),
+            _ => None,
+        }
+    }
+
+    /// A station without a known position matches no r/ and a/ filter.
+    fn matches(&self, callsign: &str, position: Option<(f64, f64)>) -> bool {
+        match self {
+            FilterPart::Range { lat, lon, range } => match position {
+                Some((beacon_lat, beacon_lon)) => haversine_distance(*lat, *lon, beacon_lat, beacon_lon) <= range * 1000.0,
+                None => false,
+            },
+            FilterPart::Area { lat_n, lon_w, lat_s, lon_e } => {
+                let (beacon_lat, beacon_lon) = match position {
+                    Some(position) => position,
+                    None => return false,
+                };
+                let lat_ok = beacon_lat <= *lat_n && beacon_lat >= *lat_s;
+                let lon_ok = if lon_w <= lon_e {
+                    beacon_lon >= *lon_w && beacon_lon <= *lon_e
+                } else {    // across the antimeridian
+                    beacon_lon >= *lon_w || beacon_lon <= *lon_e
+                };
+                lat_ok && lon_ok
+            },
+            FilterPart::Prefix(prefixes) => {
+                prefixes.iter().any(|p| callsign.starts_with(p.as_str()))
+            },
+            FilterPart::Budlist(calls) => {
+                calls.iter().any(|c| match c.strip_suffix('*') {
+                    Some(prefix) => callsign.starts_with(prefix),
+                    None => callsign == *c,
+                })
+            },
+        }
+    }
+}
+
+/// Server-side APRS-IS filter (@see https://www.aprs-is.net/javAPRSFilter.aspx) evaluated locally against parsed beacons.
+/// Supported are the r/, a/, p/ and b/ filters including their '-' exclusions, e.g. "r/49.1/16.4/100 -p/SKY".
+/// A beacon passes when it matches any of the filters and none of the exclusions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AprsFilter {
+    includes: Vec<FilterPart>,
+    excludes: Vec<FilterPart>,
+}
+
+impl AprsFilter {
+    /// Parses the filter string; unsupported or malformed parts are ignored.
+    pub fn parse(filter: &str) -> AprsFilter {
+        let mut aprs_filter = AprsFilter::default();
+
+        for part in filter.split_whitespace() {
+            let (exclude, part_str) = match part.strip_prefix('-') {
+                Some(val) => (true, val),
+                None => (false, part),
+            };
+
+            match FilterPart::parse(part_str) {
+                Some(filter_part) if exclude => aprs_filter.excludes.push(filter_part),
+                Some(filter_part) => aprs_filter.includes.push(filter_part),
+                None => warn!("Unsupported filter part '{}'", part),
+            }
+        }
+
+        aprs_filter
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.includes.is_empty()
+    }
+
+    pub fn matches(&self, beacon: &AircraftBeacon) -> bool {
+        self.matches_station(&beacon.callsign(), Some((beacon.lat, beacon.lon)))
+    }
+
+    /// Matches any station (e.g. a receiver) by its callsign and position if known.
+    pub fn matches_station(&self, callsign: &str, position: Option<(f64, f64)>) -> bool {
+        let callsign = callsign.to_uppercase();
+        self.includes.iter().any(|f| f.matches(&callsign, position)) && !self.excludes.iter().any(|f| f.matches(&callsign, position))
+    }
+}
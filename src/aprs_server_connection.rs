@@ -12,6 +12,7 @@ use log::{info, error, warn};
 
 use crate::configuration::{DELAY_MS, DEFAULT_APRS_FILTER};
 use crate::data_structures::Observer;
+use crate::line_source::LineSource;
 
 
 pub struct AprsServerConnection {
@@ -82,6 +83,11 @@ impl AprsServerConnection {
         self.aprs_filter = format!("r/{:.4}/{:.4}/{}", lat, lon, range);
     }
 
+    /// Sets APRS filter in its full syntax, e.g. "r/49.1/16.4/100 p/FLR". Use before calling the connect().
+    pub fn set_aprs_filter_str(&mut self, filter: &str) {
+        self.aprs_filter = filter.to_string();
+    }
+
     pub fn write(&mut self, message: &str) -> Result<()> {
         self.writer.as_mut().unwrap().write(&message.as_bytes())?;
         self.writer.as_mut().unwrap().write(&['\n' as u8])?;  // This will also signal a `writer.flush()`
@@ -176,3 +182,17 @@ impl AprsServerConnection {
     //     self.line_listener_fn = Some(Box::new(handler));
     // }
 }
+
+impl LineSource for AprsServerConnection {
+    fn connect(&mut self) {
+        AprsServerConnection::connect(self);
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        self.read().filter(|line| !line.is_empty())
+    }
+
+    fn set_aprs_filter(&mut self, lat: f64, lon: f64, range: u32) {
+        AprsServerConnection::set_aprs_filter(self, lat, lon, range);
+    }
+}
@@ -0,0 +1,404 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::data_structures::{AircraftBeacon, Observer, DEG_PER_SEC_PER_ROT};
+use crate::geodesy::{destination_point, haversine_distance};
+use crate::spatial_index::SpatialIndex;
+use crate::thermals::CirclingDetector;
+
+
+const DEFAULT_HORIZONTAL_SEPARATION: f64 = 200.0;   // [m]
+const DEFAULT_VERTICAL_SEPARATION: i32 = 100;       // [m]
+const DEFAULT_LOOKAHEAD: i64 = 18;          // [s] FLARM warns up to 18 s before
+const URGENT_TIME: i64 = 8;                 // [s] to the conflict
+const IMPORTANT_TIME: i64 = 12;             // [s]
+const MAX_CLOSING_SPEED: f64 = 150.0;       // [m/s] only aircraft this fast approaching can get in conflict
+const STALE_TIME: i64 = 10;                 // [s] older positions are not projected
+const COMPANY_RELATIVE_SPEED: f64 = 5.0;    // [m/s] close aircraft moving together (tow, formation) are no conflict
+const MIN_AIRBORNE_SPEED: u32 = 20;         // [km/h]
+const MIN_AIRBORNE_AGL: i32 = 50;           // [m] where the agl is known
+const INDEX_CELL_SIZE: f64 = 0.05;          // [deg]
+const EXPIRE_INTERVAL: i64 = 10;            // [s]
+
+/// Alarm levels by the time left to the conflict, as FLARM's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AlertLevel {
+    /// 13-18 s
+    Low = 1,
+    /// 9-12 s
+    Important = 2,
+    /// 0-8 s
+    Urgent = 3,
+}
+
+impl AlertLevel {
+    fn from_time(time_to_conflict: i64) -> Self {
+        if time_to_conflict <= URGENT_TIME {
+            AlertLevel::Urgent
+        } else if time_to_conflict <= IMPORTANT_TIME {
+            AlertLevel::Important
+        } else {
+            AlertLevel::Low
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConflictAlert {
+    pub id1: String,
+    pub id2: String,
+    pub level: AlertLevel,
+    pub ts: i64,
+    pub time_to_conflict: i64,      // [s]
+    pub predicted_distance: f64,    // [m] horizontal, when the separation is first lost
+    pub predicted_height_diff: i32, // [m]
+    pub lat: f64,                   // predicted position of the first aircraft
+    pub lon: f64,
+    pub altitude: i32,              // [m] MSL
+}
+
+/// Conflict of two aircraft from the first alert until it cleared.
+#[derive(Debug, Clone)]
+pub struct Encounter {
+    pub id1: String,
+    pub id2: String,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub max_level: AlertLevel,
+    pub min_distance: f64,      // [m] closest horizontal distance seen
+    pub min_height_diff: i32,   // [m] smallest height difference seen
+    pub lat: f64,               // where the first alert was raised
+    pub lon: f64,
+}
+
+impl Encounter {
+    pub const CSV_HEADER: &'static str = "start_ts;end_ts;id1;id2;max_level;min_distance;min_height_diff;lat;lon";
+
+    pub fn to_csv_line(&self) -> String {
+        format!("{};{};{};{};{};{:.0};{};{:.5};{:.5}",
+            self.start_ts, self.end_ts, self.id1, self.id2, self.max_level as u8, self.min_distance, self.min_height_diff, self.lat, self.lon)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ConflictEvent {
+    /// New conflict or a higher alert level of an ongoing one.
+    Alert(ConflictAlert),
+    /// The predicted separation is no longer lost.
+    Cleared(Encounter),
+}
+
+/// Predicted position (lat, lon, altitude [m]).
+type Position = (f64, f64, f64);
+
+/// Watches the separation of the airborne aircraft: each update projects the aircraft and its neighbours ahead
+/// (constant speed, climb rate and turn rate) and raises an alert when the predicted horizontal and vertical
+/// separation both fall below the thresholds within the lookahead time. Finished encounters are kept for review.
+/// Circling aircraft without a reported turn rate are projected along the circles found by the CirclingDetector.
+pub struct ConflictDetector {
+    aircraft: HashMap<String, AircraftBeacon>,
+    circling: CirclingDetector,
+    index: SpatialIndex,
+    active: HashMap<(String, String), Encounter>,
+    current_levels: HashMap<(String, String), AlertLevel>,
+    encounters: Vec<Encounter>,
+    horizontal_separation: f64,     // [m]
+    vertical_separation: i32,       // [m]
+    lookahead: i64,                 // [s]
+    clock_ts: i64,
+    last_expire_ts: i64,
+    event_listener: Option<Rc<RefCell<dyn Observer<ConflictEvent>>>>,
+}
+
+impl ConflictDetector {
+    pub fn new() -> Self {
+        Self {
+            aircraft: HashMap::new(),
+            circling: CirclingDetector::new(),
+            index: SpatialIndex::new(INDEX_CELL_SIZE),
+            active: HashMap::new(),
+            current_levels: HashMap::new(),
+            encounters: Vec::new(),
+            horizontal_separation: DEFAULT_HORIZONTAL_SEPARATION,
+            vertical_separation: DEFAULT_VERTICAL_SEPARATION,
+            lookahead: DEFAULT_LOOKAHEAD,
+            clock_ts: 0,
+            last_expire_ts: 0,
+            event_listener: None,
+        }
+    }
+
+    /// Minimum horizontal [m] and vertical [m] separation; a conflict is when both are lost.
+    pub fn set_separation(&mut self, horizontal: f64, vertical: i32) {
+        self.horizontal_separation = horizontal;
+        self.vertical_separation = vertical;
+    }
+
+    /// How far ahead the aircraft are projected [s].
+    pub fn set_lookahead(&mut self, lookahead: u32) {
+        self.lookahead = lookahead as i64;
+    }
+
+    pub fn set_event_listener(&mut self, listener: impl Observer<ConflictEvent> + 'static) {
+        self.event_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    fn is_airborne(beacon: &AircraftBeacon) -> bool {
+        beacon.speed >= MIN_AIRBORNE_SPEED && beacon.agl.map(|agl| agl >= MIN_AIRBORNE_AGL).unwrap_or(true)
+    }
+
+    /// Positions of the aircraft each second from `start_ts` on for the lookahead. Without a course the aircraft
+    /// is kept where it was last seen.
+    fn trajectory(&self, beacon: &AircraftBeacon, start_ts: i64) -> Vec<Position> {
+        let speed = beacon.speed as f64 / 3.6;  // [m/s]
+        let climb_rate = beacon.climb_rate.unwrap_or(0.0);
+        let turn_rate = self.turn_rate(beacon);
+
+        let (mut lat, mut lon, mut altitude) = (beacon.lat, beacon.lon, beacon.altitude_msl as f64);
+        let mut course = beacon.course.map(|c| c as f64);
+        let mut trajectory = Vec::with_capacity(self.lookahead as usize + 1);
+        for ts in beacon.ts.min(start_ts)..=start_ts + self.lookahead {
+            if ts >= start_ts {
+                trajectory.push((lat, lon, altitude));
+            }
+            if ts < beacon.ts {
+                continue;   // heard after the start - not moved back
+            }
+            if let Some(c) = course {
+                (lat, lon) = destination_point(lat, lon, c, speed);
+                course = Some((c + turn_rate).rem_euclid(360.0));
+            }
+            altitude += climb_rate;
+        }
+
+        trajectory
+    }
+
+    /// Reported turn rate, or that of the circling when not reported [deg/s].
+    fn turn_rate(&self, beacon: &AircraftBeacon) -> f64 {
+        match beacon.turn_rate {
+            Some(rot) => rot * DEG_PER_SEC_PER_ROT,
+            None => self.circling.current(&beacon.callsign())
+                .filter(|s| s.duration() > 0)
+                .map(|s| s.total_turn / s.duration() as f64)
+                .unwrap_or(0.0),
+        }
+    }
+
+    fn velocity(beacon: &AircraftBeacon) -> (f64, f64) {
+        match beacon.course {
+            Some(course) => {
+                let (sin, cos) = (course as f64).to_radians().sin_cos();
+                let speed = beacon.speed as f64 / 3.6;
+                (speed * sin, speed * cos)
+            },
+            None => (0.0, 0.0),
+        }
+    }
+
+    pub fn ingest(&mut self, beacon: AircraftBeacon) {
+        if beacon.ts > self.clock_ts {
+            self.clock_ts = beacon.ts;
+        }
+
+        let id = beacon.callsign();
+        if self.aircraft.get(&id).map(|b| beacon.ts <= b.ts).unwrap_or(false) {
+            return;
+        }
+
+        self.circling.process(&beacon);
+
+        if !Self::is_airborne(&beacon) {
+            self.index.remove(&id);
+            self.aircraft.remove(&id);
+            return;
+        }
+
+        self.index.insert(&id, beacon.lat, beacon.lon);
+        self.aircraft.insert(id.clone(), beacon);
+        self.check(&id);
+
+        if self.clock_ts >= self.last_expire_ts + EXPIRE_INTERVAL {
+            self.expire(self.clock_ts);
+        }
+    }
+
+    /// Checks the aircraft against its neighbours.
+    fn check(&mut self, id: &str) {
+        let beacon = match self.aircraft.get(id) {
+            Some(beacon) => beacon.clone(),
+            None => return,
+        };
+        let now_ts = beacon.ts;
+        let own_trajectory = self.trajectory(&beacon, now_ts);
+        let search_radius = self.horizontal_separation + MAX_CLOSING_SPEED * self.lookahead as f64;
+
+        let mut events = Vec::new();
+        for (other_id, _) in self.index.within_radius(beacon.lat, beacon.lon, search_radius) {
+            if other_id == id {
+                continue;
+            }
+            let other = match self.aircraft.get(&other_id) {
+                Some(other) if other.ts >= now_ts - STALE_TIME => other,
+                _ => continue,
+            };
+
+            let other_trajectory = self.trajectory(other, now_ts);
+            let separation = |t: usize| {
+                let (a, b) = (own_trajectory[t], other_trajectory[t]);
+                (haversine_distance(a.0, a.1, b.0, b.1), (a.2 - b.2).abs().round() as i32)
+            };
+            let (distance, height_diff) = separation(0);
+
+            let (own_velocity, other_velocity) = (Self::velocity(&beacon), Self::velocity(other));
+            let relative_speed = (own_velocity.0 - other_velocity.0).hypot(own_velocity.1 - other_velocity.1);
+            let in_company = distance < self.horizontal_separation && relative_speed < COMPANY_RELATIVE_SPEED;
+
+            let conflict = if in_company {
+                None
+            } else {
+                (0..own_trajectory.len().min(other_trajectory.len()))
+                    .map(|t| (t, separation(t)))
+                    .find(|(_, (d, h))| *d < self.horizontal_separation && *h < self.vertical_separation)
+            };
+
+            let key = if id < other_id.as_str() { (id.to_string(), other_id.clone()) } else { (other_id.clone(), id.to_string()) };
+            match conflict {
+                Some((t, (predicted_distance, predicted_height_diff))) => {
+                    let level = AlertLevel::from_time(t as i64);
+                    let raised = self.current_levels.get(&key).map(|l| level > *l).unwrap_or(true);
+                    self.current_levels.insert(key.clone(), level);
+
+                    let encounter = self.active.entry(key.clone()).or_insert_with(|| Encounter {
+                        id1: key.0.clone(),
+                        id2: key.1.clone(),
+                        start_ts: now_ts,
+                        end_ts: now_ts,
+                        max_level: level,
+                        min_distance: distance,
+                        min_height_diff: height_diff,
+                        lat: beacon.lat,
+                        lon: beacon.lon,
+                    });
+                    encounter.end_ts = now_ts;
+                    encounter.max_level = encounter.max_level.max(level);
+                    encounter.min_distance = encounter.min_distance.min(distance);
+                    encounter.min_height_diff = encounter.min_height_diff.min(height_diff);
+
+                    if raised {
+                        let (lat, lon, altitude) = own_trajectory[t];
+                        events.push(ConflictEvent::Alert(ConflictAlert {
+                            id1: id.to_string(),
+                            id2: other_id.clone(),
+                            level,
+                            ts: now_ts,
+                            time_to_conflict: t as i64,
+                            predicted_distance,
+                            predicted_height_diff,
+                            lat,
+                            lon,
+                            altitude: altitude.round() as i32,
+                        }));
+                    }
+                },
+                None => {
+                    if let Some(mut encounter) = self.active.remove(&key) {
+                        self.current_levels.remove(&key);
+                        encounter.end_ts = now_ts;
+                        encounter.min_distance = encounter.min_distance.min(distance);
+                        encounter.min_height_diff = encounter.min_height_diff.min(height_diff);
+                        self.encounters.push(encounter.clone());
+                        events.push(ConflictEvent::Cleared(encounter));
+                    }
+                },
+            }
+        }
+
+        for event in events {
+            self.notify_event_listener(event);
+        }
+    }
+
+    /// Forgets the aircraft not heard recently and clears their conflicts.
+    pub fn expire(&mut self, now_ts: i64) {
+        self.last_expire_ts = now_ts;
+
+        let stale_ids: Vec<String> = self.aircraft.iter()
+            .filter(|(_, b)| b.ts < now_ts - STALE_TIME)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &stale_ids {
+            self.index.remove(id);
+            self.aircraft.remove(id);
+        }
+        self.circling.expire(now_ts);
+
+        let ended: Vec<(String, String)> = self.active.keys()
+            .filter(|(id1, id2)| !self.aircraft.contains_key(id1) || !self.aircraft.contains_key(id2))
+            .cloned()
+            .collect();
+        for key in ended {
+            self.current_levels.remove(&key);
+            if let Some(encounter) = self.active.remove(&key) {
+                self.encounters.push(encounter.clone());
+                self.notify_event_listener(ConflictEvent::Cleared(encounter));
+            }
+        }
+    }
+
+    /// Current alert level of the two aircraft, if in conflict.
+    pub fn alert_level(&self, id1: &str, id2: &str) -> Option<AlertLevel> {
+        let key = if id1 < id2 { (id1.to_string(), id2.to_string()) } else { (id2.to_string(), id1.to_string()) };
+
+        self.current_levels.get(&key).copied()
+    }
+
+    /// Ongoing conflicts.
+    pub fn active_conflicts(&self) -> Vec<&Encounter> {
+        self.active.values().collect()
+    }
+
+    /// Finished encounters, oldest first.
+    pub fn encounters(&self) -> &[Encounter] {
+        &self.encounters
+    }
+
+    pub fn clear_encounters(&mut self) {
+        self.encounters.clear();
+    }
+
+    pub fn encounters_to_csv(&self) -> String {
+        let mut csv = String::from(Encounter::CSV_HEADER);
+        csv.push('\n');
+        for encounter in &self.encounters {
+            csv.push_str(&encounter.to_csv_line());
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    /// Time of the most recent beacon seen [s].
+    pub fn clock_ts(&self) -> i64 {
+        self.clock_ts
+    }
+
+    fn notify_event_listener(&mut self, event: ConflictEvent) {
+        if let Some(listener) = self.event_listener.as_mut() {
+            listener.borrow_mut().notify(event);
+        }
+    }
+}
+
+impl Default for ConflictDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for ConflictDetector {
+    fn notify(&mut self, beacon: AircraftBeacon) {
+        self.ingest(beacon);
+    }
+}
@@ -1,12 +1,20 @@
 #![allow(non_snake_case)]
 
+use std::cell::RefCell;
 use std::fmt;
+use std::rc::Rc;
 
 use regex::RegexBuilder;
 // use serde::{Serialize, Deserialize};
 // use serde_json;
 use serde_json::json;
 
+use crate::geodesy::{haversine_distance, initial_bearing};
+use crate::validation::ValidationIssue;
+
+
+pub const DEG_PER_SEC_PER_ROT: f64 = 3.0;   // turn rate unit used in the beacons: 1rot = half turn per minute
+pub const MIN_TRACK_DISTANCE: f64 = 10.0;   // [m] shorter moves between two fixes are GPS noise - no track
 
 // #[derive(Serialize, Deserialize)]
 #[derive(Debug, Clone)]
@@ -17,27 +25,40 @@ pub struct AircraftBeacon {
     pub addr_type: AddressType,
     pub lat: f64,
     pub lon: f64,
-    pub altitude: i32,
-    pub agl: i32,
-    pub course:u64,
+    pub altitude: i32,      // [m] as reported
+    pub altitude_msl: i32,  // [m] above mean sea level (the reported altitude unless corrected by the GeoidCorrection)
+    pub agl: Option<i32>,       // [m] None when not known
+    pub course: Option<u64>,    // [deg] None when not reported
     pub speed:u32,
-    pub climb_rate: f64,
-    pub turn_rate: f64,
+    pub climb_rate: Option<f64>,    // [m/s] None when not reported
+    pub turn_rate: Option<f64>,     // [rot] None when not reported
+    pub acceleration: Option<f64>,  // [m/s^2] along the track (derived by the KinematicsEstimator), None when not known
+    pub derived: DerivedFields, // which of the kinematic values were computed from the fixes instead of received
+    pub rejected: bool,         // implausible fix (flagged by the TrackFilter)
+    pub validation_issues: Vec<ValidationIssue>,    // (filled by the BeaconValidator)
     pub stealth: bool,
     pub do_not_track: bool, 
+    pub do_not_identify: bool,  // the owner has opted out of being identified (from the device database)
     pub aircraft_type: AircraftType,
     pub registration: String,   // OGNEMO beacons carry the aircraft registration
-    pub signal_strength: f64,   // [dB]
+    pub competition_number: String, // from the device database (filled by the DeviceEnricher)
+    pub aircraft_model: String,     // from the device database
+    pub signal_strength: Option<f64>,   // [dB]
+    pub receiver: String,       // receiving station
+    pub receptions: Vec<Reception>, // all stations which heard the same transmission (filled by the BeaconDeduplicator)
+    pub nearest_airfield: Option<String>,   // code of the closest airfield (filled by the AirfieldAnnotator)
+    pub airfield_distance: Option<f64>,     // [m] to the closest airfield
 }
 
 impl AircraftBeacon {
     pub fn new( ts: i64, prefix: String, addr: String, addr_type: AddressType,
-        lat: f64, lon: f64, altitude: i32, agl: i32,
-        course:u64, speed:u32, climb_rate: f64, turn_rate: f64, 
+        lat: f64, lon: f64, altitude: i32, agl: Option<i32>,
+        course: Option<u64>, speed:u32, climb_rate: Option<f64>, turn_rate: Option<f64>, 
         stealth: bool, do_not_track: bool, aircraft_type: AircraftType,
-        registration: String, signal_strength: f64) -> Self {
+        registration: String, signal_strength: Option<f64>) -> Self {
 
-        Self {ts, prefix, addr, addr_type, lat, lon, altitude, agl, course, speed, climb_rate, turn_rate, stealth, do_not_track, aircraft_type, registration, signal_strength}
+        Self {ts, prefix, addr, addr_type, lat, lon, altitude, altitude_msl: altitude, agl, course, speed, climb_rate, turn_rate, acceleration: None, derived: DerivedFields::default(), rejected: false, validation_issues: Vec::new(), stealth, do_not_track, do_not_identify: false, aircraft_type, registration, competition_number: String::new(), aircraft_model: String::new(), signal_strength,
+            receiver: String::new(), receptions: Vec::new(), nearest_airfield: None, airfield_distance: None}
     }
 
     pub fn to_json_str(&self) -> String {
@@ -51,32 +72,102 @@ impl AircraftBeacon {
             "lat": format!("{:.5}", self.lat),
             "lon": format!("{:.5}", self.lon),
             "alt": self.altitude,
+            "alt_msl": self.altitude_msl,
             "agl": self.agl,
             "course":  self.course,
             "speed": self.speed,
-            "vert_speed": format!("{:.1}", self.climb_rate),
-            "turn_rate": format!("{:.1}", self.turn_rate),
+            "vert_speed": self.climb_rate.map(|v| format!("{:.1}", v)),
+            "turn_rate": self.turn_rate.map(|v| format!("{:.1}", v)),
+            "accel": self.acceleration.map(|v| format!("{:.1}", v)),
+            "derived": self.derived.names(),
+            "rejected": self.rejected,
+            "issues": self.validation_issues.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
             "stealth": self.stealth,
             "dnt": self.do_not_track,
             "acft_type": self.aircraft_type.value(),
             "registration": self.registration,
+            "cn": self.competition_number,
+            "model": self.aircraft_model,
+            "receiver": self.receiver,
+            "receptions": self.receptions.iter()
+                .map(|r| json!({"receiver": r.receiver, "signal": r.signal_strength.map(|s| format!("{:.1}", s))}))
+                .collect::<Vec<_>>(),
+            "airfield": self.nearest_airfield,
+            "airfield_dist": self.airfield_distance.map(|d| d.round() as i64),
         });
         
         js.to_string()
     }
 
     pub fn set_agl(&mut self, agl: i32) {
-        self.agl = agl;
+        self.agl = Some(agl);
+    }
+
+    /// Distance to the position of the other beacon [m].
+    pub fn distance_to(&self, other: &AircraftBeacon) -> f64 {
+        haversine_distance(self.lat, self.lon, other.lat, other.lon)
+    }
+
+    /// Distance to the point, e.g. a receiver [m].
+    pub fn distance_to_point(&self, lat: f64, lon: f64) -> f64 {
+        haversine_distance(self.lat, self.lon, lat, lon)
+    }
+
+    /// Initial bearing towards the other beacon [deg].
+    pub fn bearing_to(&self, other: &AircraftBeacon) -> f64 {
+        initial_bearing(self.lat, self.lon, other.lat, other.lon)
+    }
+
+    /// Initial bearing towards the point [deg].
+    pub fn bearing_to_point(&self, lat: f64, lon: f64) -> f64 {
+        initial_bearing(self.lat, self.lon, lat, lon)
+    }
+
+    /// Height above the other beacon [m] (negative when below).
+    pub fn height_above(&self, other: &AircraftBeacon) -> i32 {
+        self.altitude_msl - other.altitude_msl
+    }
+
+    /// APRS callsign the beacon was sent under, e.g. FLRDDA5BA.
+    pub fn callsign(&self) -> String {
+        format!("{}{}", self.prefix, self.addr)
     }
 }
 
+/// Kinematic values of a beacon computed from the successive fixes (missing or implausible in the received one).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DerivedFields {
+    pub course: bool,
+    pub speed: bool,
+    pub climb_rate: bool,
+    pub turn_rate: bool,
+    pub acceleration: bool,
+}
+
+impl DerivedFields {
+    pub fn names(&self) -> Vec<&'static str> {
+        [(self.course, "course"), (self.speed, "speed"), (self.climb_rate, "vert_speed"), (self.turn_rate, "turn_rate"), (self.acceleration, "accel")]
+            .iter()
+            .filter(|(derived, _)| *derived)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+/// One station's reception of a beacon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reception {
+    pub receiver: String,
+    pub signal_strength: Option<f64>,   // [dB]
+}
+
 impl fmt::Display for AircraftBeacon {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "#AircraftBeacon: {} | {} {} | lat:{:.4}; lon:{:.4}; alt:{:.1}m | gs:{:.1} km/h", self.ts, self.prefix, self.addr, self.lat, self.lon, self.altitude, self.speed)
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AddressType {
     Unknown,
     Icao,
@@ -235,6 +326,15 @@ pub trait Observer<E: Clone> {
     fn notify(&mut self, event: E);
 }
 
+/// Shared observers can be set as listeners while the owner keeps querying them, e.g.:
+///     let tracker = Rc::new(RefCell::new(AircraftTracker::new()));
+///     client.set_beacon_listener(Rc::clone(&tracker));
+impl<E: Clone, T: Observer<E>> Observer<E> for Rc<RefCell<T>> {
+    fn notify(&mut self, event: E) {
+        self.borrow_mut().notify(event);
+    }
+}
+
 pub trait LineListener {
     fn notify(&mut self, line: &str);
 }
@@ -0,0 +1,148 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::data_structures::{AircraftBeacon, Observer, Reception};
+use crate::geodesy::haversine_distance;
+
+
+const DEFAULT_WINDOW: i64 = 2;              // [s]
+const DEFAULT_MAX_DISTANCE: f64 = 100.0;    // [m]
+
+/// Merges the copies of one transmission heard by several receivers into a single beacon.
+///
+/// Beacons of the same aircraft with the same timestamp and position (within max_distance) are held for the window
+/// and then emitted once, carrying the list of all receptions sorted by signal strength. The receiver and
+/// signal_strength of the emitted beacon are those of the strongest reception.
+/// The window runs on the beacon timestamps so that replayed data is handled the same way as live data. Emitted
+/// transmissions are remembered for another window and copies of them arriving late are dropped.
+pub struct BeaconDeduplicator {
+    window: i64,            // [s]
+    max_distance: f64,      // [m]
+    pending: HashMap<TransmissionKey, Vec<(u64, AircraftBeacon)>>,  // with the order of arrival
+    emitted: HashMap<TransmissionKey, Vec<(f64, f64)>>,             // positions (lat, lon) of the emitted ones
+    arrivals: u64,
+    clock_ts: i64,
+    last_flush_ts: i64,
+    beacon_listener: Option<Rc<RefCell<dyn Observer<AircraftBeacon>>>>,
+}
+
+/// (prefix, addr, ts) - the copies of a transmission share it; aircraft sharing an address are told apart by position.
+type TransmissionKey = (String, String, i64);
+
+fn transmission_key(beacon: &AircraftBeacon) -> TransmissionKey {
+    (beacon.prefix.clone(), beacon.addr.clone(), beacon.ts)
+}
+
+impl BeaconDeduplicator {
+    pub fn new() -> Self {
+        Self {
+            window: DEFAULT_WINDOW,
+            max_distance: DEFAULT_MAX_DISTANCE,
+            pending: HashMap::new(),
+            emitted: HashMap::new(),
+            arrivals: 0,
+            clock_ts: 0,
+            last_flush_ts: i64::MIN,
+            beacon_listener: None,
+        }
+    }
+
+    /// How long to wait for more receptions of a transmission [s].
+    pub fn set_window(&mut self, window: i64) {
+        self.window = window;
+    }
+
+    /// Maximum distance of two copies of the same transmission [m]. Farther apart are different aircraft sharing an address.
+    pub fn set_max_distance(&mut self, max_distance: f64) {
+        self.max_distance = max_distance;
+    }
+
+    pub fn set_beacon_listener(&mut self, listener: impl Observer<AircraftBeacon> + 'static) {
+        self.beacon_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    pub fn ingest(&mut self, mut beacon: AircraftBeacon) {
+        if beacon.ts > self.clock_ts {
+            self.clock_ts = beacon.ts;
+        }
+
+        let key = transmission_key(&beacon);
+        let ts = beacon.ts;
+        let max_distance = self.max_distance;
+        let is_near = |lat: f64, lon: f64| haversine_distance(lat, lon, beacon.lat, beacon.lon) <= max_distance;
+
+        if self.emitted.get(&key).map(|positions| positions.iter().any(|(lat, lon)| is_near(*lat, *lon))).unwrap_or(false) {
+            return;     // a late copy
+        }
+
+        let reception = Reception { receiver: beacon.receiver.clone(), signal_strength: beacon.signal_strength };
+        let copies = self.pending.entry(key).or_default();
+        match copies.iter_mut().find(|(_, p)| is_near(p.lat, p.lon)) {
+            Some((_, pending)) => {
+                if !pending.receptions.iter().any(|r| r.receiver == reception.receiver) {
+                    pending.receptions.push(reception);
+                }
+            },
+            None => {
+                beacon.receptions = vec![reception];
+                copies.push((self.arrivals, beacon));
+                self.arrivals += 1;
+            },
+        }
+
+        // the window has moved or the transmission came already too late for it:
+        let cutoff = self.clock_ts - self.window;
+        if cutoff > self.last_flush_ts || ts < cutoff {
+            self.flush_older_than(cutoff);
+        }
+    }
+
+    /// Emits all beacons still waiting for more receptions, e.g. at the end of a replay.
+    pub fn flush(&mut self) {
+        self.flush_older_than(i64::MAX);
+    }
+
+    fn flush_older_than(&mut self, ts: i64) {
+        self.last_flush_ts = ts;
+        let window = self.window;
+        self.emitted.retain(|(_, _, emitted_ts), _| *emitted_ts >= ts.saturating_sub(window));
+
+        let ready_keys: Vec<TransmissionKey> = self.pending.keys().filter(|(_, _, key_ts)| *key_ts < ts).cloned().collect();
+        let mut ready = Vec::new();
+        for key in ready_keys {
+            if let Some(copies) = self.pending.remove(&key) {
+                self.emitted.entry(key).or_default().extend(copies.iter().map(|(_, b)| (b.lat, b.lon)));
+                ready.extend(copies);
+            }
+        }
+        ready.sort_by_key(|(arrival, _)| *arrival);
+
+        for (_, mut beacon) in ready {
+            // strongest first, receptions without the signal strength last:
+            beacon.receptions.sort_by(|a, b| {
+                b.signal_strength.unwrap_or(f64::NEG_INFINITY).total_cmp(&a.signal_strength.unwrap_or(f64::NEG_INFINITY))
+            });
+            if let Some(strongest) = beacon.receptions.first() {
+                beacon.receiver = strongest.receiver.clone();
+                beacon.signal_strength = strongest.signal_strength;
+            }
+
+            if let Some(listener) = self.beacon_listener.as_mut() {
+                listener.borrow_mut().notify(beacon);
+            }
+        }
+    }
+}
+
+impl Default for BeaconDeduplicator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for BeaconDeduplicator {
+    fn notify(&mut self, beacon: AircraftBeacon) {
+        self.ingest(beacon);
+    }
+}
@@ -0,0 +1,247 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{info, warn};
+use serde_json::Value;
+
+use crate::data_structures::{AddressType, AircraftBeacon, Observer};
+use crate::utils::split_csv_line;
+
+
+/// One device registered in the OGN Device Database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    pub device_type: AddressType,
+    pub device_id: String,          // hex address, upper case
+    pub aircraft_model: String,
+    pub registration: String,
+    pub competition_number: String,
+    pub tracked: bool,              // the owner agrees with the aircraft being tracked
+    pub identified: bool,           // the owner agrees with the aircraft being identified (registration & cn shown)
+}
+
+fn is_yes(value: &str) -> bool {
+    value.eq_ignore_ascii_case("Y")
+}
+
+/// Devices from the OGN DDB export, either the JSON (https://ddb.glidernet.org/download/?j=1) or the CSV one.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceDatabase {
+    devices: HashMap<(AddressType, String), DeviceInfo>,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,   // of the file when loaded
+}
+
+impl DeviceDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut db = Self::new();
+        db.path = Some(path.as_ref().to_path_buf());
+        db.reload()?;
+
+        Ok(db)
+    }
+
+    /// Re-reads the file the database was loaded from; on failure the current content is kept.
+    pub fn reload(&mut self) -> io::Result<()> {
+        let path = match self.path.as_ref() {
+            Some(path) => path.clone(),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "not loaded from a file")),
+        };
+
+        let modified = fs::metadata(&path)?.modified().ok();
+        let content = fs::read_to_string(&path)?;
+        let db = if content.trim_start().starts_with('{') { Self::from_json_str(&content)? } else { Self::from_csv_str(&content) };
+
+        self.devices = db.devices;
+        self.modified = modified;
+        info!("Loaded {} devices from '{}'", self.devices.len(), path.display());
+
+        Ok(())
+    }
+
+    /// Reloads the file if it has been modified since loaded. Returns true when reloaded.
+    pub fn reload_if_modified(&mut self) -> io::Result<bool> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+
+        let modified = fs::metadata(path)?.modified().ok();
+        if modified.is_some() && modified == self.modified {
+            return Ok(false);
+        }
+        self.reload()?;
+
+        Ok(true)
+    }
+
+    pub fn from_json_str(content: &str) -> io::Result<Self> {
+        let js: Value = serde_json::from_str(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let devices = match js["devices"].as_array() {
+            Some(devices) => devices,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "no devices")),
+        };
+
+        let mut db = Self::new();
+        for device in devices {
+            let field = |name: &str| device[name].as_str().unwrap_or("").trim().to_string();
+            db.add(DeviceInfo {
+                device_type: AddressType::from_short_str(field("device_type")),
+                device_id: field("device_id").to_uppercase(),
+                aircraft_model: field("aircraft_model"),
+                registration: field("registration"),
+                competition_number: field("cn"),
+                tracked: is_yes(&field("tracked")),
+                identified: is_yes(&field("identified")),
+            });
+        }
+
+        Ok(db)
+    }
+
+    /// CSV export: #DEVICE_TYPE,DEVICE_ID,AIRCRAFT_MODEL,REGISTRATION,CN,TRACKED,IDENTIFIED with values in single quotes.
+    pub fn from_csv_str(content: &str) -> Self {
+        let mut db = Self::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = split_csv_line(line, '\'');
+            if fields.len() < 7 {
+                warn!("Invalid DDB line '{}'", line);
+                continue;
+            }
+
+            db.add(DeviceInfo {
+                device_type: AddressType::from_short_str(fields[0].clone()),
+                device_id: fields[1].to_uppercase(),
+                aircraft_model: fields[2].clone(),
+                registration: fields[3].clone(),
+                competition_number: fields[4].clone(),
+                tracked: is_yes(&fields[5]),
+                identified: is_yes(&fields[6]),
+            });
+        }
+
+        db
+    }
+
+    pub fn add(&mut self, device: DeviceInfo) {
+        self.devices.insert((device.device_type.clone(), device.device_id.clone()), device);
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    pub fn get(&self, device_type: &AddressType, device_id: &str) -> Option<&DeviceInfo> {
+        self.devices.get(&(device_type.clone(), device_id.to_uppercase()))
+    }
+
+    /// Device of the beacon; beacons of unknown address type are matched by the id under any type.
+    pub fn lookup(&self, beacon: &AircraftBeacon) -> Option<&DeviceInfo> {
+        if beacon.addr_type != AddressType::Unknown {
+            return self.get(&beacon.addr_type, &beacon.addr);
+        }
+
+        [AddressType::Flarm, AddressType::Ogn, AddressType::Icao].iter()
+            .find_map(|device_type| self.get(device_type, &beacon.addr))
+    }
+}
+
+/// Pipeline stage filling in the registration, competition number and aircraft model from the device database.
+/// Devices registered as not to be identified are left anonymous and marked do_not_identify, those not to be tracked
+/// are marked do_not_track (see the PrivacyFilter).
+pub struct DeviceEnricher {
+    database: DeviceDatabase,
+    reload_interval: Option<Duration>,
+    last_reload_check: Instant,
+    beacon_listener: Option<Rc<RefCell<dyn Observer<AircraftBeacon>>>>,
+}
+
+impl DeviceEnricher {
+    pub fn new(database: DeviceDatabase) -> Self {
+        Self { database, reload_interval: None, last_reload_check: Instant::now(), beacon_listener: None }
+    }
+
+    /// How often to check whether the database file has been modified and reload it. None (default) = never.
+    pub fn set_reload_interval(&mut self, reload_interval: Option<Duration>) {
+        self.reload_interval = reload_interval;
+    }
+
+    pub fn set_beacon_listener(&mut self, listener: impl Observer<AircraftBeacon> + 'static) {
+        self.beacon_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    pub fn database(&self) -> &DeviceDatabase {
+        &self.database
+    }
+
+    /// E.g. to add devices or reload() the database on demand.
+    pub fn database_mut(&mut self) -> &mut DeviceDatabase {
+        &mut self.database
+    }
+
+    pub fn enrich(&self, beacon: &mut AircraftBeacon) {
+        let device = match self.database.lookup(beacon) {
+            Some(device) => device,
+            None => return,
+        };
+
+        if !device.tracked {
+            beacon.do_not_track = true;
+        }
+        if !device.identified {
+            beacon.do_not_identify = true;
+            return;
+        }
+
+        if beacon.registration.is_empty() {
+            beacon.registration = device.registration.clone();
+        }
+        beacon.competition_number = device.competition_number.clone();
+        beacon.aircraft_model = device.aircraft_model.clone();
+    }
+
+    fn check_reload(&mut self) {
+        let reload_interval = match self.reload_interval {
+            Some(reload_interval) => reload_interval,
+            None => return,
+        };
+        if self.last_reload_check.elapsed() < reload_interval {
+            return;
+        }
+        self.last_reload_check = Instant::now();
+
+        if let Err(e) = self.database.reload_if_modified() {
+            warn!("Cannot reload the device database: {}", e);
+        }
+    }
+}
+
+impl Observer<AircraftBeacon> for DeviceEnricher {
+    fn notify(&mut self, mut beacon: AircraftBeacon) {
+        self.check_reload();
+        self.enrich(&mut beacon);
+
+        if let Some(listener) = self.beacon_listener.as_mut() {
+            listener.borrow_mut().notify(beacon);
+        }
+    }
+}
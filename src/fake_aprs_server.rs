@@ -0,0 +1,244 @@
+use std::io::{BufRead, BufReader, ErrorKind, Result, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+
+
+const POLL_INTERVAL_MS: u64 = 20;
+
+#[derive(Default)]
+struct ServerState {
+    script: Vec<String>,            // lines served to every connection
+    handshakes: Vec<String>,
+    filters: Vec<String>,           // '#filter ...' commands
+    received: Vec<String>,          // everything else the clients sent
+    heartbeat_interval: Option<Duration>,
+    line_delay: Duration,           // throttled output
+    read_delay: Duration,           // slow reader of what the clients send
+    half_open: bool,
+    drop_generation: usize,
+}
+
+/// In-process APRS-IS server for integration tests of the client side:
+///     let server = FakeAprsServer::start()?;
+///     let mut connection = AprsServerConnection::new(&server.address(), "tester")?;
+///     server.serve_line("FLRDDA5BA>APRS,qAS,LKKA:/074548h4821.61N/01708.55E'086/007/A=000617");
+pub struct FakeAprsServer {
+    address: SocketAddr,
+    state: Arc<Mutex<ServerState>>,
+    running: Arc<AtomicBool>,
+    connections: Arc<AtomicUsize>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl FakeAprsServer {
+    /// Starts listening on a random free port of the localhost.
+    pub fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+
+        let address = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(ServerState::default()));
+        let running = Arc::new(AtomicBool::new(true));
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accept_thread = {
+            let state = Arc::clone(&state);
+            let running = Arc::clone(&running);
+            let connections = Arc::clone(&connections);
+
+            thread::spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            connections.fetch_add(1, Ordering::SeqCst);
+                            let state = Arc::clone(&state);
+                            let running = Arc::clone(&running);
+                            thread::spawn(move || {
+                                let _ = serve_connection(stream, state, running);
+                            });
+                        },
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(POLL_INTERVAL_MS)),
+                        Err(_) => break,
+                    }
+                }
+            })
+        };
+
+        Ok(Self { address, state, running, connections, accept_thread: Some(accept_thread) })
+    }
+
+    /// "host:port" to be passed to the AprsServerConnection.
+    pub fn address(&self) -> String {
+        self.address.to_string()
+    }
+
+    /// Number of connections accepted so far.
+    pub fn num_connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Waits until at least `count` connections have been accepted. Returns false on timeout.
+    pub fn wait_for_connections(&self, count: usize, timeout: Duration) -> bool {
+        let start = Instant::now();
+        while self.num_connections() < count {
+            if start.elapsed() > timeout {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        }
+
+        true
+    }
+
+    /// Appends a line to the script served to all (current and future) connections.
+    pub fn serve_line(&self, line: &str) {
+        self.state.lock().unwrap().script.push(line.to_string());
+    }
+
+    pub fn serve_lines(&self, lines: &[&str]) {
+        let mut state = self.state.lock().unwrap();
+        state.script.extend(lines.iter().map(|line| line.to_string()));
+    }
+
+    /// Login lines ('user .. pass .. vers .. filter ..') received so far.
+    pub fn handshakes(&self) -> Vec<String> {
+        self.state.lock().unwrap().handshakes.clone()
+    }
+
+    /// '#filter ...' commands received so far.
+    pub fn filters(&self) -> Vec<String> {
+        self.state.lock().unwrap().filters.clone()
+    }
+
+    /// All other lines received from the clients (e.g. keepalives).
+    pub fn received_lines(&self) -> Vec<String> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Emits '# aprsc ...' heartbeat comments in the given interval. None (default) = no heartbeats.
+    pub fn set_heartbeat_interval(&self, interval: Option<Duration>) {
+        self.state.lock().unwrap().heartbeat_interval = interval;
+    }
+
+    /// Delay between two served lines - simulates a slow link.
+    pub fn set_line_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().line_delay = delay;
+    }
+
+    /// Delay before each read of what the clients send - simulates a slow reader on the server side.
+    pub fn set_read_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().read_delay = delay;
+    }
+
+    /// Half-open sockets: the connections stay open but nothing is sent nor read anymore.
+    pub fn set_half_open(&self, half_open: bool) {
+        self.state.lock().unwrap().half_open = half_open;
+    }
+
+    /// Closes all currently open connections. New connections are accepted as usual.
+    pub fn drop_connections(&self) {
+        self.state.lock().unwrap().drop_generation += 1;
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.accept_thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for FakeAprsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn heartbeat() -> String {
+    format!("# aprsc 2.1.14-g5e22b37 {} GLIDERN1 127.0.0.1:14580", Utc::now().format("%d %b %Y %H:%M:%S GMT"))
+}
+
+fn serve_connection(stream: TcpStream, state: Arc<Mutex<ServerState>>, running: Arc<AtomicBool>) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_millis(POLL_INTERVAL_MS)))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    writeln!(writer, "# aprsc 2.1.14-g5e22b37")?;
+
+    let drop_generation = state.lock().unwrap().drop_generation;
+    let mut logged_in = false;
+    let mut next_line = 0;
+    let mut last_heartbeat = Instant::now();
+    let mut last_line = Instant::now();
+    let mut buf = String::new();
+
+    while running.load(Ordering::SeqCst) {
+        let (half_open, read_delay, line_delay, heartbeat_interval, dropped) = {
+            let state = state.lock().unwrap();
+            (state.half_open, state.read_delay, state.line_delay, state.heartbeat_interval, state.drop_generation != drop_generation)
+        };
+
+        if dropped {
+            break;
+        }
+
+        if half_open {
+            thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+            continue;
+        }
+
+        // read what the client sends:
+        if !read_delay.is_zero() {
+            thread::sleep(read_delay);
+        }
+        match reader.read_line(&mut buf) {
+            Ok(0) => break, // EOF
+            Ok(_) => {
+                if buf.ends_with('\n') {
+                    let line = buf.trim().to_string();
+                    buf.clear();
+
+                    let mut state = state.lock().unwrap();
+                    if line.starts_with("user ") {
+                        writeln!(writer, "# logresp {} unverified, server GLIDERN1", line.split_whitespace().nth(1).unwrap_or(""))?;
+                        state.handshakes.push(line);
+                        logged_in = true;
+                    } else if line.starts_with("#filter") {
+                        state.filters.push(line);
+                    } else {
+                        state.received.push(line);
+                    }
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => (),
+            Err(_) => break,
+        }
+
+        // serve the script once the client has logged in:
+        while logged_in && last_line.elapsed() >= line_delay {
+            let line = state.lock().unwrap().script.get(next_line).cloned();
+            match line {
+                Some(line) => {
+                    writeln!(writer, "{}", line)?;
+                    next_line += 1;
+                    last_line = Instant::now();
+                },
+                None => break,
+            }
+        }
+
+        if let Some(interval) = heartbeat_interval {
+            if last_heartbeat.elapsed() >= interval {
+                writeln!(writer, "{}", heartbeat())?;
+                last_heartbeat = Instant::now();
+            }
+        }
+    }
+
+    stream.shutdown(Shutdown::Both)
+}
@@ -0,0 +1,179 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Result, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{error, info};
+
+use crate::data_structures::Observer;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rotation {
+    /// A new file whenever the date (and hence the path given by the layout) changes. A restarted recorder does not
+    /// append to the day's file but continues in a new part (ogn-2023-06-17.1.log.gz, .2.log.gz ..), so a day with
+    /// restarts is split into several files; the ReplaySource replays them in order.
+    Daily,
+    /// As daily, plus a new part once the uncompressed size of the current part exceeds the limit [B].
+    Size(u64),
+}
+
+/// Records raw APRS lines prefixed with their arrival time into rotating gzip archives.
+/// The output can be read back by the ReplaySource.
+///
+/// Set it as line listener of the AprsServerConnection or of the OgnClient:
+///     let recorder = Rc::new(RefCell::new(FeedRecorder::new("/data/ogn")));
+///     client.set_raw_line_listener(Rc::clone(&recorder));
+pub struct FeedRecorder {
+    dir: PathBuf,
+    layout: String,
+    rotation: Rotation,
+    flush_interval: u64,    // [s]
+    encoder: Option<GzEncoder<File>>,
+    current_path: Option<PathBuf>,
+    current_stem: String,
+    part: u32,
+    part_size: u64,         // [B] uncompressed
+    last_flush_ts: SystemTime,
+}
+
+impl FeedRecorder {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            layout: String::from("%Y/%m/ogn-%Y-%m-%d"),
+            rotation: Rotation::Daily,
+            flush_interval: 10,
+            encoder: None,
+            current_path: None,
+            current_stem: String::new(),
+            part: 0,
+            part_size: 0,
+            last_flush_ts: SystemTime::now(),
+        }
+    }
+
+    /// Path of the archive relative to the directory, in chrono strftime format without extension.
+    /// Default is "%Y/%m/ogn-%Y-%m-%d" which yields e.g. 2023/06/ogn-2023-06-17.log.gz
+    pub fn set_layout(&mut self, layout: &str) {
+        self.layout = layout.to_string();
+    }
+
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+    }
+
+    /// How often the compressed stream is flushed to the disk [s].
+    pub fn set_flush_interval(&mut self, seconds: u64) {
+        self.flush_interval = seconds;
+    }
+
+    /// Path of the archive currently written into.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current_path.as_deref()
+    }
+
+    /// Stores one line with the given arrival time.
+    pub fn record(&mut self, line: &str, arrival_time: DateTime<Utc>) -> Result<()> {
+        let stem = arrival_time.format(&self.layout).to_string();
+        if stem != self.current_stem {
+            self.current_stem = stem;
+            self.part = 0;
+            self.open()?;
+        }
+
+        if let Rotation::Size(max_size) = self.rotation {
+            if self.part_size >= max_size {
+                self.part += 1;
+                self.open()?;
+            }
+        }
+
+        let record = format!("{} {}\n", arrival_time.format("%Y-%m-%d %H:%M:%S%.3f"), line);
+        match self.encoder.as_mut() {
+            Some(encoder) => encoder.write_all(record.as_bytes())?,
+            None => return Err(io::Error::new(io::ErrorKind::NotConnected, "no archive open")),
+        }
+        self.part_size += record.len() as u64;
+
+        if self.last_flush_ts.elapsed().map(|e| e.as_secs()).unwrap_or(0) >= self.flush_interval {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the compressed data written so far to the disk.
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(encoder) = self.encoder.as_mut() {
+            encoder.flush()?;
+            encoder.get_ref().sync_data()?;
+        }
+        self.last_flush_ts = SystemTime::now();
+
+        Ok(())
+    }
+
+    /// Finishes the current archive so that it is a complete gzip file. Called on drop as well.
+    pub fn close(&mut self) -> Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            let file = encoder.finish()?;
+            file.sync_all()?;
+        }
+        self.current_path = None;
+
+        Ok(())
+    }
+
+    fn part_path(&self, part: u32) -> PathBuf {
+        if part > 0 {
+            self.dir.join(format!("{}.{}.log.gz", self.current_stem, part))
+        } else {
+            self.dir.join(format!("{}.log.gz", self.current_stem))
+        }
+    }
+
+    /// Opens a new archive for the current stem, from the current part on.
+    /// An existing archive (e.g. recorded before a restart) is not appended to - the recording continues in the next
+    /// free part. Thus no archive needs to be decompressed to find its size, and one left incomplete by a crash is
+    /// not followed by more data.
+    fn open(&mut self) -> Result<()> {
+        self.close()?;
+
+        while self.part_path(self.part).exists() {
+            self.part += 1;
+        }
+
+        let path = self.part_path(self.part);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        self.part_size = 0;
+        let file = OpenOptions::new().create_new(true).write(true).open(&path)?;
+        info!("Recording into '{}'", path.display());
+        self.encoder = Some(GzEncoder::new(file, Compression::default()));
+        self.current_path = Some(path);
+
+        Ok(())
+    }
+}
+
+impl Observer<String> for FeedRecorder {
+    fn notify(&mut self, line: String) {
+        if let Err(e) = self.record(&line, Utc::now()) {
+            error!("when recording line: {}", e);
+        }
+    }
+}
+
+impl Drop for FeedRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!("when closing archive: {}", e);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::airfields::AirfieldDatabase;
+use crate::data_structures::{AircraftBeacon, AircraftType, Observer};
+
+
+const DEFAULT_CONFIRMATIONS: u32 = 3;       // consecutive fixes needed to change the phase
+const DEFAULT_GAP_TIMEOUT: i64 = 10 * 60;   // [s]
+const AIRBORNE_AGL: i32 = 100;              // [m] above this it is flying regardless of the speed
+const GROUND_AGL: i32 = 50;                 // [m] below this it may be on the ground
+const GROUND_CLIMB_RATE: f64 = 1.0;         // [m/s] max abs climb rate on the ground
+const LOW_AGL_WHEN_LOST: i32 = 150;         // [m] lost below this = probably landed (out of the receivers' coverage)
+const FORGET_LOST_AFTER: i64 = 24 * 3600;   // [s]
+const AIRFIELD_RADIUS: f64 = 5000.0;        // [m] the elevation of an airfield this close is taken as the ground's
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlightPhase {
+    OnGround,
+    Airborne,
+    /// Lost while airborne and high enough that it cannot be said whether it has landed.
+    Lost,
+}
+
+#[derive(Debug, Clone)]
+pub enum FlightEvent {
+    TakeOff { id: String, beacon: AircraftBeacon },
+    /// `estimated` when the landing was not observed but deduced from a reception gap.
+    Landing { id: String, beacon: AircraftBeacon, estimated: bool },
+    /// Emitted after a landing of a flight whose take-off was seen. [s]
+    FlightCompleted { id: String, takeoff_ts: i64, landing_ts: i64, duration: i64 },
+    /// Not heard from while airborne and too high to assume a landing.
+    SignalLostInAir { id: String, beacon: AircraftBeacon },
+}
+
+struct AircraftState {
+    phase: FlightPhase,
+    candidate_count: u32,       // consecutive fixes suggesting the other phase
+    candidate_since: Option<AircraftBeacon>,    // the first of them - the actual take-off / landing fix
+    ground_altitude: Option<i32>,   // [m] altitude when last seen on the ground
+    takeoff_ts: Option<i64>,
+    last_beacon: AircraftBeacon,
+}
+
+/// Take-off & landing speeds [km/h] for the aircraft type.
+fn speed_thresholds(aircraft_type: &AircraftType) -> (u32, u32) {
+    match aircraft_type {
+        AircraftType::Paraglider | AircraftType::HangGlider => (20, 10),
+        AircraftType::Helicopter | AircraftType::Baloon | AircraftType::Airship => (30, 10),
+        AircraftType::JetPlane => (120, 60),
+        _ => (50, 30),
+    }
+}
+
+/// Turns the beacon stream into take-off and landing events per aircraft.
+///
+/// The phase changes only after a number of consecutive fixes agree (hysteresis). The height above ground comes from
+/// beacon.agl when known, otherwise from the elevation of a nearby airfield (see set_airfields()) or the altitude the
+/// aircraft was last seen on the ground at. Without any of them no landing is decided.
+/// Aircraft not heard from for the gap timeout are considered landed when they were last seen low above the ground
+/// (typically out of the receivers' coverage in a valley) and lost in the air otherwise.
+pub struct FlightPhaseDetector {
+    aircraft: HashMap<String, AircraftState>,
+    airfields: Option<AirfieldDatabase>,
+    confirmations: u32,
+    gap_timeout: i64,   // [s]
+    clock_ts: i64,
+    last_gap_check_ts: i64,
+    event_listener: Option<Rc<RefCell<dyn Observer<FlightEvent>>>>,
+}
+
+impl FlightPhaseDetector {
+    pub fn new() -> Self {
+        Self {
+            aircraft: HashMap::new(),
+            airfields: None,
+            confirmations: DEFAULT_CONFIRMATIONS,
+            gap_timeout: DEFAULT_GAP_TIMEOUT,
+            clock_ts: 0,
+            last_gap_check_ts: 0,
+            event_listener: None,
+        }
+    }
+
+    /// Number of consecutive fixes needed to change the flight phase.
+    pub fn set_confirmations(&mut self, confirmations: u32) {
+        self.confirmations = confirmations;
+    }
+
+    /// Silence after which the aircraft is considered landed or lost [s].
+    pub fn set_gap_timeout(&mut self, gap_timeout: i64) {
+        self.gap_timeout = gap_timeout;
+    }
+
+    /// Airfields whose elevation gives the height above ground where the beacons come without the agl.
+    pub fn set_airfields(&mut self, airfields: AirfieldDatabase) {
+        self.airfields = Some(airfields);
+    }
+
+    pub fn set_event_listener(&mut self, listener: impl Observer<FlightEvent> + 'static) {
+        self.event_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    pub fn phase(&self, id: &str) -> Option<FlightPhase> {
+        self.aircraft.get(id).map(|s| s.phase)
+    }
+
+    fn height_above_ground(beacon: &AircraftBeacon, ground_altitude: Option<i32>) -> Option<i32> {
+        beacon.agl.or_else(|| ground_altitude.map(|alt| beacon.altitude - alt))
+    }
+
+    fn looks_airborne(beacon: &AircraftBeacon, ground_altitude: Option<i32>) -> bool {
+        let (takeoff_speed, _) = speed_thresholds(&beacon.aircraft_type);
+        let agl = Self::height_above_ground(beacon, ground_altitude);
+
+        beacon.speed >= takeoff_speed || agl.map(|h| h >= AIRBORNE_AGL).unwrap_or(false)
+    }
+
+    /// Height above ground for the landing: from the agl, a nearby airfield or the take-off elevation.
+    fn landing_height(airfields: Option<&AirfieldDatabase>, beacon: &AircraftBeacon, ground_altitude: Option<i32>) -> Option<i32> {
+        beacon.agl
+            .or_else(|| airfields
+                .and_then(|db| db.nearest(beacon.lat, beacon.lon, AIRFIELD_RADIUS))
+                .map(|(airfield, _)| beacon.altitude_msl - airfield.elevation))
+            .or_else(|| ground_altitude.map(|alt| beacon.altitude - alt))
+    }
+
+    /// Slow, level and low; with the height above ground unknown it is never considered on the ground.
+    fn looks_on_ground(beacon: &AircraftBeacon, height: Option<i32>) -> bool {
+        let (_, landing_speed) = speed_thresholds(&beacon.aircraft_type);
+
+        beacon.speed <= landing_speed
+            && beacon.climb_rate.map(|c| c.abs() < GROUND_CLIMB_RATE).unwrap_or(true)
+            && height.map(|h| h < GROUND_AGL).unwrap_or(false)
+    }
+
+    pub fn ingest(&mut self, beacon: AircraftBeacon) {
+        if beacon.ts > self.clock_ts {
+            self.clock_ts = beacon.ts;
+        }
+
+        let id = beacon.callsign();
+        let mut events = Vec::new();
+
+        match self.aircraft.get_mut(&id) {
+            None => {
+                let phase = if Self::looks_airborne(&beacon, None) { FlightPhase::Airborne } else { FlightPhase::OnGround };
+                let ground_altitude = if phase == FlightPhase::OnGround { Some(beacon.altitude) } else { None };
+                self.aircraft.insert(id, AircraftState { phase, candidate_count: 0, candidate_since: None, ground_altitude, takeoff_ts: None, last_beacon: beacon });
+            },
+            Some(state) => {
+                if beacon.ts <= state.last_beacon.ts {
+                    return;
+                }
+
+                match state.phase {
+                    FlightPhase::OnGround => {
+                        if Self::looks_airborne(&beacon, state.ground_altitude) {
+                            state.candidate_count += 1;
+                            let takeoff_beacon = state.candidate_since.get_or_insert_with(|| beacon.clone()).clone();
+                            if state.candidate_count >= self.confirmations {
+                                state.phase = FlightPhase::Airborne;
+                                state.candidate_count = 0;
+                                state.candidate_since = None;
+                                state.takeoff_ts = Some(takeoff_beacon.ts);
+                                events.push(FlightEvent::TakeOff { id: id.clone(), beacon: takeoff_beacon });
+                            }
+                        } else {
+                            state.candidate_count = 0;
+                            state.candidate_since = None;
+                            state.ground_altitude = Some(beacon.altitude);
+                        }
+                    },
+                    FlightPhase::Airborne => {
+                        if Self::looks_on_ground(&beacon, Self::landing_height(self.airfields.as_ref(), &beacon, state.ground_altitude)) {
+                            state.candidate_count += 1;
+                            let landing_beacon = state.candidate_since.get_or_insert_with(|| beacon.clone()).clone();
+                            if state.candidate_count >= self.confirmations {
+                                state.candidate_count = 0;
+                                state.candidate_since = None;
+                                events.extend(Self::land(&id, state, &landing_beacon, false));
+                            }
+                        } else {
+                            state.candidate_count = 0;
+                            state.candidate_since = None;
+                        }
+                    },
+                    FlightPhase::Lost => {
+                        // heard again after a gap - either still flying or it has landed meanwhile:
+                        if Self::looks_on_ground(&beacon, Self::landing_height(self.airfields.as_ref(), &beacon, state.ground_altitude)) {
+                            events.extend(Self::land(&id, state, &beacon, true));
+                        } else {
+                            state.phase = FlightPhase::Airborne;
+                        }
+                    },
+                }
+
+                state.last_beacon = beacon;
+            },
+        }
+
+        for event in events {
+            self.notify_event_listener(event);
+        }
+
+        if self.clock_ts > self.last_gap_check_ts {
+            self.check_gaps(self.clock_ts);
+        }
+    }
+
+    fn land(id: &str, state: &mut AircraftState, beacon: &AircraftBeacon, estimated: bool) -> Vec<FlightEvent> {
+        state.phase = FlightPhase::OnGround;
+        state.ground_altitude = Some(beacon.altitude);
+
+        let mut events = vec![FlightEvent::Landing { id: id.to_string(), beacon: beacon.clone(), estimated }];
+        if let Some(takeoff_ts) = state.takeoff_ts.take() {
+            events.push(FlightEvent::FlightCompleted {
+                id: id.to_string(),
+                takeoff_ts,
+                landing_ts: beacon.ts,
+                duration: beacon.ts - takeoff_ts,
+            });
+        }
+
+        events
+    }
+
+    /// Resolves aircraft not heard from for the gap timeout.
+    pub fn check_gaps(&mut self, now_ts: i64) {
+        self.last_gap_check_ts = now_ts;
+
+        let silent_ids: Vec<String> = self.aircraft.iter()
+            .filter(|(_, s)| s.last_beacon.ts < now_ts - self.gap_timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        let mut events = Vec::new();
+        for id in silent_ids {
+            let state = match self.aircraft.get_mut(&id) {
+                Some(state) => state,
+                None => continue,
+            };
+
+            match state.phase {
+                FlightPhase::Airborne => {
+                    let beacon = state.last_beacon.clone();
+                    let low = Self::landing_height(self.airfields.as_ref(), &beacon, state.ground_altitude).map(|h| h < LOW_AGL_WHEN_LOST).unwrap_or(false);
+                    if low {
+                        events.extend(Self::land(&id, state, &beacon, true));
+                        self.aircraft.remove(&id);
+                    } else {
+                        state.phase = FlightPhase::Lost;
+                        events.push(FlightEvent::SignalLostInAir { id: id.clone(), beacon });
+                    }
+                },
+                FlightPhase::OnGround => {
+                    self.aircraft.remove(&id);  // parked
+                },
+                FlightPhase::Lost => {  // waiting to be heard again
+                    if state.last_beacon.ts < now_ts - FORGET_LOST_AFTER {
+                        self.aircraft.remove(&id);
+                    }
+                },
+            }
+        }
+
+        for event in events {
+            self.notify_event_listener(event);
+        }
+    }
+
+    fn notify_event_listener(&mut self, event: FlightEvent) {
+        if let Some(listener) = self.event_listener.as_mut() {
+            listener.borrow_mut().notify(event);
+        }
+    }
+}
+
+impl Default for FlightPhaseDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for FlightPhaseDetector {
+    fn notify(&mut self, beacon: AircraftBeacon) {
+        self.ingest(beacon);
+    }
+}
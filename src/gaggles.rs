@@ -0,0 +1,389 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::data_structures::{AircraftBeacon, AircraftType, Observer};
+use crate::spatial_index::SpatialIndex;
+use crate::thermals::CirclingDetector;
+
+
+const DEFAULT_LINK_DISTANCE: f64 = 500.0;   // [m] aircraft closer than this fly together ..
+const DEFAULT_LINK_HEIGHT: i32 = 300;       // [m] .. when also within this height difference
+const DEFAULT_MIN_DURATION: i64 = 60;       // [s] a group has to hold together this long to be announced
+const DEFAULT_DISSOLVE_TIME: i64 = 60;      // [s] apart for this long = left the group
+const EVALUATE_INTERVAL: i64 = 10;          // [s]
+const STALE_TIME: i64 = 60;                 // [s] aircraft not heard for longer are not grouped
+const MIN_AIRBORNE_SPEED: u32 = 20;         // [km/h] slower ones are on the ground
+const MIN_AIRBORNE_AGL: i32 = 50;           // [m] where the agl is known
+const FORMATION_MAX_COURSE_DIFF: f64 = 30.0;    // [deg] from the mean course
+const FORMATION_MAX_SPEED_DIFF: f64 = 0.2;      // relative to the mean speed
+const INDEX_CELL_SIZE: f64 = 0.05;          // [deg]
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupKind {
+    /// Gliders circling together in a thermal.
+    Gaggle,
+    /// Flying the same course and speed.
+    Formation,
+    /// Tow plane with its glider.
+    Tow,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub enum GroupEvent {
+    GroupFormed { group_id: u64, kind: GroupKind, members: Vec<String>, ts: i64 },
+    MembersChanged { group_id: u64, kind: GroupKind, joined: Vec<String>, left: Vec<String>, members: Vec<String>, ts: i64 },
+    /// [s] formed_ts is when the aircraft came together.
+    GroupDissolved { group_id: u64, kind: GroupKind, members: Vec<String>, formed_ts: i64, ts: i64 },
+}
+
+/// Aircraft flying together.
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub id: u64,
+    pub kind: GroupKind,
+    pub formed_ts: i64,     // when the aircraft came together
+    pub last_ts: i64,       // when they were last seen together
+    members: HashMap<String, i64>,  // id -> last seen in the group
+    announced: bool,
+}
+
+impl Group {
+    /// Ids of the members, sorted.
+    pub fn members(&self) -> Vec<String> {
+        let mut members: Vec<String> = self.members.keys().cloned().collect();
+        members.sort();
+
+        members
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.members.contains_key(id)
+    }
+
+    /// Seen in the group when it was last seen together - not just waiting for the dissolve time to leave.
+    fn is_current_member(&self, id: &str) -> bool {
+        self.members.get(id).map(|ts| *ts >= self.last_ts).unwrap_or(false)
+    }
+}
+
+/// Finds groups of aircraft flying together - gliders sharing a thermal, formation flights, aerotows - by clustering
+/// the airborne aircraft in space (single linkage within the link distance and height) and time (a group has to
+/// hold together for the min duration, members leave after being apart for the dissolve time). When a group splits,
+/// the larger part continues it and the rest leaves it after the dissolve time as well.
+pub struct GroupDetector {
+    aircraft: HashMap<String, AircraftBeacon>,
+    index: SpatialIndex,
+    circling: CirclingDetector,
+    groups: Vec<Group>,
+    next_group_id: u64,
+    link_distance: f64,     // [m]
+    link_height: i32,       // [m]
+    min_duration: i64,      // [s]
+    dissolve_time: i64,     // [s]
+    clock_ts: i64,
+    last_evaluate_ts: i64,
+    event_listener: Option<Rc<RefCell<dyn Observer<GroupEvent>>>>,
+}
+
+impl GroupDetector {
+    pub fn new() -> Self {
+        Self {
+            aircraft: HashMap::new(),
+            index: SpatialIndex::new(INDEX_CELL_SIZE),
+            circling: CirclingDetector::new(),
+            groups: Vec::new(),
+            next_group_id: 1,
+            link_distance: DEFAULT_LINK_DISTANCE,
+            link_height: DEFAULT_LINK_HEIGHT,
+            min_duration: DEFAULT_MIN_DURATION,
+            dissolve_time: DEFAULT_DISSOLVE_TIME,
+            clock_ts: 0,
+            last_evaluate_ts: 0,
+            event_listener: None,
+        }
+    }
+
+    /// Max horizontal [m] and vertical [m] distance of aircraft flying together.
+    pub fn set_link_distance(&mut self, link_distance: f64, link_height: i32) {
+        self.link_distance = link_distance;
+        self.link_height = link_height;
+    }
+
+    /// How long the aircraft have to stay together before the group is announced [s].
+    pub fn set_min_duration(&mut self, min_duration: i64) {
+        self.min_duration = min_duration;
+    }
+
+    /// How long a member has to be apart to leave the group [s].
+    pub fn set_dissolve_time(&mut self, dissolve_time: i64) {
+        self.dissolve_time = dissolve_time;
+    }
+
+    pub fn set_event_listener(&mut self, listener: impl Observer<GroupEvent> + 'static) {
+        self.event_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    fn is_airborne(beacon: &AircraftBeacon) -> bool {
+        beacon.speed >= MIN_AIRBORNE_SPEED && beacon.agl.map(|agl| agl >= MIN_AIRBORNE_AGL).unwrap_or(true)
+    }
+
+    pub fn ingest(&mut self, beacon: AircraftBeacon) {
+        if beacon.ts > self.clock_ts {
+            self.clock_ts = beacon.ts;
+        }
+
+        let id = beacon.callsign();
+        if self.aircraft.get(&id).map(|b| beacon.ts <= b.ts).unwrap_or(false) {
+            return;
+        }
+
+        self.circling.process(&beacon);
+        if Self::is_airborne(&beacon) {
+            self.index.insert(&id, beacon.lat, beacon.lon);
+            self.aircraft.insert(id, beacon);
+        } else {
+            self.index.remove(&id);
+            self.aircraft.remove(&id);
+        }
+
+        if self.clock_ts >= self.last_evaluate_ts + EVALUATE_INTERVAL {
+            self.evaluate(self.clock_ts);
+        }
+    }
+
+    /// Connected components (of 2+ aircraft) of the aircraft within the link distance & height of each other.
+    fn clusters(&self) -> Vec<Vec<String>> {
+        let ids: Vec<&String> = self.aircraft.keys().collect();
+        let position: HashMap<&String, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        let mut parent: Vec<usize> = (0..ids.len()).collect();
+
+        fn find(parent: &mut [usize], i: usize) -> usize {
+            let mut root = i;
+            while parent[root] != root {
+                root = parent[root];
+            }
+            let mut i = i;
+            while parent[i] != root {
+                let next = parent[i];
+                parent[i] = root;
+                i = next;
+            }
+
+            root
+        }
+
+        for (i, id) in ids.iter().enumerate() {
+            let beacon = &self.aircraft[*id];
+            for (other_id, _) in self.index.within_radius(beacon.lat, beacon.lon, self.link_distance) {
+                let j = match position.get(&other_id) {
+                    Some(j) if *j != i => *j,
+                    _ => continue,
+                };
+                if (self.aircraft[&other_id].altitude_msl - beacon.altitude_msl).abs() <= self.link_height {
+                    let (root_i, root_j) = (find(&mut parent, i), find(&mut parent, j));
+                    parent[root_i] = root_j;
+                }
+            }
+        }
+
+        let mut components: HashMap<usize, Vec<String>> = HashMap::new();
+        for (i, id) in ids.iter().enumerate() {
+            let root = find(&mut parent, i);
+            components.entry(root).or_default().push((*id).clone());
+        }
+
+        components.into_values().filter(|c| c.len() >= 2).collect()
+    }
+
+    fn classify(&self, members: &[String]) -> GroupKind {
+        let beacons: Vec<&AircraftBeacon> = members.iter().filter_map(|id| self.aircraft.get(id)).collect();
+        if beacons.is_empty() {
+            return GroupKind::Other;
+        }
+
+        if beacons.len() == 2 {
+            let is_tug = |b: &AircraftBeacon| matches!(b.aircraft_type, AircraftType::TowPlane | AircraftType::PistonPlane);
+            let is_glider = |b: &AircraftBeacon| b.aircraft_type == AircraftType::Glider;
+            if (is_tug(beacons[0]) && is_glider(beacons[1])) || (is_glider(beacons[0]) && is_tug(beacons[1])) {
+                return GroupKind::Tow;
+            }
+        }
+
+        let num_circling = members.iter().filter(|id| self.circling.current(id).is_some()).count();
+        if num_circling * 2 >= beacons.len() {
+            return GroupKind::Gaggle;
+        }
+
+        let courses: Vec<f64> = beacons.iter().filter_map(|b| b.course).map(|c| c as f64).collect();
+        if courses.len() == beacons.len() {
+            let (sin, cos) = courses.iter().fold((0.0, 0.0), |(s, c), course| (s + course.to_radians().sin(), c + course.to_radians().cos()));
+            let mean_course = sin.atan2(cos).to_degrees();
+            let mean_speed = beacons.iter().map(|b| b.speed as f64).sum::<f64>() / beacons.len() as f64;
+
+            let same_course = courses.iter().all(|c| ((c - mean_course + 540.0).rem_euclid(360.0) - 180.0).abs() <= FORMATION_MAX_COURSE_DIFF);
+            let same_speed = beacons.iter().all(|b| (b.speed as f64 - mean_speed).abs() <= FORMATION_MAX_SPEED_DIFF * mean_speed);
+            if same_course && same_speed {
+                return GroupKind::Formation;
+            }
+        }
+
+        GroupKind::Other
+    }
+
+    /// Re-clusters the aircraft and updates the groups.
+    pub fn evaluate(&mut self, now_ts: i64) {
+        self.last_evaluate_ts = now_ts;
+
+        let stale_ids: Vec<String> = self.aircraft.iter()
+            .filter(|(_, b)| b.ts < now_ts - STALE_TIME)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in stale_ids {
+            self.index.remove(&id);
+            self.aircraft.remove(&id);
+        }
+        self.circling.expire(now_ts);
+
+        // largest first so that it continues a group which splits:
+        let mut clusters = self.clusters();
+        for cluster in clusters.iter_mut() {
+            cluster.sort();
+        }
+        clusters.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+
+        let mut events = Vec::new();
+        let mut matched = vec![false; self.groups.len()];
+        let mut moved_out: HashMap<usize, Vec<String>> = HashMap::new();   // group index -> members merged into another
+        for cluster in clusters {
+            let kind = self.classify(&cluster);
+
+            // the group sharing the most current members:
+            let best = self.groups.iter().enumerate()
+                .filter(|(i, _)| !matched[*i])
+                .map(|(i, g)| (i, cluster.iter().filter(|id| g.is_current_member(id)).count()))
+                .filter(|(_, overlap)| *overlap > 0)
+                .max_by_key(|(_, overlap)| *overlap);
+
+            let group_idx = match best {
+                Some((i, _)) => {
+                    matched[i] = true;
+                    let group = &mut self.groups[i];
+                    let joined: Vec<String> = cluster.iter().filter(|id| !group.contains(id)).cloned().collect();
+                    for id in &cluster {
+                        group.members.insert(id.clone(), now_ts);
+                    }
+                    group.last_ts = now_ts;
+                    group.kind = kind;
+
+                    if group.announced && !joined.is_empty() {
+                        let mut joined = joined;
+                        joined.sort();
+                        events.push(GroupEvent::MembersChanged { group_id: group.id, kind, joined, left: Vec::new(), members: group.members(), ts: now_ts });
+                    }
+                    i
+                },
+                None => {
+                    let members = cluster.iter().map(|id| (id.clone(), now_ts)).collect();
+                    self.groups.push(Group { id: self.next_group_id, kind, formed_ts: now_ts, last_ts: now_ts, members, announced: false });
+                    self.next_group_id += 1;
+                    matched.push(true);
+                    self.groups.len() - 1
+                },
+            };
+
+            // merged groups: their current members move over, the rest of the old group dissolves below; those split off
+            // from a group leave it after the dissolve time
+            for (i, group) in self.groups.iter_mut().enumerate() {
+                if i == group_idx {
+                    continue;
+                }
+                let moved: Vec<String> = cluster.iter().filter(|id| group.is_current_member(id)).cloned().collect();
+                for id in moved {
+                    group.members.remove(&id);
+                    moved_out.entry(i).or_default().push(id);
+                }
+            }
+        }
+
+        let dissolve_time = self.dissolve_time;
+        for (i, group) in self.groups.iter_mut().enumerate() {
+            let mut left: Vec<String> = group.members.iter()
+                .filter(|(_, ts)| **ts < now_ts - dissolve_time)
+                .map(|(id, _)| id.clone())
+                .collect();
+            for id in &left {
+                group.members.remove(id);
+            }
+            left.extend(moved_out.remove(&i).unwrap_or_default());
+            left.sort();
+
+            if group.members.len() < 2 {
+                if group.announced {
+                    let mut members = group.members();
+                    members.extend(left);
+                    members.sort();
+                    events.push(GroupEvent::GroupDissolved { group_id: group.id, kind: group.kind, members, formed_ts: group.formed_ts, ts: now_ts });
+                }
+                group.members.clear();
+                continue;
+            }
+
+            if group.announced && !left.is_empty() {
+                events.push(GroupEvent::MembersChanged { group_id: group.id, kind: group.kind, joined: Vec::new(), left, members: group.members(), ts: now_ts });
+            }
+
+            if !group.announced && group.last_ts == now_ts && now_ts - group.formed_ts >= self.min_duration {
+                group.announced = true;
+                events.push(GroupEvent::GroupFormed { group_id: group.id, kind: group.kind, members: group.members(), ts: now_ts });
+            }
+        }
+        self.groups.retain(|g| !g.members.is_empty());
+
+        for event in events {
+            self.notify_event_listener(event);
+        }
+    }
+
+    /// Announced groups flying right now.
+    pub fn groups(&self) -> Vec<&Group> {
+        self.groups.iter().filter(|g| g.announced).collect()
+    }
+
+    /// Announced group the aircraft belongs to.
+    pub fn group_of(&self, id: &str) -> Option<&Group> {
+        self.groups.iter().find(|g| g.announced && g.contains(id))
+    }
+
+    /// Time of the most recent beacon seen [s].
+    pub fn clock_ts(&self) -> i64 {
+        self.clock_ts
+    }
+
+    fn notify_event_listener(&mut self, event: GroupEvent) {
+        if let Some(listener) = self.event_listener.as_mut() {
+            listener.borrow_mut().notify(event);
+        }
+    }
+}
+
+impl Default for GroupDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for GroupDetector {
+    fn notify(&mut self, beacon: AircraftBeacon) {
+        self.ingest(beacon);
+    }
+}
@@ -0,0 +1,144 @@
+// Geodetic computations on WGS84 lat/lon coordinates [deg]; distances in [m], bearings in [deg] clockwise from north.
+
+
+pub const EARTH_RADIUS: f64 = 6_371_000.0;  // [m] mean radius for the spherical formulas
+pub const METERS_PER_DEG_LAT: f64 = 111_320.0;  // [m] length of a degree of latitude (and of longitude on the equator)
+
+// WGS84 ellipsoid:
+const WGS84_A: f64 = 6_378_137.0;           // [m] semi-major axis
+const WGS84_F: f64 = 1.0 / 298.257_223_563; // flattening
+const WGS84_B: f64 = WGS84_A * (1.0 - WGS84_F);
+
+const VINCENTY_MAX_ITERATIONS: u32 = 200;
+const VINCENTY_PRECISION: f64 = 1e-12;
+
+/// Great-circle distance between two points [m] (spherical, ~0.5% error).
+pub fn haversine_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS * a.min(1.0).sqrt().asin()
+}
+
+/// Distance on the WGS84 ellipsoid (Vincenty's inverse formula, sub-millimetre accuracy) [m].
+/// None for nearly antipodal points where the iteration does not converge.
+pub fn vincenty_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> Option<f64> {
+    let l = (lon2 - lon1).to_radians();
+    let u1 = ((1.0 - WGS84_F) * lat1.to_radians().tan()).atan();
+    let u2 = ((1.0 - WGS84_F) * lat2.to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = u1.sin_cos();
+    let (sin_u2, cos_u2) = u2.sin_cos();
+
+    let mut lambda = l;
+    for _ in 0..VINCENTY_MAX_ITERATIONS {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2) + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2)).sqrt();
+        if sin_sigma == 0.0 {
+            return Some(0.0);   // coincident points
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos_sq_alpha = 1.0 - sin_alpha * sin_alpha;
+        let cos_2sigma_m = if cos_sq_alpha != 0.0 { cos_sigma - 2.0 * sin_u1 * sin_u2 / cos_sq_alpha } else { 0.0 };   // equatorial line
+        let c = WGS84_F / 16.0 * cos_sq_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos_sq_alpha));
+
+        let lambda_prev = lambda;
+        lambda = l + (1.0 - c) * WGS84_F * sin_alpha
+            * (sigma + c * sin_sigma * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)));
+
+        if (lambda - lambda_prev).abs() < VINCENTY_PRECISION {
+            let u_sq = cos_sq_alpha * (WGS84_A * WGS84_A - WGS84_B * WGS84_B) / (WGS84_B * WGS84_B);
+            let a = 1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+            let b = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+            let delta_sigma = b * sin_sigma * (cos_2sigma_m + b / 4.0 * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)
+                - b / 6.0 * cos_2sigma_m * (-3.0 + 4.0 * sin_sigma * sin_sigma) * (-3.0 + 4.0 * cos_2sigma_m * cos_2sigma_m)));
+
+            return Some(WGS84_B * a * (sigma - delta_sigma));
+        }
+    }
+
+    None
+}
+
+/// Ellipsoidal distance where it can be computed, the great-circle one otherwise [m].
+pub fn geodesic_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    vincenty_distance(lat1, lon1, lat2, lon2).unwrap_or_else(|| haversine_distance(lat1, lon1, lat2, lon2))
+}
+
+/// Bearing at the start of the great-circle path from point 1 to point 2 [deg 0..360).
+pub fn initial_bearing(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lon = (lon2 - lon1).to_radians();
+
+    let y = d_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Bearing at the end of the great-circle path from point 1 to point 2 [deg 0..360).
+pub fn final_bearing(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    (initial_bearing(lat2, lon2, lat1, lon1) + 180.0).rem_euclid(360.0)
+}
+
+/// Point reached from the start travelling the distance [m] along the great circle of the initial bearing [deg].
+/// Returns (lat, lon) with lon in <-180, 180).
+pub fn destination_point(lat: f64, lon: f64, bearing: f64, distance: f64) -> (f64, f64) {
+    let delta = distance / EARTH_RADIUS;
+    let theta = bearing.to_radians();
+    let (lat1, lon1) = (lat.to_radians(), lon.to_radians());
+
+    let lat2 = (lat1.sin() * delta.cos() + lat1.cos() * delta.sin() * theta.cos()).asin();
+    let lon2 = lon1 + (theta.sin() * delta.sin() * lat1.cos()).atan2(delta.cos() - lat1.sin() * lat2.sin());
+
+    (lat2.to_degrees(), (lon2.to_degrees() + 180.0).rem_euclid(360.0) - 180.0)
+}
+
+/// Distance of the point from the great circle through the path start & end [m]; negative left of the path.
+pub fn cross_track_distance(lat: f64, lon: f64, start_lat: f64, start_lon: f64, end_lat: f64, end_lon: f64) -> f64 {
+    let delta13 = haversine_distance(start_lat, start_lon, lat, lon) / EARTH_RADIUS;
+    let theta13 = initial_bearing(start_lat, start_lon, lat, lon).to_radians();
+    let theta12 = initial_bearing(start_lat, start_lon, end_lat, end_lon).to_radians();
+
+    (delta13.sin() * (theta13 - theta12).sin()).asin() * EARTH_RADIUS
+}
+
+/// Distance from the path start to the point on the path closest to the point [m]; negative behind the start.
+pub fn along_track_distance(lat: f64, lon: f64, start_lat: f64, start_lon: f64, end_lat: f64, end_lon: f64) -> f64 {
+    let delta13 = haversine_distance(start_lat, start_lon, lat, lon) / EARTH_RADIUS;
+    let delta_xt = cross_track_distance(lat, lon, start_lat, start_lon, end_lat, end_lon) / EARTH_RADIUS;
+    let theta13 = initial_bearing(start_lat, start_lon, lat, lon).to_radians();
+    let theta12 = initial_bearing(start_lat, start_lon, end_lat, end_lon).to_radians();
+
+    let distance = (delta13.cos() / delta_xt.cos()).clamp(-1.0, 1.0).acos() * EARTH_RADIUS;
+    if (theta12 - theta13).cos() < 0.0 { -distance } else { distance }
+}
+
+/// Whether the point lies inside the polygon of (lat, lon) vertices (ray casting; the polygon may be open or closed).
+/// Polygons across the antimeridian are handled by taking the longitudes relative to the point.
+pub fn point_in_polygon(lat: f64, lon: f64, polygon: &[(f64, f64)]) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+
+    let relative_lon = |vertex_lon: f64| (vertex_lon - lon + 180.0).rem_euclid(360.0) - 180.0;
+
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (lat_i, lon_i) = (polygon[i].0, relative_lon(polygon[i].1));
+        let (lat_j, lon_j) = (polygon[j].0, relative_lon(polygon[j].1));
+
+        if (lat_i > lat) != (lat_j > lat) {
+            let lon_cross = lon_i + (lat - lat_i) / (lat_j - lat_i) * (lon_j - lon_i);
+            if lon_cross > 0.0 {
+                inside = !inside;
+            }
+        }
+        j = i;
+    }
+
+    inside
+}
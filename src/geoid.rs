@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::data_structures::{AddressType, AircraftBeacon};
+
+
+const EDGE_TOLERANCE: f64 = 1e-6;   // [grid cells] rounding of positions at the edge of a regional grid
+
+/// What the altitude reported by a source is relative to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AltitudeReference {
+    /// Mean sea level (the geoid) - no correction needed.
+    Msl,
+    /// WGS84 ellipsoid, as GPS receivers natively compute it.
+    Ellipsoid,
+}
+
+/// Geoid undulation grid (height of the geoid above the WGS84 ellipsoid), e.g. EGM96 or EGM2008.
+/// Loads the NGA WW15MGH.GRD text grid or the GeographicLib .pgm files (egm96-5.pgm, egm2008-2_5.pgm, ..).
+#[derive(Debug, Clone)]
+pub struct GeoidModel {
+    rows: usize,
+    cols: usize,
+    north: f64,     // [deg] latitude of the first row
+    west: f64,      // [deg] longitude of the first column
+    lat_step: f64,  // [deg]
+    lon_step: f64,  // [deg]
+    values: Vec<f32>,   // [m] rows from the north
+}
+
+impl GeoidModel {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        if data.starts_with(b"P5") {
+            Self::from_pgm_bytes(&data)
+        } else {
+            Self::from_grd_str(&String::from_utf8_lossy(&data))
+        }
+    }
+
+    /// NGA grid: header 'south north west east dlat dlon' followed by the values row by row from the north.
+    pub fn from_grd_str(content: &str) -> io::Result<Self> {
+        let numbers: Vec<f64> = content.split_whitespace()
+            .map(|s| s.parse::<f64>().map_err(|e| invalid_data(&format!("invalid number '{}': {}", s, e))))
+            .collect::<io::Result<Vec<f64>>>()?;
+        if numbers.len() < 6 {
+            return Err(invalid_data("missing grid header"));
+        }
+
+        let (south, north, west, east, lat_step, lon_step) = (numbers[0], numbers[1], numbers[2], numbers[3], numbers[4], numbers[5]);
+        if lat_step <= 0.0 || lon_step <= 0.0 {
+            return Err(invalid_data("invalid grid spacing"));
+        }
+        if !(-90.0..=90.0).contains(&south) || !(-90.0..=90.0).contains(&north) || south >= north
+            || !(-180.0..=360.0).contains(&west) || west >= east || east - west > 360.0 {
+            return Err(invalid_data("invalid grid bounds"));
+        }
+        let rows = ((north - south) / lat_step).round() as usize + 1;
+        let cols = ((east - west) / lon_step).round() as usize + 1;
+        if rows < 2 || cols < 2 {
+            return Err(invalid_data("grid smaller than 2x2"));
+        }
+        let values: Vec<f32> = numbers[6..].iter().map(|v| *v as f32).collect();
+        if values.len() != rows * cols {
+            return Err(invalid_data(&format!("expected {}x{} values, got {}", rows, cols, values.len())));
+        }
+
+        Ok(Self { rows, cols, north, west, lat_step, lon_step, values })
+    }
+
+    /// GeographicLib grid: 16 bit PGM image from 90N and 0E with the 'Offset' and 'Scale' of the values in comments.
+    pub fn from_pgm_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut offset = 0.0;
+        let mut scale = 1.0;
+        let mut header_values: Vec<usize> = Vec::new();   // width, height, maxval
+        let mut pos = 2;
+
+        while header_values.len() < 3 {
+            let end = data[pos..].iter().position(|b| *b == b'\n').map(|p| pos + p).ok_or_else(|| invalid_data("truncated PGM header"))?;
+            let line = String::from_utf8_lossy(&data[pos..end]).trim().to_string();
+            pos = end + 1;
+
+            if let Some(comment) = line.strip_prefix('#') {
+                let mut parts = comment.split_whitespace();
+                match (parts.next(), parts.next().and_then(|v| v.parse::<f64>().ok())) {
+                    (Some("Offset"), Some(v)) => offset = v,
+                    (Some("Scale"), Some(v)) => scale = v,
+                    _ => (),
+                }
+            } else {
+                for value in line.split_whitespace() {
+                    header_values.push(value.parse().map_err(|_| invalid_data("invalid PGM header"))?);
+                }
+            }
+        }
+
+        let (cols, rows) = (header_values[0], header_values[1]);
+        if cols < 2 || rows < 2 {
+            return Err(invalid_data("invalid PGM size"));
+        }
+        let raw = data.get(pos..pos + rows * cols * 2).ok_or_else(|| invalid_data("truncated PGM data"))?;
+        let values = raw.chunks_exact(2)
+            .map(|b| (offset + scale * u16::from_be_bytes([b[0], b[1]]) as f64) as f32)
+            .collect();
+
+        Ok(Self {
+            rows,
+            cols,
+            north: 90.0,
+            west: 0.0,
+            lat_step: 180.0 / (rows - 1) as f64,
+            lon_step: 360.0 / cols as f64,
+            values,
+        })
+    }
+
+    /// The grid goes all the way around the globe (wraps at 360 deg).
+    pub fn is_global(&self) -> bool {
+        self.cols as f64 * self.lon_step >= 360.0 - EDGE_TOLERANCE
+    }
+
+    /// Geoid height above the WGS84 ellipsoid [m] (bilinear interpolation), None outside of a regional grid.
+    pub fn separation(&self, lat: f64, lon: f64) -> Option<f64> {
+        let y = (self.north - lat) / self.lat_step;
+        if !(-EDGE_TOLERANCE..=(self.rows - 1) as f64 + EDGE_TOLERANCE).contains(&y) {
+            return None;
+        }
+        let y = y.clamp(0.0, (self.rows - 1) as f64);
+        let row = (y.floor() as usize).min(self.rows - 2);
+        let fy = y - row as f64;
+
+        let x = (lon - self.west).rem_euclid(360.0) / self.lon_step;
+        let (col, col2) = if self.is_global() {
+            // the grid may or may not repeat the first column at 360 deg:
+            let cols_around = (360.0 / self.lon_step).round() as usize;
+            let col = (x.floor() as usize).min(cols_around - 1);
+            (col, (col + 1) % cols_around)
+        } else {
+            if x > (self.cols - 1) as f64 + EDGE_TOLERANCE {
+                return None;
+            }
+            let col = (x.floor() as usize).min(self.cols - 2);
+            (col, col + 1)
+        };
+        let fx = (x - col as f64).min(1.0);
+
+        let value = |r: usize, c: usize| self.values[r * self.cols + c] as f64;
+
+        Some((1.0 - fy) * ((1.0 - fx) * value(row, col) + fx * value(row, col2))
+            + fy * ((1.0 - fx) * value(row + 1, col) + fx * value(row + 1, col2)))
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Converts the altitudes of the sources reporting ellipsoidal heights to MSL. The policy is set per address type
+/// as it depends on the devices (and their firmware) sending under it.
+#[derive(Debug, Clone)]
+pub struct GeoidCorrection {
+    model: GeoidModel,
+    references: HashMap<AddressType, AltitudeReference>,
+    default_reference: AltitudeReference,
+}
+
+impl GeoidCorrection {
+    pub fn new(model: GeoidModel) -> Self {
+        Self { model, references: HashMap::new(), default_reference: AltitudeReference::Msl }
+    }
+
+    pub fn set_reference(&mut self, addr_type: AddressType, reference: AltitudeReference) {
+        self.references.insert(addr_type, reference);
+    }
+
+    /// Reference of the address types without their own (default Msl).
+    pub fn set_default_reference(&mut self, reference: AltitudeReference) {
+        self.default_reference = reference;
+    }
+
+    pub fn reference(&self, addr_type: &AddressType) -> AltitudeReference {
+        self.references.get(addr_type).copied().unwrap_or(self.default_reference)
+    }
+
+    pub fn model(&self) -> &GeoidModel {
+        &self.model
+    }
+
+    /// Fills in beacon.altitude_msl from the reported altitude. Outside of a regional grid the altitude is taken as is.
+    pub fn correct(&self, beacon: &mut AircraftBeacon) {
+        beacon.altitude_msl = match self.reference(&beacon.addr_type) {
+            AltitudeReference::Msl => beacon.altitude,
+            AltitudeReference::Ellipsoid => match self.model.separation(beacon.lat, beacon.lon) {
+                Some(separation) => beacon.altitude - separation.round() as i32,
+                None => beacon.altitude,
+            },
+        };
+    }
+}
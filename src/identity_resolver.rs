@@ -0,0 +1,421 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use log::info;
+
+use crate::data_structures::{AircraftBeacon, AircraftType, Observer};
+use crate::spatial_index::SpatialIndex;
+use crate::geodesy::haversine_distance;
+
+
+const MAX_TRACK_LEN: usize = 1000;          // [fixes]
+const COLOCATION_DISTANCE: f64 = 200.0;     // [m] horizontal
+const COLOCATION_HEIGHT: i32 = 100;         // [m] vertical
+const COLOCATION_TIME: i64 = 2;             // [s] max time difference of the compared fixes
+const SEPARATION_DISTANCE: f64 = 2000.0;    // [m] seen this far apart at the same time = certainly not the same aircraft
+const SAME_ADDR_COLOCATIONS_TO_LINK: u32 = 2;   // co-located fixes needed to link the same hex address
+const TIGHT_COLOCATION_DISTANCE: f64 = 30.0;    // [m] closer than a tow rope - for ids with different addresses
+const MAX_COURSE_DIFF: f64 = 15.0;          // [deg]
+const MAX_SPEED_DIFF: u32 = 10;             // [km/h]
+const MAX_CLIMB_RATE_DIFF: f64 = 1.0;       // [m/s]
+const MAX_COLOCATION_GAP: i64 = 30;         // [s] without a co-located fix restarts the co-location
+
+/// One physical aircraft seen under one or more source ids (e.g. FLR484D20 + ICA484D20 + OGN123456).
+#[derive(Debug, Clone)]
+pub struct LogicalAircraft {
+    pub id: String,                 // the preferred one of the source ids
+    pub source_ids: Vec<String>,
+    pub current: AircraftBeacon,    // most recent beacon from any of the sources
+    pub track: VecDeque<AircraftBeacon>,    // merged track of all sources, oldest first
+}
+
+impl LogicalAircraft {
+    fn new(beacon: &AircraftBeacon) -> Self {
+        let id = beacon.callsign();
+        Self { id: id.clone(), source_ids: vec![id], current: beacon.clone(), track: VecDeque::new() }
+    }
+
+    /// Adds the fix keeping the track ordered by time; a fix with a time already present (from another source) is skipped.
+    fn add_fix(&mut self, beacon: &AircraftBeacon) {
+        let pos = self.track.partition_point(|b| b.ts < beacon.ts);
+        if self.track.get(pos).map(|b| b.ts == beacon.ts).unwrap_or(false) {
+            return;
+        }
+        self.track.insert(pos, beacon.clone());
+        while self.track.len() > MAX_TRACK_LEN {
+            self.track.pop_front();
+        }
+
+        if beacon.ts >= self.current.ts {
+            self.current = beacon.clone();
+        }
+    }
+
+    fn merge(&mut self, other: LogicalAircraft) {
+        self.source_ids.extend(other.source_ids);
+        for beacon in other.track.iter() {
+            self.add_fix(beacon);
+        }
+        if other.current.ts > self.current.ts {
+            self.current = other.current;
+        }
+        self.rank_ids();
+    }
+
+    /// Adds a source id linked before it was heard from.
+    fn add_source_id(&mut self, id: &str) {
+        if !self.source_ids.iter().any(|s| s == id) {
+            self.source_ids.push(id.to_string());
+            self.rank_ids();
+        }
+    }
+
+    fn rank_ids(&mut self) {
+        // ICAO address is the most stable identity, then FLARM, then OGN:
+        let rank = |id: &String| match &id[..3.min(id.len())] {
+            "ICA" => 0,
+            "FLR" => 1,
+            "OGN" => 2,
+            _ => 3,
+        };
+        self.source_ids.sort_by(|a, b| rank(a).cmp(&rank(b)).then(a.cmp(b)));
+        self.id = self.source_ids[0].clone();
+    }
+}
+
+/// How two source ids got linked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LinkKind {
+    /// By link(), e.g. from a device database; stays until the ids are forgotten.
+    Explicit,
+    SameAddress,
+    Colocation,
+}
+
+/// Consecutive co-located fixes of two ids.
+struct Colocation {
+    count: u32,
+    since_ts: i64,
+    last_ts: i64,
+}
+
+/// Links the source ids of one aircraft into a single logical aircraft. Two ids are linked when
+///  - they share the same hex address under different address types and are seen at the same place more than once,
+///  - explicitly by link(), e.g. from a device database,
+///  - or, when enabled by set_colocation_linking(), they fly together for a long time: within a few tens of meters
+///    with matching course, speed and climb rate. Tow planes are never linked this way (aerotow).
+///
+/// The automatic links are undone when the ids are seen far apart at the same time, the logical aircraft is then
+/// split up again. The beacons are passed on to the beacon listener unchanged. A logical aircraft is forgotten once
+/// all its source ids have been removed; explicit links are kept.
+pub struct IdentityResolver {
+    parent: HashMap<String, String>,        // union-find over the source ids
+    links: HashMap<(String, String), LinkKind>,     // by pair_key()
+    groups: HashMap<String, LogicalAircraft>,   // by union-find root
+    latest: HashMap<String, AircraftBeacon>,    // by source id
+    index: SpatialIndex,
+    colocations: HashMap<(String, String), Colocation>,     // by pair_key()
+    colocation_duration: Option<i64>,   // [s] flying together this long links different addresses; None = off
+    beacon_listener: Option<Rc<RefCell<dyn Observer<AircraftBeacon>>>>,
+}
+
+impl IdentityResolver {
+    pub fn new() -> Self {
+        Self {
+            parent: HashMap::new(),
+            links: HashMap::new(),
+            groups: HashMap::new(),
+            latest: HashMap::new(),
+            index: SpatialIndex::new(0.25),
+            colocations: HashMap::new(),
+            colocation_duration: None,
+            beacon_listener: None,
+        }
+    }
+
+    /// Links ids of different addresses flying together for the given time [s], e.g. 600. Off (None) by default.
+    pub fn set_colocation_linking(&mut self, duration: Option<i64>) {
+        self.colocation_duration = duration;
+    }
+
+    pub fn set_beacon_listener(&mut self, listener: impl Observer<AircraftBeacon> + 'static) {
+        self.beacon_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    fn root(&mut self, id: &str) -> String {
+        let mut root = id.to_string();
+        while let Some(parent) = self.parent.get(&root) {
+            if *parent == root {
+                break;
+            }
+            root = parent.clone();
+        }
+
+        // path compression:
+        let mut node = id.to_string();
+        while node != root {
+            let next = self.parent.insert(node, root.clone()).unwrap_or_else(|| root.clone());
+            node = next;
+        }
+
+        root
+    }
+
+    /// Source ids in the same union-find set as the root.
+    fn members(&mut self, root: &str) -> Vec<String> {
+        let ids: Vec<String> = self.parent.keys().cloned().collect();
+
+        ids.into_iter().filter(|id| self.root(id) == root).collect()
+    }
+
+    /// Links two source ids (callsigns, e.g. FLRDDA5BA & OGN123456) into one logical aircraft.
+    pub fn link(&mut self, id_a: &str, id_b: &str) {
+        self.links.insert(Self::pair_key(id_a, id_b), LinkKind::Explicit);
+        self.union(id_a, id_b);
+
+        info!("Linked identities {} & {}", id_a, id_b);
+    }
+
+    fn union(&mut self, id_a: &str, id_b: &str) {
+        let root_a = self.root(id_a);
+        let root_b = self.root(id_b);
+        if root_a == root_b {
+            return;
+        }
+
+        let group_b = self.groups.remove(&root_b);
+        match (self.groups.get_mut(&root_a), group_b) {
+            (Some(group_a), Some(group_b)) => group_a.merge(group_b),
+            (None, Some(group_b)) => {
+                self.groups.insert(root_a.clone(), group_b);
+            },
+            _ => (),
+        }
+        self.parent.insert(root_a.clone(), root_a.clone());
+        self.parent.insert(root_b, root_a);
+    }
+
+    /// Logical aircraft id the source id belongs to.
+    pub fn logical_id(&mut self, id: &str) -> String {
+        let root = self.root(id);
+        match self.groups.get(&root) {
+            Some(group) => group.id.clone(),
+            None => id.to_string(),
+        }
+    }
+
+    /// Logical aircraft the source id belongs to.
+    pub fn get(&mut self, id: &str) -> Option<&LogicalAircraft> {
+        let root = self.root(id);
+        self.groups.get(&root)
+    }
+
+    /// All logical aircraft, each once regardless of how many sources it is heard from.
+    pub fn iter(&self) -> impl Iterator<Item = &LogicalAircraft> {
+        self.groups.values()
+    }
+
+    /// Current state of each logical aircraft, e.g. one icon per aircraft on a traffic display.
+    pub fn current_aircraft(&self) -> Vec<AircraftBeacon> {
+        self.groups.values().map(|g| g.current.clone()).collect()
+    }
+
+    pub fn ingest(&mut self, beacon: AircraftBeacon) {
+        let id = beacon.callsign();
+        self.add_to_group(&beacon);
+
+        self.find_links(&id, &beacon);
+
+        self.latest.insert(id.clone(), beacon.clone());
+        self.index.insert(&id, beacon.lat, beacon.lon);
+
+        if let Some(listener) = self.beacon_listener.as_mut() {
+            listener.borrow_mut().notify(beacon);
+        }
+    }
+
+    fn add_to_group(&mut self, beacon: &AircraftBeacon) {
+        let id = beacon.callsign();
+        let root = self.root(&id);
+        if !self.parent.contains_key(&id) {
+            self.parent.insert(id.clone(), id.clone());
+        }
+        let group = self.groups.entry(root).or_insert_with(|| LogicalAircraft::new(beacon));
+        group.add_source_id(&id);
+        group.add_fix(beacon);
+    }
+
+    /// Same position and altitude of fixes at the same time; with different addresses also the same course, speed and
+    /// climb rate.
+    fn colocated(beacon: &AircraftBeacon, other: &AircraftBeacon, same_addr: bool) -> bool {
+        if (other.altitude - beacon.altitude).abs() > COLOCATION_HEIGHT {
+            return false;
+        }
+        if same_addr {
+            return true;
+        }
+
+        let is_tug = |b: &AircraftBeacon| b.aircraft_type == AircraftType::TowPlane;
+        let course_diff = match (beacon.course, other.course) {
+            (Some(a), Some(b)) => ((a as f64 - b as f64 + 540.0).rem_euclid(360.0) - 180.0).abs(),
+            _ => return false,
+        };
+        let climb_diff = match (beacon.climb_rate, other.climb_rate) {
+            (Some(a), Some(b)) => (a - b).abs(),
+            _ => 0.0,
+        };
+
+        !is_tug(beacon) && !is_tug(other)
+            && haversine_distance(beacon.lat, beacon.lon, other.lat, other.lon) <= TIGHT_COLOCATION_DISTANCE
+            && course_diff <= MAX_COURSE_DIFF
+            && beacon.speed.abs_diff(other.speed) <= MAX_SPEED_DIFF
+            && climb_diff <= MAX_CLIMB_RATE_DIFF
+    }
+
+    /// Compares the fix with the latest fixes of other ids around.
+    fn find_links(&mut self, id: &str, beacon: &AircraftBeacon) {
+        for (other_id, _) in self.index.within_radius(beacon.lat, beacon.lon, COLOCATION_DISTANCE) {
+            // fixes not at the same time tell nothing:
+            let other = match self.latest.get(&other_id) {
+                Some(other) if other_id != id && other.addr_type != beacon.addr_type && (other.ts - beacon.ts).abs() <= COLOCATION_TIME => other,
+                _ => continue,
+            };
+            let same_addr = other.addr == beacon.addr;
+            if !same_addr && self.colocation_duration.is_none() {
+                continue;
+            }
+
+            let key = Self::pair_key(id, &other_id);
+            if !Self::colocated(beacon, other, same_addr) {
+                self.colocations.remove(&key);
+                continue;
+            }
+            if self.root(&other_id) == self.root(id) {
+                continue;
+            }
+
+            let colocation = self.colocations.entry(key.clone()).or_insert(Colocation { count: 0, since_ts: beacon.ts, last_ts: i64::MIN });
+            if beacon.ts > colocation.last_ts + MAX_COLOCATION_GAP {
+                *colocation = Colocation { count: 0, since_ts: beacon.ts, last_ts: beacon.ts - COLOCATION_TIME - 1 };
+            }
+            if beacon.ts > colocation.last_ts + COLOCATION_TIME {
+                colocation.count += 1;
+                colocation.last_ts = beacon.ts;
+            }
+
+            let (kind, linked) = if same_addr {
+                (LinkKind::SameAddress, colocation.count >= SAME_ADDR_COLOCATIONS_TO_LINK)
+            } else {
+                (LinkKind::Colocation, self.colocation_duration.map(|d| colocation.last_ts - colocation.since_ts >= d).unwrap_or(false))
+            };
+            if linked {
+                self.colocations.remove(&key);
+                self.links.entry(key).or_insert(kind);
+                self.union(id, &other_id);
+                info!("Linked identities {} & {}", id, other_id);
+            }
+        }
+
+        // seen far apart at the same time = not the same aircraft:
+        let separated: Vec<(String, String)> = self.links.iter()
+            .filter(|(_, kind)| **kind != LinkKind::Explicit)
+            .map(|(key, _)| key)
+            .chain(self.colocations.keys())
+            .filter(|(a, b)| a == id || b == id)
+            .filter(|(a, b)| {
+                let other_id = if a == id { b } else { a };
+                match self.latest.get(other_id) {
+                    Some(other) => (other.ts - beacon.ts).abs() <= COLOCATION_TIME
+                        && haversine_distance(beacon.lat, beacon.lon, other.lat, other.lon) > SEPARATION_DISTANCE,
+                    None => false,
+                }
+            })
+            .cloned()
+            .collect();
+        for key in separated {
+            self.colocations.remove(&key);
+            if self.links.remove(&key).is_some() {
+                info!("Unlinked identities {} & {}", key.0, key.1);
+                self.split(&key.0);
+            }
+        }
+    }
+
+    /// Rebuilds the logical aircraft of the id from the remaining links, e.g. after an unlink.
+    fn split(&mut self, id: &str) {
+        let root = self.root(id);
+        let members = self.members(&root);
+        let group = self.groups.remove(&root);
+        for member in members.iter() {
+            self.parent.remove(member);
+        }
+
+        let links: Vec<(String, String)> = self.links.keys()
+            .filter(|(a, b)| members.contains(a) || members.contains(b))
+            .cloned()
+            .collect();
+        for (a, b) in links {
+            self.union(&a, &b);
+        }
+
+        // the fixes go back to the logical aircraft of their source:
+        let mut fixes: Vec<AircraftBeacon> = group.map(|g| g.track.into_iter().collect()).unwrap_or_default();
+        fixes.extend(members.iter().filter_map(|m| self.latest.get(m)).cloned());
+        for beacon in fixes.iter() {
+            self.add_to_group(beacon);
+        }
+    }
+
+    fn pair_key(id_a: &str, id_b: &str) -> (String, String) {
+        if id_a < id_b {
+            (id_a.to_string(), id_b.to_string())
+        } else {
+            (id_b.to_string(), id_a.to_string())
+        }
+    }
+
+    /// Forgets the source id, e.g. when the tracker reports it as lost. The logical aircraft goes with its last source id.
+    pub fn remove(&mut self, id: &str) {
+        self.latest.remove(id);
+        self.index.remove(id);
+        self.colocations.retain(|(a, b), _| a != id && b != id);
+
+        let root = self.root(id);
+        let all_gone = match self.groups.get(&root) {
+            Some(group) => group.source_ids.iter().all(|s| !self.latest.contains_key(s)),
+            None => !self.latest.contains_key(id),
+        };
+        if !all_gone {
+            return;
+        }
+
+        self.groups.remove(&root);
+        let members = self.members(&root);
+        for member in members.iter() {
+            self.parent.remove(member);
+        }
+
+        // the explicit links of the ids stay:
+        self.links.retain(|(a, b), kind| *kind == LinkKind::Explicit || !(members.contains(a) || members.contains(b)));
+        let links: Vec<(String, String)> = self.links.keys()
+            .filter(|(a, b)| members.contains(a) || members.contains(b))
+            .cloned()
+            .collect();
+        for (a, b) in links {
+            self.union(&a, &b);
+        }
+    }
+}
+
+impl Default for IdentityResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for IdentityResolver {
+    fn notify(&mut self, beacon: AircraftBeacon) {
+        self.ingest(beacon);
+    }
+}
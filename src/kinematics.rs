@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::data_structures::{AircraftBeacon, Observer, DEG_PER_SEC_PER_ROT, MIN_TRACK_DISTANCE};
+use crate::geodesy::{haversine_distance, initial_bearing};
+
+
+const MAX_FIX_INTERVAL: i64 = 30;       // [s] older previous fix is not used for the derivation
+const MIN_MOVING_SPEED: f64 = 10.0;     // [km/h] a missing speed/course is only filled in above this
+const MAX_SPEED_DIFF: f64 = 20.0;       // [km/h] received speed off by more (and by more than half) is implausible
+const MAX_CLIMB_RATE_DIFF: f64 = 5.0;   // [m/s] received climb rate off by more is implausible
+const MAX_COURSE_DIFF: f64 = 45.0;      // [deg] received course off by more is implausible
+const MAX_TURN_RATE_DIFF: f64 = 10.0;   // [deg/s] received turn rate off by more is implausible
+const MAX_SPEED: f64 = 1500.0;          // [km/h] faster derived speed = bad fix, nothing derived from the move to it
+
+/// Signed difference a - b of the angles in <-180, 180) [deg].
+fn angle_diff(a: f64, b: f64) -> f64 {
+    (a - b + 540.0).rem_euclid(360.0) - 180.0
+}
+
+struct LastFix {
+    beacon: AircraftBeacon,
+    track: Option<f64>,         // [deg] derived from the move to this fix
+    speed: Option<f64>,         // [km/h] derived from the move to this fix
+}
+
+/// Fills in the kinematic values missing in the beacons (e.g. SafeSky sends no turn rate) or received
+/// implausible, computed from the successive fixes of each aircraft: track, ground speed, vertical speed, turn rate and
+/// acceleration. Which values were derived is marked in beacon.derived.
+pub struct KinematicsEstimator {
+    last_fixes: HashMap<String, LastFix>,
+    beacon_listener: Option<Rc<RefCell<dyn Observer<AircraftBeacon>>>>,
+}
+
+impl KinematicsEstimator {
+    pub fn new() -> Self {
+        Self { last_fixes: HashMap::new(), beacon_listener: None }
+    }
+
+    pub fn set_beacon_listener(&mut self, listener: impl Observer<AircraftBeacon> + 'static) {
+        self.beacon_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    /// Derives what is missing in the beacon from the previous fix of the aircraft.
+    pub fn process(&mut self, beacon: &mut AircraftBeacon) {
+        let id = beacon.callsign();
+        let mut track = None;
+        let mut speed = None;
+
+        if let Some(last) = self.last_fixes.get(&id) {
+            let dt = beacon.ts - last.beacon.ts;
+            if dt <= 0 {
+                return;     // duplicate or out of order
+            }
+
+            if dt <= MAX_FIX_INTERVAL {
+                let dt = dt as f64;
+                let distance = haversine_distance(last.beacon.lat, last.beacon.lon, beacon.lat, beacon.lon);
+                let derived_speed = distance / dt * 3.6;
+                // the fix is still recorded so that a single bad one does not stop the derivation for the aircraft:
+                if derived_speed <= MAX_SPEED {
+                    speed = Some(derived_speed);
+                    if distance >= MIN_TRACK_DISTANCE {
+                        track = Some(initial_bearing(last.beacon.lat, last.beacon.lon, beacon.lat, beacon.lon));
+                    }
+
+                    let received_speed = beacon.speed as f64;
+                    let speed_missing = beacon.speed == 0 && derived_speed >= MIN_MOVING_SPEED;
+                    let speed_implausible = (received_speed - derived_speed).abs() > MAX_SPEED_DIFF.max(derived_speed / 2.0);
+                    if speed_missing || speed_implausible {
+                        beacon.speed = derived_speed.round() as u32;
+                        beacon.derived.speed = true;
+                    }
+
+                    let turn = track.zip(last.track).map(|(track, last_track)| angle_diff(track, last_track));
+                    if let Some(track) = track {
+                        // in a turn the course at the fix is ahead of the track to it by about half the turn:
+                        let course = track + turn.unwrap_or(0.0) / 2.0;
+                        let course_implausible = beacon.course.map(|c| angle_diff(c as f64, course).abs() > MAX_COURSE_DIFF).unwrap_or(true);
+                        if course_implausible && derived_speed >= MIN_MOVING_SPEED {
+                            beacon.course = Some((course.round() as i64).rem_euclid(360) as u64);
+                            beacon.derived.course = true;
+                        }
+                    }
+
+                    let derived_climb_rate = (beacon.altitude - last.beacon.altitude) as f64 / dt;
+                    if beacon.climb_rate.map(|c| (c - derived_climb_rate).abs() > MAX_CLIMB_RATE_DIFF).unwrap_or(true) {
+                        beacon.climb_rate = Some(derived_climb_rate);
+                        beacon.derived.climb_rate = true;
+                    }
+
+                    if let Some(turn) = turn {
+                        let derived_turn_rate = turn / dt;     // [deg/s]
+                        if beacon.turn_rate.map(|r| (r * DEG_PER_SEC_PER_ROT - derived_turn_rate).abs() > MAX_TURN_RATE_DIFF).unwrap_or(true) {
+                            beacon.turn_rate = Some(derived_turn_rate / DEG_PER_SEC_PER_ROT);
+                            beacon.derived.turn_rate = true;
+                        }
+                    }
+
+                    if let Some(last_speed) = last.speed {
+                        beacon.acceleration = Some((derived_speed - last_speed) / 3.6 / dt);
+                        beacon.derived.acceleration = true;
+                    }
+                }
+            }
+        }
+
+        self.last_fixes.insert(id, LastFix { beacon: beacon.clone(), track, speed });
+    }
+
+    /// Forgets the aircraft, e.g. when the tracker reports it as lost.
+    pub fn remove(&mut self, id: &str) {
+        self.last_fixes.remove(id);
+    }
+}
+
+impl Default for KinematicsEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for KinematicsEstimator {
+    fn notify(&mut self, mut beacon: AircraftBeacon) {
+        self.process(&mut beacon);
+
+        if let Some(listener) = self.beacon_listener.as_mut() {
+            listener.borrow_mut().notify(beacon);
+        }
+    }
+}
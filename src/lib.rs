@@ -10,14 +10,45 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 pub mod utils;
+pub mod geodesy;
 use crate::utils::{now, from_caps, from_caps_float, from_caps_int};
 mod configuration;
-mod aprs_server_connection;
+pub mod aprs_server_connection;
 pub mod data_structures;
+pub mod line_source;
+pub mod replay_source;
+pub mod feed_recorder;
+pub mod aprs_filter;
+pub mod relay_server;
+pub mod aircraft_tracker;
+pub mod spatial_index;
+pub mod deduplicator;
+pub mod identity_resolver;
+pub mod flight_detector;
+pub mod airfields;
+pub mod logbook;
+pub mod terrain;
+pub mod geoid;
+pub mod device_database;
+pub mod privacy;
+pub mod kinematics;
+pub mod track_filter;
+pub mod validation;
+pub mod thermals;
+pub mod wind;
+pub mod gaggles;
+pub mod conflicts;
+#[cfg(feature = "test-support")]
+pub mod fake_aprs_server;
 
 use crate::configuration::{AIRCRAFT_REGEX1, AIRCRAFT_REGEX2, AIRCRAFT_REGEX3, AIRCRAFT_REGEX4, SKY_REGEX, NEMO_REGEX, SERVER_ADDR};
 use self::aprs_server_connection::AprsServerConnection;
 use self::data_structures::{AddressType, AircraftBeacon, AircraftType, Observer};
+use self::line_source::LineSource;
+use self::terrain::ElevationProvider;
+use self::geoid::GeoidCorrection;
+use self::privacy::PrivacyFilter;
+use self::validation::BeaconValidator;
 
 
 //#[derive(Clone)]
@@ -28,6 +59,11 @@ pub struct MyLineListener {
     aircraft_re2: Regex,
     aircraft_re3: Regex,
     aircraft_re4: Regex,
+    reference_time: Option<DateTime<Utc>>,
+    elevation_provider: Option<Box<dyn ElevationProvider>>,
+    geoid_correction: Option<GeoidCorrection>,
+    privacy_filter: Option<PrivacyFilter>,
+    validator: Option<BeaconValidator>,
 }
 
 impl MyLineListener {
@@ -39,17 +75,63 @@ impl MyLineListener {
             aircraft_re2: Regex::new(AIRCRAFT_REGEX2).unwrap(),
             aircraft_re3: Regex::new(AIRCRAFT_REGEX3).unwrap(),
             aircraft_re4: Regex::new(AIRCRAFT_REGEX4).unwrap(),
+            reference_time: None,
+            elevation_provider: None,
+            geoid_correction: None,
+            privacy_filter: None,
+            validator: None,
+        }
+    }
+
+    /// Sets the clock the beacon rx_time is completed with into a full timestamp, e.g. the time of a replayed log.
+    /// None (default) means the current system time.
+    pub fn set_reference_time(&mut self, reference_time: Option<DateTime<Utc>>) {
+        self.reference_time = reference_time;
+    }
+
+    fn reference_time(&self) -> DateTime<Utc> {
+        self.reference_time.unwrap_or_else(Utc::now)
+    }
+
+    /// With a terrain elevation provider set the agl of every beacon is computed from its altitude.
+    pub fn set_elevation_provider(&mut self, provider: impl ElevationProvider + 'static) {
+        self.elevation_provider = Some(Box::new(provider));
+    }
+
+    /// Converts ellipsoidal altitudes of the sources configured so to MSL.
+    pub fn set_geoid_correction(&mut self, geoid_correction: GeoidCorrection) {
+        self.geoid_correction = Some(geoid_correction);
+    }
+
+    /// Privacy policy applied before the beacons reach the listeners.
+    pub fn set_privacy_filter(&mut self, privacy_filter: Option<PrivacyFilter>) {
+        self.privacy_filter = privacy_filter;
+    }
+
+    /// Validation of the parsed beacons (timestamps checked against the reference time).
+    pub fn set_validator(&mut self, validator: Option<BeaconValidator>) {
+        self.validator = validator;
+    }
+
+    fn fill_altitudes(&mut self, beacon: &mut AircraftBeacon) {
+        if let Some(geoid_correction) = self.geoid_correction.as_ref() {
+            geoid_correction.correct(beacon);
+        }
+
+        if let Some(provider) = self.elevation_provider.as_mut() {
+            if let Some(elevation) = provider.elevation(beacon.lat, beacon.lon) {
+                beacon.set_agl(beacon.altitude_msl - elevation.round() as i32);
+            }
         }
     }
 
     //rx_time: HHMMSS
-    fn rx_time_to_utc_ts(rx_time: &str) -> Result<Option<i64>, std::num::ParseIntError> {
+    fn rx_time_to_utc_ts(rx_time: &str, reference_time: DateTime<Utc>) -> Result<Option<i64>, std::num::ParseIntError> {
 
         let hour = rx_time[0..2].parse::<u32>()?;
         let min = rx_time[2..4].parse::<u32>()?;
         let sec = rx_time[4..].parse::<u32>()?;
 
-        // TODO FIX somehow: here originate 'TS from the future' when beacon comes with local time originating from -timezones after UTC midnight
         // let utc: DateTime<Utc> = Utc::now();    
         // let utc = utc.with_hour(hour)
         //     .unwrap()
@@ -59,7 +141,7 @@ impl MyLineListener {
         //     .unwrap()
         //     .with_nanosecond(0)
         //     .unwrap();
-        let mut utc: DateTime<Utc> = Utc::now();  
+        let mut utc: DateTime<Utc> = reference_time;
         match utc.with_hour(hour) {
             Some(val) => utc = val,
             None => return Ok(None),
@@ -74,20 +156,33 @@ impl MyLineListener {
         };
         utc = utc.with_nanosecond(0).unwrap();
 
+        // the beacon time is on the day which puts it closest to the reference time (rollover around midnight):
+        let diff = utc.timestamp() - reference_time.timestamp();
+        if diff > 12 * 3600 {
+            utc -= chrono::Duration::days(1);
+        } else if diff < -12 * 3600 {
+            utc += chrono::Duration::days(1);
+        }
+
         Ok(Some(utc.timestamp() as i64))
     }
 
-    fn parse_signal_strength(line: &str) -> f64 {
-        if !line.contains("dB") {
-            return 0_f64;
-        }
+    /// None when the line carries no signal strength.
+    fn parse_signal_strength(line: &str) -> Option<f64> {
+        let temp = &line[..line.rfind("dB")?];
+        let temp = &temp[temp.rfind(' ')? + 1..];
 
-        let temp = &line[..line.rfind("dB").unwrap()];
-        let temp = &temp[temp.rfind(" ").unwrap()+1..];
+        temp.parse::<f64>().ok()
+    }
 
-        let signal_strength = temp.parse::<f64>().unwrap_or(0_f64);
+    /// Receiving station from the APRS path, e.g. 'LKKA' from 'FLRDDA5BA>APRS,qAS,LKKA:/...'
+    fn parse_receiver(line: &str) -> String {
+        let header = match line.find(':') {
+            Some(pos) => &line[..pos],
+            None => return String::new(),
+        };
 
-        signal_strength
+        header.rsplit(',').next().unwrap_or("").to_string()
     }
 
     pub fn parse_beacon_line(&self, line: &str) -> Option<AircraftBeacon> {
@@ -108,7 +203,7 @@ impl MyLineListener {
 
         if !SUPPORTED_BEACONS.contains(prefix) {
             if line.contains("OGNEMO") {
-                return MyLineListener::parse_nemo_beacon(line);
+                return MyLineListener::parse_nemo_beacon(line, self.reference_time());
 
             } else {
                 // println!("Unsupported beacon: {}", line);
@@ -118,7 +213,7 @@ impl MyLineListener {
 
         let beacon;
         if prefix == "SKY" {
-            beacon = MyLineListener::parse_sky_beacon(line);
+            beacon = MyLineListener::parse_sky_beacon(line, self.reference_time());
         } else {
             beacon = self.parse_aircraft_beacon(line);
         }
@@ -126,7 +221,7 @@ impl MyLineListener {
         beacon
     }
 
-    fn parse_sky_beacon(line: &str) -> Option<AircraftBeacon> {
+    fn parse_sky_beacon(line: &str, reference_time: DateTime<Utc>) -> Option<AircraftBeacon> {
         lazy_static! {
             static ref SKY_RE: Regex = Regex::new(SKY_REGEX).unwrap();
         }
@@ -147,14 +242,14 @@ impl MyLineListener {
         let lon = from_caps(&caps, 6, "0");
         let lon_letter = from_caps(&caps, 7, "E");
         // let aprs_symbol = from_caps(&caps, 8, "");
-        let course: u64 = from_caps_int(&caps, 9, 0) as u64;
+        let course: Option<u64> = Some(from_caps_int(&caps, 9, 0) as u64).filter(|c| *c != 0);    // 000 = not known, 360 = north
         let speed: u64 = from_caps_int(&caps, 10, 0) as u64; // [kt]
         let altitude: f64 = from_caps_float(&caps, 11, 0_f64); // [ft]
         let flags: u8 = u8::from_str_radix(from_caps(&caps, 12, "0"), 16).unwrap_or(0);
         let addr2 = from_caps(&caps, 13, "0").to_string();
         let vertical_speed: f64 = from_caps_float(&caps, 14, 0_f64); // [fpm]
 
-        let ts = match Self::rx_time_to_utc_ts(rx_time) {
+        let ts = match Self::rx_time_to_utc_ts(rx_time, reference_time) {
             Ok(val) => match val {
                 Some(val) => val,
                 None => return None,
@@ -181,11 +276,11 @@ impl MyLineListener {
         let aircraft_type: AircraftType = AircraftType::from(flags >> 2 & 0x0F);
         let address_type: AddressType = AddressType::SafeSky;
 
-        let vertical_speed = vertical_speed * 0.00508; // ft per min -> meters/s
+        let vertical_speed = Some(vertical_speed * 0.00508); // ft per min -> meters/s
         // convert altitude in FL to meters:
         let altitude = (altitude * 0.3048).round() as i32;
 
-        let beacon = AircraftBeacon::new(
+        let mut beacon = AircraftBeacon::new(
             ts,
             prefix,
             addr2,
@@ -193,17 +288,18 @@ impl MyLineListener {
             lat,
             lon,
             altitude,
-            0,
+            None,
             course,
             speed,
             vertical_speed,
-            0.0,  // not known from the line 
+            None,  // not known from the line 
             stealth,
             do_not_track,
             aircraft_type,
             "".to_string(),
             Self::parse_signal_strength(&line),
         );
+        beacon.receiver = Self::parse_receiver(line);
 
         Some(beacon)
     }
@@ -211,7 +307,7 @@ impl MyLineListener {
     /**
      * OGNEMO beacons usually contain ICA beacons.
      */
-    fn parse_nemo_beacon(line: &str) -> Option<AircraftBeacon> {
+    fn parse_nemo_beacon(line: &str, reference_time: DateTime<Utc>) -> Option<AircraftBeacon> {
         lazy_static! {
             static ref NEMO_RE: Regex = Regex::new(NEMO_REGEX).unwrap();
         }
@@ -231,15 +327,15 @@ impl MyLineListener {
         let lon = from_caps(&caps, 5, "0");
         let lon_letter = from_caps(&caps, 6, "E");
         // let aprs_symbol = from_caps(&caps, 7, "");
-        let course: u64 = from_caps_int(&caps, 8, 0) as u64;
+        let course: Option<u64> = Some(from_caps_int(&caps, 8, 0) as u64).filter(|c| *c != 0);    // 000 = not known, 360 = north
         let speed: u64 = from_caps_int(&caps, 9, 0) as u64; // [kt]
         let altitude: f64 = from_caps_float(&caps, 10, 0_f64); // [ft]
         let flags: u8 = u8::from_str_radix(from_caps(&caps, 11, "0"), 16).unwrap_or(0);
         let addr2 = from_caps(&caps, 12, "0").to_string();
         let vertical_speed: f64 = from_caps_float(&caps, 13, 0_f64); // [fpm]
-        let angular_speed: f64 = from_caps_float(&caps, 14, 0_f64);
+        let angular_speed: Option<f64> = Some(from_caps_float(&caps, 14, 0_f64));
 
-        let ts = match Self::rx_time_to_utc_ts(rx_time) {
+        let ts = match Self::rx_time_to_utc_ts(rx_time, reference_time) {
             Ok(val) => match val {
                 Some(val) => val,
                 None => return None,
@@ -275,11 +371,11 @@ impl MyLineListener {
             _ => prefix = "NEMO".to_string()
         };
 
-        let vertical_speed = vertical_speed * 0.00508; // ft per min -> meters/s
+        let vertical_speed = Some(vertical_speed * 0.00508); // ft per min -> meters/s
         // convert altitude in FL to meters:
         let altitude = (altitude * 0.3048).round() as i32;
 
-        let beacon = AircraftBeacon::new(
+        let mut beacon = AircraftBeacon::new(
             ts,
             prefix,
             addr2,
@@ -287,7 +383,7 @@ impl MyLineListener {
             lat,
             lon,
             altitude,
-            0,
+            None,
             course,
             speed,
             vertical_speed,
@@ -298,11 +394,13 @@ impl MyLineListener {
             registration,
             Self::parse_signal_strength(&line),
         );
+        beacon.receiver = Self::parse_receiver(line);
 
         Some(beacon)
     }
 
     fn parse_aircraft_beacon(&self, line: &str) -> Option<AircraftBeacon> {
+        let reference_time = self.reference_time();
         // there are two very similar lines where one does not contain the 'rot' part:
         let mut regex = &self.aircraft_re4;
         // let mut regex_with_id = false;
@@ -342,13 +440,13 @@ impl MyLineListener {
         let lon = from_caps(&caps, 6, "0");
         let lon_letter = from_caps(&caps, 7, "E");
         // let aprs_symbol = from_caps(&caps, 8, "");
-        let course: u64 = from_caps_int(&caps, 9, 0) as u64;
+        let course: Option<u64> = Some(from_caps_int(&caps, 9, 0) as u64).filter(|c| *c != 0);    // 000 = not known, 360 = north
         let speed: u64 = from_caps_int(&caps, 10, 0) as u64; // [kt]
         let altitude: f64 = from_caps_float(&caps, 11, 0_f64); // [ft]
         let flags: u8 = u8::from_str_radix(from_caps(&caps, 12, "0"), 16).unwrap_or(0);
         // let addr2 = if regex_with_id {from_caps(&caps, 13, "").to_string()} else {"".to_string()};
-        let vertical_speed: f64 = if regex_with_fpm {from_caps_float(&caps, 14, 0_f64)} else {0_f64}; // [fpm]
-        let angular_speed: f64 = if regex_with_rot {from_caps_float(&caps, 15, 0_f64)} else {0_f64};
+        let vertical_speed: Option<f64> = if regex_with_fpm {Some(from_caps_float(&caps, 14, 0_f64))} else {None}; // [fpm]
+        let angular_speed: Option<f64> = if regex_with_rot {Some(from_caps_float(&caps, 15, 0_f64))} else {None};
         // let flight_level: f64 = from_caps_float(&caps, 16, 0_f64);     // [flight level ~ hundrets of ft]
         // let re = Regex::new(AIRCRAFT_REGEX_ALT).unwrap();
         // let flight_level: i32 = match re.captures(line) {
@@ -359,7 +457,7 @@ impl MyLineListener {
         //     None => 0,
         // };
 
-        let ts = match Self::rx_time_to_utc_ts(rx_time) {
+        let ts = match Self::rx_time_to_utc_ts(rx_time, reference_time) {
             Ok(val) => match val {
                 Some(val) => val,
                 None => return None,
@@ -405,11 +503,11 @@ impl MyLineListener {
             }
         }
 
-        let vertical_speed = vertical_speed * 0.00508; // ft per min -> meters/s
+        let vertical_speed = vertical_speed.map(|v| v * 0.00508); // ft per min -> meters/s
         // convert altitude in FL to meters:
         let altitude = (altitude * 0.3048).round() as i32;
 
-        let beacon = AircraftBeacon::new(
+        let mut beacon = AircraftBeacon::new(
             ts,
             prefix,
             addr1,
@@ -417,7 +515,7 @@ impl MyLineListener {
             lat,
             lon,
             altitude,
-            0,
+            None,
             course,
             speed,
             vertical_speed,
@@ -428,6 +526,7 @@ impl MyLineListener {
             "".to_string(),
             Self::parse_signal_strength(&line),
         );
+        beacon.receiver = Self::parse_receiver(line);
 
         Some(beacon)
     }
@@ -451,14 +550,28 @@ impl Observer<String> for MyLineListener {
         let beacon_opt = self.parse_beacon_line(&line);
         
         if beacon_opt.is_some() {
-            let beacon = beacon_opt.unwrap();
+            let mut beacon = beacon_opt.unwrap();
+            self.fill_altitudes(&mut beacon);
 
-            if self.beacon_listener.is_some() {
-                self.beacon_listener.as_mut().unwrap().borrow_mut().notify(beacon.clone());
+            if let Some(validator) = self.validator.as_ref() {
+                if !validator.process(&mut beacon, Some(self.reference_time().timestamp())) {
+                    return;
+                }
             }
 
-            if self.beacon_listener_fn.is_some() {
-                (self.beacon_listener_fn.as_mut().unwrap())(beacon);
+            let beacons = match self.privacy_filter.as_mut() {
+                Some(privacy_filter) => privacy_filter.process(beacon),
+                None => vec![beacon],
+            };
+
+            for beacon in beacons {
+                if self.beacon_listener.is_some() {
+                    self.beacon_listener.as_mut().unwrap().borrow_mut().notify(beacon.clone());
+                }
+
+                if self.beacon_listener_fn.is_some() {
+                    (self.beacon_listener_fn.as_mut().unwrap())(beacon);
+                }
             }
         }
     }
@@ -466,39 +579,56 @@ impl Observer<String> for MyLineListener {
 
 pub struct OgnClient {
     do_run: bool,
-    server: AprsServerConnection,
+    source: Box<dyn LineSource>,
     line_listener: Rc<RefCell<MyLineListener>>,
+    raw_line_listener: Option<Rc<RefCell<dyn Observer<String>>>>,
 }
 
 impl OgnClient {
     pub fn new(username: &str) -> std::io::Result<Self> {
-        // let line_listener = MyLineListener::new();
-        // let line_listener = RefCell::new(MyLineListener::new());
-        let line_listener = Rc::new(RefCell::new(MyLineListener::new()));
+        let server = AprsServerConnection::new(SERVER_ADDR, username)?; 
 
-        let mut server = AprsServerConnection::new(SERVER_ADDR, username)?; 
-        server.set_line_listener(Rc::clone(&line_listener));    // this finally clones the fucking reference, not the content!
+        Ok(Self::with_source(server))
+    }
 
-        Ok(Self {
+    /// Creates a client reading lines from any source, e.g. a recorded file or an in-memory vector.
+    pub fn with_source(source: impl LineSource + 'static) -> Self {
+        Self {
             do_run: true,
-            server: server,
-            line_listener: line_listener,
-        })
+            source: Box::new(source),
+            line_listener: Rc::new(RefCell::new(MyLineListener::new())),
+            raw_line_listener: None,
+        }
     }
 
     /// Needs to be set before connect()!
     pub fn set_aprs_filter(&mut self, lat: f64, lon: f64, range: u32) {
-        self.server.set_aprs_filter(lat, lon, range);
+        self.source.set_aprs_filter(lat, lon, range);
     }
 
     pub fn connect(& mut self) {
-        self.server.connect();
-        
+        self.source.connect();
     }
 
+    /// Processes lines until stop() is called or the source runs out of data.
     pub fn do_loop(&mut self) {
         while self.do_run {
-            self.server.read();
+            match self.source.next_line() {
+                Some(line) => {
+                    if let Some(raw_line_listener) = self.raw_line_listener.as_ref() {
+                        raw_line_listener.borrow_mut().notify(line.clone());
+                    }
+
+                    let mut line_listener = self.line_listener.borrow_mut();
+                    line_listener.set_reference_time(self.source.reference_time());
+                    line_listener.notify(line);
+                },
+                None => {
+                    if self.source.is_eof() {
+                        break;
+                    }
+                },
+            }
         }
     }
 
@@ -506,6 +636,29 @@ impl OgnClient {
         self.do_run = false;
     }
 
+    /// Listener of every raw line read from the source, e.g. the FeedRecorder.
+    pub fn set_raw_line_listener(&mut self, listener: Rc<RefCell<impl Observer<String> + 'static>>) {
+        self.raw_line_listener = Some(listener);
+    }
+
+    /// Terrain elevation model to compute the agl of the beacons from, e.g. a DemElevationProvider.
+    pub fn set_elevation_provider(&mut self, provider: impl ElevationProvider + 'static) {
+        self.line_listener.borrow_mut().set_elevation_provider(provider);
+    }
+
+    pub fn set_geoid_correction(&mut self, geoid_correction: GeoidCorrection) {
+        self.line_listener.borrow_mut().set_geoid_correction(geoid_correction);
+    }
+
+    /// Privacy policy (drop no-track, anonymise no-identify aircraft, ..) applied before the beacons reach the listeners.
+    pub fn set_privacy_filter(&mut self, privacy_filter: Option<PrivacyFilter>) {
+        self.line_listener.borrow_mut().set_privacy_filter(privacy_filter);
+    }
+
+    pub fn set_validator(&mut self, validator: Option<BeaconValidator>) {
+        self.line_listener.borrow_mut().set_validator(validator);
+    }
+
     pub fn set_beacon_listener(&mut self, listener: impl Observer<AircraftBeacon> + 'static) {
         // self.line_listener.as_mut().unwrap().borrow_mut().set_beacon_listener(listener);
         self.line_listener.borrow_mut().set_beacon_listener(listener);
@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Stdin};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use flate2::read::MultiGzDecoder;
+use log::error;
+
+
+/// Source of raw APRS lines consumed by the OgnClient.
+pub trait LineSource {
+    /// Opens the underlying stream (if there is any to be opened).
+    fn connect(&mut self) {}
+
+    /// Returns the next line without the trailing newline.
+    /// None means there is nothing to process right now (reconnect, timeout, end of data).
+    fn next_line(&mut self) -> Option<String>;
+
+    /// True when the source cannot ever provide any more lines.
+    fn is_eof(&self) -> bool {
+        false
+    }
+
+    /// Time of the most recently returned line if the source knows it better than the system clock (e.g. a replayed log).
+    fn reference_time(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    /// Sets APRS filter to receive beacons from the desired area only. Meaningful for the APRS server connection only.
+    fn set_aprs_filter(&mut self, _lat: f64, _lon: f64, _range: u32) {}
+}
+
+
+/// Reads lines from any buffered reader until EOF.
+pub struct ReaderSource<R: BufRead> {
+    reader: R,
+    eof: bool,
+}
+
+impl<R: BufRead> ReaderSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, eof: false }
+    }
+}
+
+impl<R: BufRead> LineSource for ReaderSource<R> {
+    fn next_line(&mut self) -> Option<String> {
+        if self.eof {
+            return None;
+        }
+
+        let mut buf = Vec::new();
+        match self.reader.read_until(b'\n', &mut buf) {
+            Ok(0) => {  // EOF
+                self.eof = true;
+                None
+            },
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&buf);
+                Some(line.trim_end_matches(['\r', '\n']).to_string())
+            },
+            Err(e) => {
+                error!("when reading line: '{:?}' - {}", e.kind(), e);
+                self.eof = true;
+                None
+            },
+        }
+    }
+
+    fn is_eof(&self) -> bool {
+        self.eof
+    }
+}
+
+
+/// Plain text or gzip-compressed file with one APRS line per line.
+pub type FileSource = ReaderSource<BufReader<Box<dyn Read>>>;
+
+impl FileSource {
+    /// Opens the file; gzip compression is detected from the file content, not from the extension.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let is_gzip = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+
+        let inner: Box<dyn Read> = if is_gzip {
+            Box::new(MultiGzDecoder::new(reader))   // multi-member files come from appending to an existing archive
+        } else {
+            Box::new(reader)
+        };
+
+        Ok(ReaderSource::new(BufReader::new(inner)))
+    }
+}
+
+
+/// Standard input, e.g. `zcat archive.gz | my_app`.
+pub type StdinSource = ReaderSource<BufReader<Stdin>>;
+
+impl StdinSource {
+    pub fn stdin() -> Self {
+        ReaderSource::new(BufReader::new(io::stdin()))
+    }
+}
+
+
+/// In-memory lines, handy for tests of the listener code.
+pub struct VecSource {
+    lines: VecDeque<String>,
+}
+
+impl VecSource {
+    pub fn new(lines: Vec<String>) -> Self {
+        Self { lines: lines.into() }
+    }
+
+    pub fn push(&mut self, line: &str) {
+        self.lines.push_back(line.to_string());
+    }
+}
+
+impl LineSource for VecSource {
+    fn next_line(&mut self) -> Option<String> {
+        self.lines.pop_front()
+    }
+
+    fn is_eof(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
@@ -0,0 +1,513 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use serde_json::json;
+
+use crate::airfields::AirfieldDatabase;
+use crate::data_structures::{AircraftBeacon, AircraftType, Observer};
+use crate::flight_detector::{FlightEvent, FlightPhaseDetector};
+use crate::geodesy::haversine_distance;
+use crate::utils::quote_csv_field;
+
+
+const AIRFIELD_RADIUS: f64 = 3000.0;    // [m] take-off / landing this close belongs to the airfield
+const RECENT_TRACK_LEN: i64 = 5 * 60;   // [s]
+const TOW_PAIRING_TIME: i64 = 60;       // [s] max difference of the glider's and the tow plane's take-off
+const TOW_PAIRING_DISTANCE: f64 = 500.0;    // [m] max distance of their take-off points
+const LAUNCH_DECISION_DELAY: i64 = 90;  // [s] after take-off without a tow plane the launch is classified from the climb
+const RELEASE_DISTANCE: f64 = 300.0;    // [m] glider this far from its tow plane has released
+const RELEASE_HEIGHT_DIFF: i32 = 100;   // [m] .. or this much higher/lower
+const RELEASE_CONFIRMATIONS: u32 = 2;   // consecutive fixes apart needed to call it a release
+const MAX_FIX_SKEW: i64 = 2;            // [s] the partner's fix compared with must be this close in time ..
+const MAX_INTERPOLATION_GAP: i64 = 10;  // [s] .. or interpolated between its fixes at most this far apart
+const PRUNE_INTERVAL: i64 = 60;         // [s]
+const MAX_TOW_DURATION: i64 = 20 * 60;  // [s]
+const MIN_TOW_HEIGHT: i32 = 100;        // [m] separated lower than this they did not climb together - not a tow
+const WINCH_MIN_GAIN: i32 = 150;        // [m] height gained in the launch
+const WINCH_MIN_CLIMB: f64 = 6.0;       // [m/s] peak climb rate in the launch
+const WINCH_LAUNCH_TIME: i64 = 90;      // [s] time from the take-off to the top of the launch
+const OPEN_ENTRY_TIMEOUT: i64 = 24 * 3600;      // [s] an entry without landing is closed this long after the take-off
+const DEFAULT_RETENTION: i64 = 7 * 24 * 3600;   // [s] entries taken off longer ago are dropped
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LaunchMethod {
+    Unknown,
+    Aerotow,
+    /// The tow plane's own entry.
+    Towing,
+    Winch,
+    SelfLaunch,
+}
+
+impl LaunchMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LaunchMethod::Unknown => "unknown",
+            LaunchMethod::Aerotow => "aerotow",
+            LaunchMethod::Towing => "towing",
+            LaunchMethod::Winch => "winch",
+            LaunchMethod::SelfLaunch => "self-launch",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogbookEntry {
+    pub date: NaiveDate,                // UTC date of the take-off
+    pub aircraft_id: String,
+    pub registration: String,
+    pub aircraft_type: AircraftType,
+    pub takeoff_airfield: Option<String>,
+    pub takeoff_ts: i64,
+    pub launch_method: LaunchMethod,
+    pub tow_partner: Option<String>,    // tow plane of the glider or glider of the tow plane
+    pub release_ts: Option<i64>,
+    pub release_height: Option<i32>,    // [m] above the take-off point
+    pub landing_airfield: Option<String>,
+    pub landing_ts: Option<i64>,
+    pub duration: Option<i64>,          // [s]
+}
+
+impl LogbookEntry {
+    pub const CSV_HEADER: &'static str = "date;aircraft_id;registration;aircraft_type;takeoff_airfield;takeoff_ts;launch_method;tow_partner;release_ts;release_height;landing_airfield;landing_ts;duration";
+
+    /// Text fields (registrations, airfield codes) containing ';' are quoted.
+    pub fn to_csv_line(&self) -> String {
+        let opt = |val: Option<i64>| val.map(|v| v.to_string()).unwrap_or_default();
+        let text = |val: &str| quote_csv_field(val, ';');
+
+        format!("{};{};{};{};{};{};{};{};{};{};{};{};{}",
+            self.date,
+            text(&self.aircraft_id),
+            text(&self.registration),
+            self.aircraft_type.value(),
+            text(self.takeoff_airfield.as_deref().unwrap_or_default()),
+            self.takeoff_ts,
+            self.launch_method.as_str(),
+            text(self.tow_partner.as_deref().unwrap_or_default()),
+            opt(self.release_ts),
+            opt(self.release_height.map(|h| h as i64)),
+            text(self.landing_airfield.as_deref().unwrap_or_default()),
+            opt(self.landing_ts),
+            opt(self.duration),
+        )
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "date": self.date.to_string(),
+            "aircraft_id": self.aircraft_id,
+            "registration": self.registration,
+            "acft_type": self.aircraft_type.value(),
+            "takeoff_airfield": self.takeoff_airfield,
+            "takeoff_ts": self.takeoff_ts,
+            "launch_method": self.launch_method.as_str(),
+            "tow_partner": self.tow_partner,
+            "release_ts": self.release_ts,
+            "release_height": self.release_height,
+            "landing_airfield": self.landing_airfield,
+            "landing_ts": self.landing_ts,
+            "duration": self.duration,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum LaunchState {
+    /// Waiting for a tow plane or for the climb profile.
+    Pending { takeoff: AircraftBeacon },
+    /// `apart` counts the consecutive fixes away from the partner, `release` is the first of them.
+    InTow { takeoff: AircraftBeacon, partner: String, apart: u32, release: Option<Box<AircraftBeacon>> },
+}
+
+/// Collects the events of the internal flight phase detector.
+struct EventSink(Rc<RefCell<Vec<FlightEvent>>>);
+
+impl Observer<FlightEvent> for EventSink {
+    fn notify(&mut self, event: FlightEvent) {
+        self.0.borrow_mut().push(event);
+    }
+}
+
+fn is_glider(aircraft_type: &AircraftType) -> bool {
+    *aircraft_type == AircraftType::Glider
+}
+
+fn can_tow(aircraft_type: &AircraftType) -> bool {
+    *aircraft_type == AircraftType::TowPlane || *aircraft_type == AircraftType::PistonPlane
+}
+
+fn is_powered(aircraft_type: &AircraftType) -> bool {
+    matches!(aircraft_type, AircraftType::TowPlane | AircraftType::PistonPlane | AircraftType::JetPlane | AircraftType::DropPlane
+        | AircraftType::Helicopter | AircraftType::Airship | AircraftType::Uav)
+}
+
+/// Airfield logbook built from the beacon stream: take-offs & landings, launch method, tow plane pairing
+/// (co-located take-off and parallel climb of a tow plane and a glider), release height and flight duration.
+///
+/// An entry is closed without landing when the aircraft's signal is lost in the air or a day after the take-off.
+/// Entries are kept for the retention time (a week by default, see set_retention()).
+pub struct Logbook {
+    airfields: AirfieldDatabase,
+    detector: FlightPhaseDetector,
+    events: Rc<RefCell<Vec<FlightEvent>>>,
+    recent: HashMap<String, VecDeque<AircraftBeacon>>,
+    entries: Vec<LogbookEntry>,
+    open_entries: HashMap<String, usize>,   // aircraft id -> index of its entry without landing
+    launches: HashMap<String, LaunchState>,
+    retention: i64,     // [s]
+    clock_ts: i64,
+    last_prune_ts: i64,
+}
+
+impl Logbook {
+    pub fn new(airfields: AirfieldDatabase) -> Self {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut detector = FlightPhaseDetector::new();
+        detector.set_event_listener(EventSink(Rc::clone(&events)));
+        detector.set_airfields(airfields.clone());    // the ground for the landings of beacons without the agl
+
+        Self {
+            airfields,
+            detector,
+            events,
+            recent: HashMap::new(),
+            entries: Vec::new(),
+            open_entries: HashMap::new(),
+            launches: HashMap::new(),
+            retention: DEFAULT_RETENTION,
+            clock_ts: 0,
+            last_prune_ts: 0,
+        }
+    }
+
+    /// Entries which took off longer ago than this are dropped [s].
+    pub fn set_retention(&mut self, retention: i64) {
+        self.retention = retention;
+    }
+
+    pub fn ingest(&mut self, beacon: AircraftBeacon) {
+        let id = beacon.callsign();
+        let track = self.recent.entry(id.clone()).or_default();
+        if track.back().map(|b| b.ts >= beacon.ts).unwrap_or(false) {
+            return;
+        }
+        track.push_back(beacon.clone());
+        while track.front().map(|b| b.ts < beacon.ts - RECENT_TRACK_LEN).unwrap_or(false) {
+            track.pop_front();
+        }
+
+        self.detector.ingest(beacon.clone());
+        let events: Vec<FlightEvent> = self.events.borrow_mut().drain(..).collect();
+        for event in events {
+            self.on_flight_event(event);
+        }
+
+        self.update_launch(&id, &beacon);
+
+        if beacon.ts > self.clock_ts {
+            self.clock_ts = beacon.ts;
+        }
+        if self.clock_ts >= self.last_prune_ts + PRUNE_INTERVAL {
+            self.prune(self.clock_ts);
+        }
+    }
+
+    /// Drops the recent tracks of aircraft not heard for the recent track length, closes the entries open for too long
+    /// and drops the entries older than the retention time.
+    fn prune(&mut self, now_ts: i64) {
+        self.last_prune_ts = now_ts;
+        self.recent.retain(|_, track| track.back().map(|b| b.ts >= now_ts - RECENT_TRACK_LEN).unwrap_or(false));
+
+        let timed_out: Vec<String> = self.open_entries.iter()
+            .filter(|(_, idx)| self.entries[**idx].takeoff_ts < now_ts - OPEN_ENTRY_TIMEOUT)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in timed_out {
+            self.close_entry(&id);
+        }
+
+        let cutoff = now_ts - self.retention;
+        if self.entries.iter().any(|e| e.takeoff_ts < cutoff) {
+            // the open entries' indices shift:
+            let open: HashMap<usize, String> = self.open_entries.drain().map(|(id, idx)| (idx, id)).collect();
+            let mut kept = Vec::new();
+            for (idx, entry) in self.entries.drain(..).enumerate() {
+                if entry.takeoff_ts >= cutoff {
+                    if let Some(id) = open.get(&idx) {
+                        self.open_entries.insert(id.clone(), kept.len());
+                    }
+                    kept.push(entry);
+                } else if let Some(id) = open.get(&idx) {
+                    self.launches.remove(id);
+                }
+            }
+            self.entries = kept;
+        }
+    }
+
+    /// Leaves the aircraft's entry without landing.
+    fn close_entry(&mut self, id: &str) {
+        self.open_entries.remove(id);
+        if let Some(LaunchState::InTow { partner, .. }) = self.launches.remove(id) {
+            self.launches.remove(&partner);
+        }
+    }
+
+    /// Position (lat, lon, altitude) of the aircraft at the time from its recent track: a fix at most MAX_FIX_SKEW
+    /// away or interpolated between the fixes around.
+    fn position_at(&self, id: &str, ts: i64) -> Option<(f64, f64, i32)> {
+        let track = self.recent.get(id)?;
+        let after_idx = track.iter().position(|b| b.ts >= ts);
+        let before = match after_idx {
+            Some(0) => None,
+            Some(i) => track.get(i - 1),
+            None => track.back(),
+        };
+        let after = after_idx.and_then(|i| track.get(i));
+
+        match (before, after) {
+            (Some(a), Some(b)) if b.ts - a.ts <= MAX_INTERPOLATION_GAP => {
+                let f = (ts - a.ts) as f64 / (b.ts - a.ts) as f64;
+                Some((a.lat + f * (b.lat - a.lat), a.lon + f * (b.lon - a.lon), a.altitude + (f * (b.altitude - a.altitude) as f64).round() as i32))
+            },
+            (_, Some(b)) if b.ts - ts <= MAX_FIX_SKEW => Some((b.lat, b.lon, b.altitude)),
+            (Some(a), _) if ts - a.ts <= MAX_FIX_SKEW => Some((a.lat, a.lon, a.altitude)),
+            _ => None,
+        }
+    }
+
+    fn airfield_code(&self, beacon: &AircraftBeacon) -> Option<String> {
+        self.airfields.nearest(beacon.lat, beacon.lon, AIRFIELD_RADIUS).map(|(a, _)| a.code.clone())
+    }
+
+    fn on_flight_event(&mut self, event: FlightEvent) {
+        match event {
+            FlightEvent::TakeOff { id, beacon } => {
+                let date = Utc.timestamp_opt(beacon.ts, 0).single().map(|t| t.date_naive()).unwrap_or_default();
+                let entry = LogbookEntry {
+                    date,
+                    aircraft_id: id.clone(),
+                    registration: beacon.registration.clone(),
+                    aircraft_type: beacon.aircraft_type.clone(),
+                    takeoff_airfield: self.airfield_code(&beacon),
+                    takeoff_ts: beacon.ts,
+                    launch_method: LaunchMethod::Unknown,
+                    tow_partner: None,
+                    release_ts: None,
+                    release_height: None,
+                    landing_airfield: None,
+                    landing_ts: None,
+                    duration: None,
+                };
+                self.entries.push(entry);
+                self.open_entries.insert(id.clone(), self.entries.len() - 1);
+                self.launches.insert(id.clone(), LaunchState::Pending { takeoff: beacon });
+
+                self.pair_tow(&id, None);
+            },
+            FlightEvent::Landing { id, beacon, .. } => {
+                let landing_airfield = self.airfield_code(&beacon);
+                if let Some(idx) = self.open_entries.remove(&id) {
+                    let entry = &mut self.entries[idx];
+                    entry.landing_airfield = landing_airfield;
+                    entry.landing_ts = Some(beacon.ts);
+                    entry.duration = Some(beacon.ts - entry.takeoff_ts);
+                }
+                self.launches.remove(&id);
+                self.recent.remove(&id);
+            },
+            FlightEvent::SignalLostInAir { id, .. } => {
+                self.close_entry(&id);
+            },
+            _ => (),
+        }
+    }
+
+    /// Pairs a freshly taken-off aircraft with a pending take-off of a tow plane / glider at the same place and time.
+    /// @param excluded aircraft known not to be the partner
+    fn pair_tow(&mut self, id: &str, excluded: Option<&str>) {
+        let takeoff = match self.launches.get(id) {
+            Some(LaunchState::Pending { takeoff }) => takeoff.clone(),
+            _ => return,
+        };
+
+        let partner = self.launches.iter()
+            .filter(|(other_id, _)| *other_id != id && Some(other_id.as_str()) != excluded)
+            .filter_map(|(other_id, state)| match state {
+                LaunchState::Pending { takeoff: other } => Some((other_id, other)),
+                _ => None,
+            })
+            .find(|(_, other)| {
+                let roles = (is_glider(&takeoff.aircraft_type) && can_tow(&other.aircraft_type))
+                    || (can_tow(&takeoff.aircraft_type) && is_glider(&other.aircraft_type));
+
+                roles
+                    && (other.ts - takeoff.ts).abs() <= TOW_PAIRING_TIME
+                    && haversine_distance(takeoff.lat, takeoff.lon, other.lat, other.lon) <= TOW_PAIRING_DISTANCE
+            })
+            .map(|(other_id, other)| (other_id.clone(), other.clone()));
+
+        if let Some((partner_id, partner_takeoff)) = partner {
+            let (glider_id, tug_id) = if is_glider(&takeoff.aircraft_type) { (id.to_string(), partner_id.clone()) } else { (partner_id.clone(), id.to_string()) };
+
+            self.set_launch(&glider_id, LaunchMethod::Aerotow, Some(&tug_id));
+            self.set_launch(&tug_id, LaunchMethod::Towing, Some(&glider_id));
+            self.launches.insert(id.to_string(), LaunchState::InTow { takeoff, partner: partner_id.clone(), apart: 0, release: None });
+            self.launches.insert(partner_id, LaunchState::InTow { takeoff: partner_takeoff, partner: id.to_string(), apart: 0, release: None });
+        }
+    }
+
+    fn set_launch(&mut self, id: &str, launch_method: LaunchMethod, tow_partner: Option<&str>) {
+        if let Some(idx) = self.open_entries.get(id) {
+            let entry = &mut self.entries[*idx];
+            entry.launch_method = launch_method;
+            entry.tow_partner = tow_partner.map(|p| p.to_string());
+        }
+    }
+
+    fn set_release(&mut self, id: &str, release: &AircraftBeacon, takeoff: &AircraftBeacon) {
+        if let Some(idx) = self.open_entries.get(id) {
+            let entry = &mut self.entries[*idx];
+            entry.release_ts = Some(release.ts);
+            entry.release_height = Some(release.altitude - takeoff.altitude);
+        }
+    }
+
+    /// Follows the launch of the aircraft: release from the tow or classification of a launch without a tow plane.
+    fn update_launch(&mut self, id: &str, beacon: &AircraftBeacon) {
+        let state = match self.launches.get(id) {
+            Some(state) => state.clone(),
+            None => return,
+        };
+
+        match state {
+            LaunchState::InTow { takeoff, partner, apart, release } => {
+                // compared with the partner's position at the same time:
+                let is_apart = match self.position_at(&partner, beacon.ts) {
+                    Some((lat, lon, altitude)) => {
+                        haversine_distance(lat, lon, beacon.lat, beacon.lon) > RELEASE_DISTANCE
+                            || (altitude - beacon.altitude).abs() > RELEASE_HEIGHT_DIFF
+                    },
+                    None => false,
+                };
+
+                if !is_apart {
+                    if beacon.ts - takeoff.ts > MAX_TOW_DURATION {
+                        self.launches.remove(id);
+                        self.launches.remove(&partner);
+                    } else if apart > 0 {
+                        self.launches.insert(id.to_string(), LaunchState::InTow { takeoff, partner, apart: 0, release: None });
+                    }
+                    return;
+                }
+
+                // the release height is that of the glider:
+                let glider_id = if is_glider(&beacon.aircraft_type) { id.to_string() } else { partner.clone() };
+                let release = release.unwrap_or_else(|| Box::new(self.recent.get(&glider_id).and_then(|t| t.back()).unwrap_or(beacon).clone()));
+                if apart + 1 < RELEASE_CONFIRMATIONS {
+                    self.launches.insert(id.to_string(), LaunchState::InTow { takeoff, partner, apart: apart + 1, release: Some(release) });
+                    return;
+                }
+
+                let takeoff_of = |launches: &HashMap<String, LaunchState>, id: &str| match launches.get(id) {
+                    Some(LaunchState::InTow { takeoff, .. }) => takeoff.clone(),
+                    _ => takeoff.clone(),
+                };
+                let glider_takeoff = takeoff_of(&self.launches, &glider_id);
+                let tug_id = if glider_id == id { partner.clone() } else { id.to_string() };
+
+                if release.altitude - glider_takeoff.altitude < MIN_TOW_HEIGHT {
+                    // only took off side by side - each may still pair with another one:
+                    let tug_takeoff = takeoff_of(&self.launches, &tug_id);
+                    self.set_launch(&glider_id, LaunchMethod::Unknown, None);
+                    self.set_launch(&tug_id, LaunchMethod::Unknown, None);
+                    self.launches.insert(glider_id.clone(), LaunchState::Pending { takeoff: glider_takeoff });
+                    self.launches.insert(tug_id.clone(), LaunchState::Pending { takeoff: tug_takeoff });
+                    self.pair_tow(&glider_id, Some(&tug_id));
+                    self.pair_tow(&tug_id, Some(&glider_id));
+                    return;
+                }
+
+                self.set_release(&glider_id, &release, &glider_takeoff);
+                self.set_release(&tug_id, &release, &glider_takeoff);
+
+                self.launches.remove(id);
+                self.launches.remove(&partner);
+            },
+            LaunchState::Pending { takeoff } => {
+                if beacon.ts - takeoff.ts < LAUNCH_DECISION_DELAY {
+                    return;
+                }
+
+                if is_glider(&takeoff.aircraft_type) {
+                    self.classify_glider_launch(id, &takeoff);
+                } else if is_powered(&takeoff.aircraft_type) {
+                    self.set_launch(id, LaunchMethod::SelfLaunch, None);
+                }
+                self.launches.remove(id);
+            },
+        }
+    }
+
+    /// Winch launch = steep climb to the top of the launch shortly after the take-off; anything else is left unknown
+    /// (a self-launch cannot be told from e.g. a tow plane not received).
+    fn classify_glider_launch(&mut self, id: &str, takeoff: &AircraftBeacon) {
+        let launch: Vec<AircraftBeacon> = match self.recent.get(id) {
+            Some(track) => track.iter().filter(|b| b.ts >= takeoff.ts && b.ts <= takeoff.ts + WINCH_LAUNCH_TIME).cloned().collect(),
+            None => Vec::new(),
+        };
+
+        let top = launch.iter().max_by(|a, b| a.altitude.cmp(&b.altitude).then(b.ts.cmp(&a.ts)));     // the first fix at the top
+        let peak_climb = launch.iter().filter_map(|b| b.climb_rate).fold(0.0, f64::max);
+
+        match top {
+            Some(top) if top.altitude - takeoff.altitude >= WINCH_MIN_GAIN && peak_climb >= WINCH_MIN_CLIMB => {
+                let top = top.clone();
+                self.set_launch(id, LaunchMethod::Winch, None);
+                self.set_release(id, &top, takeoff);
+            },
+            _ => self.set_launch(id, LaunchMethod::Unknown, None),
+        }
+    }
+
+    pub fn entries(&self) -> &[LogbookEntry] {
+        &self.entries
+    }
+
+    /// Daily logbook of an airfield: flights which took off or landed there on the date, by take-off time.
+    pub fn daily(&self, airfield_code: &str, date: NaiveDate) -> Vec<&LogbookEntry> {
+        let code = Some(airfield_code.to_string());
+        let mut entries: Vec<&LogbookEntry> = self.entries.iter()
+            .filter(|e| e.date == date && (e.takeoff_airfield == code || e.landing_airfield == code))
+            .collect();
+        entries.sort_by_key(|e| e.takeoff_ts);
+
+        entries
+    }
+
+    pub fn to_csv(entries: &[&LogbookEntry]) -> String {
+        let mut csv = String::from(LogbookEntry::CSV_HEADER);
+        csv.push('\n');
+        for entry in entries {
+            csv.push_str(&entry.to_csv_line());
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    pub fn to_json_str(entries: &[&LogbookEntry]) -> String {
+        let js: Vec<serde_json::Value> = entries.iter().map(|e| e.to_json()).collect();
+
+        serde_json::Value::Array(js).to_string()
+    }
+}
+
+impl Observer<AircraftBeacon> for Logbook {
+    fn notify(&mut self, beacon: AircraftBeacon) {
+        self.ingest(beacon);
+    }
+}
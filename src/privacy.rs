@@ -0,0 +1,258 @@
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::BuildHasher;
+use std::rc::Rc;
+
+use regex::{Captures, Regex};
+
+use crate::data_structures::{AircraftBeacon, Observer};
+use crate::device_database::DeviceDatabase;
+
+
+const DEFAULT_COARSEN_PRECISION: f64 = 0.01;    // [deg] ~1 km
+const DEFAULT_DELAY: i64 = 5 * 60;              // [s]
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrivacyMode {
+    /// Not passed on at all.
+    Drop,
+    /// Address replaced by a pseudonym (stable for the lifetime of the filter), registration, cn & model removed.
+    Anonymise,
+    /// Position rounded to the coarsen precision.
+    Coarsen,
+    /// Passed on only after the delay.
+    Delay,
+}
+
+/// Enforces the OGN data policy: aircraft which opted out of tracking are dropped and those which opted out of
+/// identification (stealth flag, do_not_identify or the device database) are anonymised. The modes applied to each
+/// group, as well as modes applied to all the beacons (e.g. a delayed public feed), are selectable.
+///
+/// Works as a beacon stage (Observer passing the beacons to its listener) or on raw APRS lines for the outputs
+/// relaying them (filter_line()). The delay runs on the beacon timestamps.
+pub struct PrivacyFilter {
+    no_track_modes: Vec<PrivacyMode>,
+    no_identify_modes: Vec<PrivacyMode>,
+    all_modes: Vec<PrivacyMode>,
+    coarsen_precision: f64,     // [deg]
+    delay: i64,                 // [s]
+    device_database: Option<DeviceDatabase>,
+    pseudonym_hasher: RandomState,
+    clock_ts: i64,
+    delayed_beacons: VecDeque<AircraftBeacon>,
+    delayed_lines: VecDeque<(String, AircraftBeacon)>,
+    position_re: Regex,
+    beacon_listener: Option<Rc<RefCell<dyn Observer<AircraftBeacon>>>>,
+}
+
+impl PrivacyFilter {
+    pub fn new() -> Self {
+        Self {
+            no_track_modes: vec![PrivacyMode::Drop],
+            no_identify_modes: vec![PrivacyMode::Anonymise],
+            all_modes: Vec::new(),
+            coarsen_precision: DEFAULT_COARSEN_PRECISION,
+            delay: DEFAULT_DELAY,
+            device_database: None,
+            pseudonym_hasher: RandomState::new(),
+            clock_ts: 0,
+            delayed_beacons: VecDeque::new(),
+            delayed_lines: VecDeque::new(),
+            // APRS position: 4821.61N/01654.95E + optional precision enhancement !W12!
+            position_re: Regex::new(r"(\d{4}\.\d{2})([NS])(.)(\d{5}\.\d{2})([EW])").unwrap(),
+            beacon_listener: None,
+        }
+    }
+
+    /// Modes for aircraft which opted out of tracking (default Drop).
+    pub fn set_no_track_modes(&mut self, modes: &[PrivacyMode]) {
+        self.no_track_modes = modes.to_vec();
+    }
+
+    /// Modes for aircraft which opted out of identification (default Anonymise).
+    pub fn set_no_identify_modes(&mut self, modes: &[PrivacyMode]) {
+        self.no_identify_modes = modes.to_vec();
+    }
+
+    /// Modes applied to every beacon (default none).
+    pub fn set_all_modes(&mut self, modes: &[PrivacyMode]) {
+        self.all_modes = modes.to_vec();
+    }
+
+    /// Grid the coarsened positions are rounded to [deg].
+    pub fn set_coarsen_precision(&mut self, precision: f64) {
+        self.coarsen_precision = precision;
+    }
+
+    /// [s]
+    pub fn set_delay(&mut self, delay: i64) {
+        self.delay = delay;
+    }
+
+    /// Opt-outs registered in the device database apply also to beacons which have not passed a DeviceEnricher.
+    pub fn set_device_database(&mut self, device_database: DeviceDatabase) {
+        self.device_database = Some(device_database);
+    }
+
+    pub fn set_beacon_listener(&mut self, listener: impl Observer<AircraftBeacon> + 'static) {
+        self.beacon_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    /// Modes applicable to the beacon.
+    pub fn modes(&self, beacon: &AircraftBeacon) -> Vec<PrivacyMode> {
+        let device = self.device_database.as_ref().and_then(|db| db.lookup(beacon));
+        let no_track = beacon.do_not_track || device.map(|d| !d.tracked).unwrap_or(false);
+        let no_identify = beacon.stealth || beacon.do_not_identify || device.map(|d| !d.identified).unwrap_or(false);
+
+        let mut modes = self.all_modes.clone();
+        if no_track {
+            modes.extend(&self.no_track_modes);
+        }
+        if no_identify {
+            modes.extend(&self.no_identify_modes);
+        }
+
+        modes
+    }
+
+    /// Pseudonymous 24 bit address for the address.
+    fn pseudonym(&self, beacon: &AircraftBeacon) -> String {
+        format!("{:06X}", self.pseudonym_hasher.hash_one(beacon.callsign()) & 0xFF_FFFF)
+    }
+
+    fn coarsen_value(&self, value: f64) -> f64 {
+        if self.coarsen_precision <= 0.0 {
+            return value;
+        }
+
+        (value / self.coarsen_precision).round() * self.coarsen_precision
+    }
+
+    fn transform(&self, beacon: &mut AircraftBeacon, modes: &[PrivacyMode]) {
+        if modes.contains(&PrivacyMode::Anonymise) {
+            beacon.addr = self.pseudonym(beacon);
+            beacon.registration.clear();
+            beacon.competition_number.clear();
+            beacon.aircraft_model.clear();
+        }
+
+        if modes.contains(&PrivacyMode::Coarsen) {
+            beacon.lat = self.coarsen_value(beacon.lat);
+            beacon.lon = self.coarsen_value(beacon.lon);
+        }
+    }
+
+    /// Applies the policy to the beacon. Returns the beacons ready to be passed on (delayed ones included).
+    pub fn process(&mut self, mut beacon: AircraftBeacon) -> Vec<AircraftBeacon> {
+        if beacon.ts > self.clock_ts {
+            self.clock_ts = beacon.ts;
+        }
+
+        let mut ready = Vec::new();
+        while self.delayed_beacons.front().map(|b| b.ts + self.delay <= self.clock_ts).unwrap_or(false) {
+            ready.extend(self.delayed_beacons.pop_front());
+        }
+
+        let modes = self.modes(&beacon);
+        if !modes.contains(&PrivacyMode::Drop) {
+            self.transform(&mut beacon, &modes);
+            if modes.contains(&PrivacyMode::Delay) && self.delay > 0 {
+                self.delayed_beacons.push_back(beacon);
+            } else {
+                ready.push(beacon);
+            }
+        }
+
+        ready
+    }
+
+    /// Applies the policy to a raw APRS line the beacon was parsed from. Returns the lines ready to be sent on
+    /// (delayed ones included) with their beacons as modified by the policy.
+    pub fn filter_line(&mut self, line: &str, beacon: &AircraftBeacon) -> Vec<(String, AircraftBeacon)> {
+        if beacon.ts > self.clock_ts {
+            self.clock_ts = beacon.ts;
+        }
+
+        let mut ready = Vec::new();
+        while self.delayed_lines.front().map(|(_, b)| b.ts + self.delay <= self.clock_ts).unwrap_or(false) {
+            ready.extend(self.delayed_lines.pop_front());
+        }
+
+        let modes = self.modes(beacon);
+        if !modes.contains(&PrivacyMode::Drop) {
+            let mut line = line.to_string();
+            if modes.contains(&PrivacyMode::Anonymise) {
+                let pseudonym = self.pseudonym(beacon);
+                line = line.replace(&beacon.addr, &pseudonym);
+                if !beacon.registration.is_empty() {
+                    line = line.replace(&beacon.registration, &format!("{}{}", beacon.prefix, pseudonym));
+                }
+            }
+            if modes.contains(&PrivacyMode::Coarsen) {
+                line = self.coarsen_line(&line);
+            }
+
+            let mut beacon = beacon.clone();
+            self.transform(&mut beacon, &modes);
+            if modes.contains(&PrivacyMode::Delay) && self.delay > 0 {
+                self.delayed_lines.push_back((line, beacon));
+            } else {
+                ready.push((line, beacon));
+            }
+        }
+
+        ready
+    }
+
+    /// Rounds the APRS position of the line and removes its precision enhancement.
+    fn coarsen_line(&self, line: &str) -> String {
+        let to_aprs = |value: f64, deg_digits: usize| {
+            // rounded in hundredths of a minute so that 59.996' carries into the degrees:
+            let hundredths = (self.coarsen_value(value.abs()) * 60.0 * 100.0).round() as u64;
+            let (deg, min) = (hundredths / 6000, hundredths % 6000);
+            format!("{:0width$}{:02}.{:02}", deg, min / 100, min % 100, width = deg_digits)
+        };
+        let from_aprs = |s: &str| {
+            let dot = s.find('.').unwrap_or(s.len());
+            s[..dot - 2].parse::<f64>().unwrap_or(0.0) + s[dot - 2..].parse::<f64>().unwrap_or(0.0) / 60.0
+        };
+
+        let line = self.position_re.replace(line, |caps: &Captures| {
+            format!("{}{}{}{}{}", to_aprs(from_aprs(&caps[1]), 2), &caps[2], &caps[3], to_aprs(from_aprs(&caps[4]), 3), &caps[5])
+        });
+
+        match line.find("!W") {
+            Some(pos) if line[pos..].len() >= 5 && &line[pos + 4..pos + 5] == "!" => format!("{}!W00!{}", &line[..pos], &line[pos + 5..]),
+            _ => line.to_string(),
+        }
+    }
+
+    /// Passes on all delayed beacons, e.g. at the end of a replay.
+    pub fn flush(&mut self) {
+        let delayed: Vec<AircraftBeacon> = self.delayed_beacons.drain(..).collect();
+        for beacon in delayed {
+            self.notify_beacon_listener(beacon);
+        }
+    }
+
+    fn notify_beacon_listener(&mut self, beacon: AircraftBeacon) {
+        if let Some(listener) = self.beacon_listener.as_mut() {
+            listener.borrow_mut().notify(beacon);
+        }
+    }
+}
+
+impl Default for PrivacyFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for PrivacyFilter {
+    fn notify(&mut self, beacon: AircraftBeacon) {
+        for beacon in self.process(beacon) {
+            self.notify_beacon_listener(beacon);
+        }
+    }
+}
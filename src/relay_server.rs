@@ -0,0 +1,357 @@
+use std::collections::{HashSet, VecDeque};
+use std::io::{BufRead, BufReader, Result, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use regex::Regex;
+
+use crate::aprs_filter::AprsFilter;
+use crate::aprs_server_connection::AprsServerConnection;
+use crate::data_structures::Observer;
+use crate::line_source::LineSource;
+use crate::privacy::PrivacyFilter;
+use crate::MyLineListener;
+
+
+const HEARTBEAT_INTERVAL: u64 = 20;     // [s]
+const RECENT_LINES_CAPACITY: usize = 10_000;
+const DOWNSTREAM_QUEUE_LEN: usize = 1000;   // [lines] a client this far behind is dropped
+
+/// The lines are written by a thread of the client from its queue so a slow client does not hold up the others.
+struct Downstream {
+    id: u64,
+    username: String,
+    filter: AprsFilter,
+    queue: SyncSender<String>,
+    stream: TcpStream,
+}
+
+impl Downstream {
+    /// Queues the line for the client; false when the client is to be dropped.
+    fn send(&self, line: &str) -> bool {
+        match self.queue.try_send(line.to_string()) {
+            Ok(_) => true,
+            Err(e) => {
+                let reason = match e {
+                    TrySendError::Full(_) => "too slow",
+                    TrySendError::Disconnected(_) => "disconnected",
+                };
+                warn!("Dropping downstream client '{}': {}", self.username, reason);
+                let _ = self.stream.shutdown(Shutdown::Both);
+                false
+            },
+        }
+    }
+}
+
+/// APRS-IS compatible relay: lines from one or a few upstream connections are served to local downstream clients.
+/// The downstream clients log in as to any APRS-IS server ('user X pass Y vers Z filter F') and the filter
+/// (as well as later '#filter ...' commands) is evaluated locally against the parsed aircraft beacons. Other lines
+/// (receivers' positions and statuses) are matched by their callsign and the position if they carry one.
+/// Lines received from several upstreams are relayed once.
+/// The OGN data policy is enforced by a default PrivacyFilter (no-track aircraft dropped, no-identify anonymised).
+pub struct RelayServer {
+    downstreams: Arc<Mutex<Vec<Downstream>>>,
+    running: Arc<AtomicBool>,
+    local_addr: SocketAddr,
+    accept_thread: Option<JoinHandle<()>>,
+    line_tx: Sender<String>,
+    line_rx: Receiver<String>,
+    parser: MyLineListener,
+    privacy_filter: Option<PrivacyFilter>,
+    recent_lines: HashSet<String>,
+    recent_lines_order: VecDeque<String>,
+    last_heartbeat_ts: SystemTime,
+}
+
+impl RelayServer {
+    /// Starts accepting downstream clients on the given local address, e.g. "0.0.0.0:14580".
+    pub fn bind<A: ToSocketAddrs>(address: A) -> Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        info!("Relay listening on {}", local_addr);
+
+        let downstreams = Arc::new(Mutex::new(Vec::new()));
+        let running = Arc::new(AtomicBool::new(true));
+        let next_id = Arc::new(AtomicU64::new(1));
+
+        let accept_thread = {
+            let downstreams = Arc::clone(&downstreams);
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    if !running.load(Ordering::SeqCst) {
+                        break;      // woken up by stop()
+                    }
+                    let downstreams = Arc::clone(&downstreams);
+                    let id = next_id.fetch_add(1, Ordering::SeqCst);
+                    thread::spawn(move || serve_downstream(id, stream, downstreams));
+                }
+            })
+        };
+
+        let (line_tx, line_rx) = mpsc::channel();
+
+        Ok(Self {
+            downstreams,
+            running,
+            local_addr,
+            accept_thread: Some(accept_thread),
+            line_tx,
+            line_rx,
+            parser: MyLineListener::new(),
+            privacy_filter: Some(PrivacyFilter::new()),
+            recent_lines: HashSet::new(),
+            recent_lines_order: VecDeque::new(),
+            last_heartbeat_ts: SystemTime::now(),
+        })
+    }
+
+    /// Opens an upstream APRS-IS connection with its own filter (e.g. "r/49.1/16.4/300") in a background thread.
+    pub fn add_upstream(&mut self, address: &str, username: &str, filter: &str) {
+        let address = address.to_string();
+        let username = username.to_string();
+        let filter = filter.to_string();
+
+        self.add_upstream_source(move || {
+            let mut connection = AprsServerConnection::new(&address, &username).unwrap();
+            connection.set_aprs_filter_str(&filter);
+            connection
+        });
+    }
+
+    /// Reads an arbitrary line source in a background thread. The source is created by the factory in that thread.
+    pub fn add_upstream_source<S, F>(&mut self, factory: F)
+    where
+        S: LineSource,
+        F: FnOnce() -> S + Send + 'static,
+    {
+        let line_tx = self.line_tx.clone();
+        let running = Arc::clone(&self.running);
+
+        thread::spawn(move || {
+            let mut source = factory();
+            source.connect();
+            while running.load(Ordering::SeqCst) {
+                match source.next_line() {
+                    Some(line) => {
+                        if line_tx.send(line).is_err() {
+                            break;
+                        }
+                    },
+                    None => {
+                        if source.is_eof() {
+                            break;
+                        }
+                    },
+                }
+            }
+        });
+    }
+
+    /// Replaces the default privacy policy; None relays the lines unchanged (only for non-public setups!).
+    pub fn set_privacy_filter(&mut self, privacy_filter: Option<PrivacyFilter>) {
+        self.privacy_filter = privacy_filter;
+    }
+
+    /// Number of currently connected downstream clients.
+    pub fn num_downstreams(&self) -> usize {
+        self.downstreams.lock().unwrap().len()
+    }
+
+    /// Relays the upstream lines until stop() is called.
+    pub fn do_loop(&mut self) {
+        while self.running.load(Ordering::SeqCst) {
+            match self.line_rx.recv_timeout(Duration::from_secs(1)) {
+                Ok(line) => self.relay(line),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            self.send_heartbeat();
+        }
+    }
+
+    /// Stops relaying, accepting new clients and disconnects the connected ones.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+
+        // the accept thread is blocked until a connection comes:
+        if let Some(accept_thread) = self.accept_thread.take() {
+            let mut wake_addr = self.local_addr;
+            if wake_addr.ip().is_unspecified() {
+                wake_addr.set_ip(Ipv4Addr::LOCALHOST.into());
+            }
+            if TcpStream::connect_timeout(&wake_addr, Duration::from_secs(1)).is_ok() {
+                let _ = accept_thread.join();
+            }
+        }
+
+        for downstream in self.downstreams.lock().unwrap().drain(..) {
+            let _ = downstream.stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// Sends the line to all downstream clients whose filter it passes.
+    pub fn relay(&mut self, line: String) {
+        if line.starts_with('#') || !self.is_new(&line) {
+            return;
+        }
+
+        let beacon = match self.parser.parse_beacon_line(&line) {
+            Some(beacon) => beacon,
+            None => {
+                self.relay_station_line(&line);
+                return;
+            },
+        };
+
+        let lines = match self.privacy_filter.as_mut() {
+            Some(privacy_filter) => privacy_filter.filter_line(&line, &beacon),
+            None => vec![(line, beacon)],
+        };
+
+        let mut downstreams = self.downstreams.lock().unwrap();
+        for (line, beacon) in lines {
+            downstreams.retain(|downstream| !downstream.filter.matches(&beacon) || downstream.send(&line));
+        }
+    }
+
+    /// Sends a line of other than an aircraft (e.g. a receiver) to the clients whose filter its station passes.
+    fn relay_station_line(&mut self, line: &str) {
+        let (callsign, position) = match parse_station(line) {
+            Some(station) => station,
+            None => return,
+        };
+
+        let mut downstreams = self.downstreams.lock().unwrap();
+        downstreams.retain(|downstream| !downstream.filter.matches_station(&callsign, position) || downstream.send(line));
+    }
+
+    /// Remembers recently relayed lines to relay a line heard from several upstreams just once.
+    fn is_new(&mut self, line: &str) -> bool {
+        if !self.recent_lines.insert(line.to_string()) {
+            return false;
+        }
+
+        self.recent_lines_order.push_back(line.to_string());
+        if self.recent_lines_order.len() > RECENT_LINES_CAPACITY {
+            if let Some(oldest) = self.recent_lines_order.pop_front() {
+                self.recent_lines.remove(&oldest);
+            }
+        }
+
+        true
+    }
+
+    fn send_heartbeat(&mut self) {
+        if self.last_heartbeat_ts.elapsed().map(|e| e.as_secs()).unwrap_or(0) < HEARTBEAT_INTERVAL {
+            return;
+        }
+        self.last_heartbeat_ts = SystemTime::now();
+
+        let heartbeat = format!("# ognClient-rs relay {} OGNRELAY", Utc::now().format("%d %b %Y %H:%M:%S GMT"));
+        let mut downstreams = self.downstreams.lock().unwrap();
+        downstreams.retain(|downstream| downstream.send(&heartbeat));
+    }
+}
+
+impl Observer<String> for RelayServer {
+    fn notify(&mut self, line: String) {
+        self.relay(line);
+    }
+}
+
+/// Callsign and position (lat, lon) if the line carries one, e.g. of a receiver beacon:
+///     LKHS>OGNSDR,TCPIP*,qAC,GLIDERN1:/135930h4944.57NI01619.87E&/A=001066
+fn parse_station(line: &str) -> Option<(String, Option<(f64, f64)>)> {
+    lazy_static! {
+        static ref STATION_RE: Regex = Regex::new(r"^([A-Za-z0-9-]+)>[^:]*:(?:[/@](?:\d{6}[hz/])?|[!=])(\d{2})(\d{2}\.\d{2})([NS]).(\d{3})(\d{2}\.\d{2})([EW])").unwrap();
+        static ref CALLSIGN_RE: Regex = Regex::new(r"^([A-Za-z0-9-]+)>[^:]*:").unwrap();
+    }
+
+    if let Some(caps) = STATION_RE.captures(line) {
+        let value = |deg: &str, min: &str, negative: bool| {
+            let value = deg.parse::<f64>().unwrap_or(0.0) + min.parse::<f64>().unwrap_or(0.0) / 60.0;
+            if negative { -value } else { value }
+        };
+        let lat = value(&caps[2], &caps[3], &caps[4] == "S");
+        let lon = value(&caps[5], &caps[6], &caps[7] == "W");
+
+        return Some((caps[1].to_string(), Some((lat, lon))));
+    }
+
+    CALLSIGN_RE.captures(line).map(|caps| (caps[1].to_string(), None))
+}
+
+/// Handles the login and the '#filter' commands of one downstream client.
+fn serve_downstream(id: u64, stream: TcpStream, downstreams: Arc<Mutex<Vec<Downstream>>>) {
+    let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let _ = writer.set_write_timeout(Some(Duration::from_secs(5)));  // slow clients get dropped
+    if writeln!(writer, "# ognClient-rs relay").is_err() {
+        return;
+    }
+
+    let reader = BufReader::new(stream);
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line.trim().to_string(),
+            Err(_) => break,
+        };
+
+        if line.starts_with("user ") {
+            let items: Vec<&str> = line.split_whitespace().collect();
+            let username = items.get(1).unwrap_or(&"").to_string();
+            let filter = match items.iter().position(|i| *i == "filter") {
+                Some(pos) => items[pos+1..].join(" "),
+                None => String::new(),
+            };
+
+            if writeln!(writer, "# logresp {} unverified, server OGNRELAY", username).is_err() {
+                break;
+            }
+            let (queue, stream) = match (writer.try_clone(), writer.try_clone()) {
+                (Ok(queue_writer), Ok(stream)) => {
+                    let (queue, lines) = mpsc::sync_channel(DOWNSTREAM_QUEUE_LEN);
+                    thread::spawn(move || write_downstream(queue_writer, lines));
+                    (queue, stream)
+                },
+                _ => break,
+            };
+            info!("Downstream client '{}' from {} logged in with filter '{}'", username, peer, filter);
+
+            let mut downstreams = downstreams.lock().unwrap();
+            downstreams.retain(|d| d.id != id);
+            downstreams.push(Downstream { id, username, filter: AprsFilter::parse(&filter), queue, stream });
+
+        } else if let Some(filter) = line.strip_prefix("#filter") {
+            let mut downstreams = downstreams.lock().unwrap();
+            if let Some(downstream) = downstreams.iter_mut().find(|d| d.id == id) {
+                downstream.filter = AprsFilter::parse(filter);
+            }
+        }
+    }
+
+    downstreams.lock().unwrap().retain(|d| d.id != id);
+}
+
+/// Writes the queued lines to the client until it is dropped (the queue closes) or the write fails.
+fn write_downstream(mut writer: TcpStream, lines: Receiver<String>) {
+    for line in lines {
+        if writeln!(writer, "{}", line).is_err() {
+            let _ = writer.shutdown(Shutdown::Both);
+            break;
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use log::warn;
+
+use crate::line_source::{FileSource, LineSource};
+
+
+/// Replays a recorded APRS log with the original timing between the lines.
+///
+/// Every line of the log is expected to be prefixed with its arrival time in one of the forms:
+///     2023-06-17 12:34:56.789 FLRDDA5BA>APRS,qAS,LKKA:/...     (as produced by utils::now())
+///     1687005296.789 FLRDDA5BA>APRS,qAS,LKKA:/...               (unix timestamp [s])
+/// Lines without a recognised prefix are passed as they are with the time of the previous line.
+/// An archive of the FeedRecorder is replayed together with the parts it continues in (e.g. ogn-2023-06-17.log.gz,
+/// then ogn-2023-06-17.1.log.gz, ogn-2023-06-17.2.log.gz ..).
+pub struct ReplaySource {
+    file: FileSource,
+    next_parts: VecDeque<PathBuf>,
+    speed: f64,
+    start_time: Option<DateTime<Utc>>,
+    first_line_time: Option<DateTime<Utc>>,
+    first_line_instant: Instant,
+    current_time: Option<DateTime<Utc>>,
+}
+
+impl ReplaySource {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            file: FileSource::open(path.as_ref())?,
+            next_parts: following_parts(path.as_ref()),
+            speed: 1.0,
+            start_time: None,
+            first_line_time: None,
+            first_line_instant: Instant::now(),
+            current_time: None,
+        })
+    }
+
+    /// Replay speed factor: 1.0 = original timing, 10.0 = ten times faster, 0.0 = as fast as possible.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    /// Lines recorded before this time are skipped. Use before the first call of next_line().
+    pub fn seek(&mut self, start_time: DateTime<Utc>) {
+        self.start_time = Some(start_time);
+    }
+
+    /// Splits the line into its recorded arrival time and the raw APRS line.
+    pub fn split_timestamp(line: &str) -> Option<(DateTime<Utc>, &str)> {
+        // 2023-06-17 12:34:56.789 <line>
+        if line.len() > 20 && line.as_bytes()[4] == b'-' && line.as_bytes()[10] == b' ' {
+            let ts_end = 19 + line.get(19..)?.find(' ')?;
+            let ts = NaiveDateTime::parse_from_str(&line[..ts_end], "%Y-%m-%d %H:%M:%S%.f").ok()?;
+
+            return Some((Utc.from_utc_datetime(&ts), line[ts_end+1..].trim_start()));
+        }
+
+        // 1687005296.789 <line>
+        let ts_end = line.find([' ', '\t'])?;
+        let ts = line[..ts_end].parse::<f64>().ok()?;
+        let ts = Utc.timestamp_millis_opt((ts * 1000.0).round() as i64).single()?;
+
+        Some((ts, line[ts_end+1..].trim_start()))
+    }
+
+    /// Sleeps until it is time to release a line recorded at the given time.
+    fn wait_for(&mut self, line_time: DateTime<Utc>) {
+        let first_line_time = match self.first_line_time {
+            Some(val) => val,
+            None => {
+                self.first_line_time = Some(line_time);
+                self.first_line_instant = Instant::now();
+                return;
+            }
+        };
+
+        if self.speed <= 0.0 || !self.speed.is_finite() {
+            return;
+        }
+
+        let offset_ms = (line_time - first_line_time).num_milliseconds();
+        if offset_ms <= 0 {
+            return;
+        }
+
+        let due = self.first_line_instant + Duration::from_secs_f64(offset_ms as f64 / 1000.0 / self.speed);
+        let now = Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        }
+    }
+}
+
+/// The parts of a FeedRecorder archive following the given one, in order.
+fn following_parts(path: &Path) -> VecDeque<PathBuf> {
+    let stem = match path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".log.gz")) {
+        Some(stem) => stem,
+        None => return VecDeque::new(),
+    };
+    // opened at a part (stem.N) the replay continues with the next one:
+    let (stem, first_part) = match stem.rsplit_once('.').and_then(|(s, n)| Some((s, n.parse::<u32>().ok()?))) {
+        Some((stem, part)) => (stem, part + 1),
+        None => (stem, 1),
+    };
+
+    (first_part..)
+        .map(|part| path.with_file_name(format!("{}.{}.log.gz", stem, part)))
+        .take_while(|path| path.exists())
+        .collect()
+}
+
+impl LineSource for ReplaySource {
+    fn next_line(&mut self) -> Option<String> {
+        loop {
+            let line = match self.file.next_line() {
+                Some(line) => line,
+                None => {
+                    let path = self.next_parts.pop_front()?;
+                    match FileSource::open(&path) {
+                        Ok(file) => self.file = file,
+                        Err(e) => warn!("Cannot open '{}': {}", path.display(), e),
+                    }
+                    continue;
+                },
+            };
+
+            let (line_time, aprs_line) = match Self::split_timestamp(&line) {
+                Some((line_time, aprs_line)) => (line_time, aprs_line.to_string()),
+                None => match self.current_time {
+                    Some(line_time) => (line_time, line),
+                    None => {
+                        warn!("Replay line without timestamp: '{}'", line);
+                        continue;
+                    },
+                },
+            };
+
+            if let Some(start_time) = self.start_time {
+                if line_time < start_time {
+                    continue;
+                }
+            }
+
+            self.wait_for(line_time);
+            self.current_time = Some(line_time);
+
+            return Some(aprs_line);
+        }
+    }
+
+    fn is_eof(&self) -> bool {
+        self.file.is_eof() && self.next_parts.is_empty()
+    }
+
+    fn reference_time(&self) -> Option<DateTime<Utc>> {
+        self.current_time
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::geodesy::{haversine_distance, EARTH_RADIUS, METERS_PER_DEG_LAT};
+
+
+const HALF_CIRCUMFERENCE: f64 = 20_015_000.0;   // [m]
+
+/// Grid index of point positions on lat/lon cells of a fixed size (in principle a geohash of a fixed precision).
+/// Queries handle boxes across the antimeridian and circles around the poles.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    cell_size: f64,     // [deg]
+    num_lon_cells: i32,
+    cells: HashMap<(i32, i32), HashSet<String>>,
+    positions: HashMap<String, (f64, f64)>,
+}
+
+impl SpatialIndex {
+    /// @param cell_size edge of a grid cell [deg]; 0.25 is a good fit for regional traffic
+    pub fn new(cell_size: f64) -> Self {
+        Self {
+            cell_size,
+            num_lon_cells: (360.0 / cell_size).ceil() as i32,
+            cells: HashMap::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    fn lat_idx(&self, lat: f64) -> i32 {
+        ((lat.clamp(-90.0, 90.0) + 90.0) / self.cell_size).floor() as i32
+    }
+
+    fn lon_idx(&self, lon: f64) -> i32 {
+        let lon = (lon + 180.0).rem_euclid(360.0);
+        ((lon / self.cell_size).floor() as i32).min(self.num_lon_cells - 1)
+    }
+
+    /// Inserts or moves the item to the given position.
+    pub fn insert(&mut self, id: &str, lat: f64, lon: f64) {
+        self.remove(id);
+
+        let cell = (self.lat_idx(lat), self.lon_idx(lon));
+        self.cells.entry(cell).or_default().insert(id.to_string());
+        self.positions.insert(id.to_string(), (lat, lon));
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        if let Some((lat, lon)) = self.positions.remove(id) {
+            let cell = (self.lat_idx(lat), self.lon_idx(lon));
+            if let Some(ids) = self.cells.get_mut(&cell) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn position(&self, id: &str) -> Option<(f64, f64)> {
+        self.positions.get(id).copied()
+    }
+
+    /// Ids of items within the box. When lon_w > lon_e the box spans across the antimeridian.
+    pub fn in_bbox(&self, lat_s: f64, lon_w: f64, lat_n: f64, lon_e: f64) -> Vec<String> {
+        let lon_in_box = |lon: f64| {
+            if lon_w <= lon_e {
+                lon >= lon_w && lon <= lon_e
+            } else {
+                lon >= lon_w || lon <= lon_e
+            }
+        };
+
+        self.candidates(lat_s, lon_w, lat_n, lon_e)
+            .into_iter()
+            .filter(|id| {
+                let (lat, lon) = self.positions[id];
+                lat >= lat_s && lat <= lat_n && lon_in_box(lon)
+            })
+            .collect()
+    }
+
+    /// Items within the radius [m] around the point with their distances [m], nearest first.
+    pub fn within_radius(&self, lat: f64, lon: f64, radius: f64) -> Vec<(String, f64)> {
+        let d_lat = radius / METERS_PER_DEG_LAT;
+        let lat_s = (lat - d_lat).max(-90.0);
+        let lat_n = (lat + d_lat).min(90.0);
+
+        // the longitude span widens towards the poles; a circle containing a pole covers all longitudes:
+        let d_lon = if lat_s <= -90.0 || lat_n >= 90.0 || radius >= HALF_CIRCUMFERENCE {
+            180.0
+        } else {
+            let ratio = (radius / EARTH_RADIUS).sin() / lat.to_radians().cos();
+            if ratio >= 1.0 { 180.0 } else { ratio.asin().to_degrees() }
+        };
+
+        let (lon_w, lon_e) = if d_lon >= 180.0 {
+            (-180.0, 180.0)
+        } else {
+            let lon_e = wrap_lon(lon + d_lon);
+            (wrap_lon(lon - d_lon), if lon_e == -180.0 { 180.0 } else { lon_e })
+        };
+
+        let mut result: Vec<(String, f64)> = self.candidates(lat_s, lon_w, lat_n, lon_e)
+            .into_iter()
+            .filter_map(|id| {
+                let (p_lat, p_lon) = self.positions[&id];
+                let dist = haversine_distance(lat, lon, p_lat, p_lon);
+                if dist <= radius { Some((id, dist)) } else { None }
+            })
+            .collect();
+        result.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        result
+    }
+
+    /// The k items nearest to the point with their distances [m], nearest first.
+    pub fn nearest(&self, lat: f64, lon: f64, k: usize) -> Vec<(String, f64)> {
+        if k == 0 || self.is_empty() {
+            return Vec::new();
+        }
+
+        let mut radius = self.cell_size * METERS_PER_DEG_LAT;
+        loop {
+            let mut result = self.within_radius(lat, lon, radius);
+            if result.len() >= k || radius >= HALF_CIRCUMFERENCE {
+                result.truncate(k);
+                return result;
+            }
+            radius *= 2.0;
+        }
+    }
+
+    /// Ids from all cells touching the box.
+    fn candidates(&self, lat_s: f64, lon_w: f64, lat_n: f64, lon_e: f64) -> Vec<String> {
+        let lat_from = self.lat_idx(lat_s);
+        let lat_to = self.lat_idx(lat_n);
+        let lon_from = self.lon_idx(lon_w);
+        let mut lon_to = self.lon_idx(lon_e);
+        if lon_to < lon_from || (lon_w > lon_e && lon_to == lon_from) {
+            lon_to += self.num_lon_cells;   // across the antimeridian
+        }
+        if lon_w <= -180.0 && lon_e >= 180.0 {
+            lon_to = lon_from + self.num_lon_cells - 1;
+        }
+
+        // scanning all occupied cells is cheaper than visiting many empty ones:
+        let num_box_cells = (lat_to - lat_from + 1) as usize * (lon_to - lon_from + 1) as usize;
+        if num_box_cells > self.cells.len() {
+            return self.cells.iter()
+                .filter(|((lat_i, lon_i), _)| {
+                    let lon_i = if *lon_i < lon_from { lon_i + self.num_lon_cells } else { *lon_i };
+                    *lat_i >= lat_from && *lat_i <= lat_to && lon_i <= lon_to
+                })
+                .flat_map(|(_, ids)| ids.iter().cloned())
+                .collect();
+        }
+
+        let mut ids = Vec::new();
+        for lat_i in lat_from..=lat_to {
+            for lon_i in lon_from..=lon_to {
+                if let Some(cell_ids) = self.cells.get(&(lat_i, lon_i.rem_euclid(self.num_lon_cells))) {
+                    ids.extend(cell_ids.iter().cloned());
+                }
+            }
+        }
+
+        ids
+    }
+}
+
+/// Normalises the longitude into <-180, 180).
+fn wrap_lon(lon: f64) -> f64 {
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
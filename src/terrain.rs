@@ -0,0 +1,427 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use log::{info, warn};
+use regex::Regex;
+
+
+const DEFAULT_CACHE_SIZE: usize = 4;    // [tiles] a 1" tile takes ~50 MB as f32, a 3" one ~6 MB
+const HGT_VOID: f32 = -32768.0;
+
+/// Source of the terrain elevation, e.g. a digital elevation model.
+pub trait ElevationProvider {
+    /// Terrain elevation above mean sea level [m]; None when not known for the position.
+    fn elevation(&mut self, lat: f64, lon: f64) -> Option<f64>;
+}
+
+/// Regular lat/lon grid of elevation samples, rows from the north.
+struct ElevationGrid {
+    width: usize,
+    height: usize,
+    north: f64,     // [deg] latitude of the first row of samples
+    west: f64,      // [deg] longitude of the first column of samples
+    lat_step: f64,  // [deg]
+    lon_step: f64,  // [deg]
+    samples: Vec<f32>,
+    nodata: Option<f32>,
+}
+
+impl ElevationGrid {
+    fn sample(&self, col: usize, row: usize) -> Option<f32> {
+        let value = self.samples[row * self.width + col];
+        if value.is_nan() || Some(value) == self.nodata {
+            return None;
+        }
+
+        Some(value)
+    }
+
+    /// Bilinear interpolation of the four surrounding samples; void samples are left out.
+    fn elevation(&self, lat: f64, lon: f64) -> Option<f64> {
+        let x = ((lon - self.west) / self.lon_step).clamp(0.0, (self.width - 1) as f64);
+        let y = ((self.north - lat) / self.lat_step).clamp(0.0, (self.height - 1) as f64);
+        let col = (x.floor() as usize).min(self.width.saturating_sub(2));
+        let row = (y.floor() as usize).min(self.height.saturating_sub(2));
+        let fx = x - col as f64;
+        let fy = y - row as f64;
+
+        let corners = [
+            (col, row, (1.0 - fx) * (1.0 - fy)),
+            (col + 1, row, fx * (1.0 - fy)),
+            (col, row + 1, (1.0 - fx) * fy),
+            (col + 1, row + 1, fx * fy),
+        ];
+
+        let (sum, weights) = corners.iter()
+            .filter(|(c, r, _)| *c < self.width && *r < self.height)
+            .filter_map(|(c, r, w)| self.sample(*c, *r).map(|v| (v as f64 * w, *w)))
+            .fold((0.0, 0.0), |acc, (v, w)| (acc.0 + v, acc.1 + w));
+
+        if weights > 0.0 { Some(sum / weights) } else { None }
+    }
+}
+
+/// SRTM .hgt tile: big-endian i16 samples of a 1x1 deg square named after its south-west corner, e.g. N49E016.hgt
+fn read_hgt(data: &[u8], lat: i32, lon: i32) -> io::Result<ElevationGrid> {
+    let num_samples = data.len() / 2;
+    let size = (num_samples as f64).sqrt().round() as usize;
+    if size < 2 || size * size != num_samples {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("not a square hgt tile ({} bytes)", data.len())));
+    }
+
+    let samples = data.chunks_exact(2)
+        .map(|b| i16::from_be_bytes([b[0], b[1]]) as f32)
+        .collect();
+
+    Ok(ElevationGrid {
+        width: size,
+        height: size,
+        north: lat as f64 + 1.0,
+        west: lon as f64,
+        lat_step: 1.0 / (size - 1) as f64,
+        lon_step: 1.0 / (size - 1) as f64,
+        samples,
+        nodata: Some(HGT_VOID),
+    })
+}
+
+struct TiffEntry {
+    field_type: u16,
+    count: usize,
+    value_offset: usize,    // of the value itself when it fits in the entry
+}
+
+/// Minimal reader of single-band GeoTIFF elevation tiles (e.g. Copernicus DEM): strips or tiles, uncompressed or
+/// deflate, with or without a predictor, 16/32 bit integer or 32 bit float samples.
+struct TiffReader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+    entries: HashMap<u16, TiffEntry>,
+}
+
+impl<'a> TiffReader<'a> {
+    fn new(data: &'a [u8]) -> io::Result<Self> {
+        let little_endian = match data.get(0..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err(invalid_data("not a TIFF file")),
+        };
+
+        let mut reader = Self { data, little_endian, entries: HashMap::new() };
+        if reader.u16_at(2)? != 42 {
+            return Err(invalid_data("unsupported TIFF version (BigTIFF?)"));
+        }
+
+        let ifd = reader.u32_at(4)? as usize;
+        let num_entries = reader.u16_at(ifd)? as usize;
+        for i in 0..num_entries {
+            let pos = ifd + 2 + i * 12;
+            let tag = reader.u16_at(pos)?;
+            let field_type = reader.u16_at(pos + 2)?;
+            let count = reader.u32_at(pos + 4)? as usize;
+            let size = count * Self::type_size(field_type);
+            let value_offset = if size <= 4 { pos + 8 } else { reader.u32_at(pos + 8)? as usize };
+            reader.entries.insert(tag, TiffEntry { field_type, count, value_offset });
+        }
+
+        Ok(reader)
+    }
+
+    fn type_size(field_type: u16) -> usize {
+        match field_type {
+            3 | 8 => 2,
+            4 | 9 | 11 => 4,
+            5 | 10 | 12 => 8,
+            _ => 1,
+        }
+    }
+
+    fn bytes(&self, pos: usize, len: usize) -> io::Result<&'a [u8]> {
+        self.data.get(pos..pos + len).ok_or_else(|| invalid_data("truncated TIFF file"))
+    }
+
+    fn u16_at(&self, pos: usize) -> io::Result<u16> {
+        let b = self.bytes(pos, 2)?;
+        Ok(if self.little_endian { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) })
+    }
+
+    fn u32_at(&self, pos: usize) -> io::Result<u32> {
+        let b: [u8; 4] = self.bytes(pos, 4)?.try_into().unwrap();
+        Ok(if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    fn f64_at(&self, pos: usize) -> io::Result<f64> {
+        let b: [u8; 8] = self.bytes(pos, 8)?.try_into().unwrap();
+        Ok(if self.little_endian { f64::from_le_bytes(b) } else { f64::from_be_bytes(b) })
+    }
+
+    /// Numeric values of the tag converted to f64.
+    fn values(&self, tag: u16) -> io::Result<Vec<f64>> {
+        let entry = match self.entries.get(&tag) {
+            Some(entry) => entry,
+            None => return Ok(Vec::new()),
+        };
+
+        let size = Self::type_size(entry.field_type);
+        (0..entry.count)
+            .map(|i| {
+                let pos = entry.value_offset + i * size;
+                match entry.field_type {
+                    3 => self.u16_at(pos).map(|v| v as f64),
+                    4 => self.u32_at(pos).map(|v| v as f64),
+                    11 => self.u32_at(pos).map(|v| f32::from_bits(v) as f64),
+                    12 => self.f64_at(pos),
+                    _ => self.bytes(pos, 1).map(|b| b[0] as f64),
+                }
+            })
+            .collect()
+    }
+
+    fn value(&self, tag: u16, default: f64) -> io::Result<f64> {
+        Ok(self.values(tag)?.first().copied().unwrap_or(default))
+    }
+
+    fn ascii(&self, tag: u16) -> Option<String> {
+        let entry = self.entries.get(&tag)?;
+        let bytes = self.bytes(entry.value_offset, entry.count).ok()?;
+        Some(String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_string())
+    }
+
+    fn read_grid(&self) -> io::Result<ElevationGrid> {
+        let width = self.value(256, 0.0)? as usize;
+        let height = self.value(257, 0.0)? as usize;
+        let bits_per_sample = self.value(258, 16.0)? as usize;
+        let compression = self.value(259, 1.0)? as u32;
+        let predictor = self.value(317, 1.0)? as u32;
+        let sample_format = self.value(339, 1.0)? as u32;
+        let bytes_per_sample = bits_per_sample / 8;
+
+        if width < 2 || height < 2 {
+            return Err(invalid_data("empty raster"));
+        }
+        if !matches!((bits_per_sample, sample_format), (16, 1) | (16, 2) | (32, 1) | (32, 2) | (32, 3)) {
+            return Err(invalid_data(&format!("unsupported samples ({} bit, format {})", bits_per_sample, sample_format)));
+        }
+        // the floating point predictor is for the float samples only, horizontal differencing for the integer ones:
+        if !matches!((predictor, sample_format), (1, _) | (2, 1) | (2, 2) | (3, 3)) {
+            return Err(invalid_data(&format!("unsupported predictor {} for sample format {}", predictor, sample_format)));
+        }
+
+        let (block_width, block_height, offsets, byte_counts) = if self.entries.contains_key(&324) {
+            (self.value(322, 0.0)? as usize, self.value(323, 0.0)? as usize, self.values(324)?, self.values(325)?)
+        } else {
+            (width, self.value(278, height as f64)? as usize, self.values(273)?, self.values(279)?)
+        };
+        if block_width == 0 || block_height == 0 || offsets.len() != byte_counts.len() {
+            return Err(invalid_data("invalid raster layout"));
+        }
+        let blocks_across = width.div_ceil(block_width);
+
+        let mut samples = vec![f32::NAN; width * height];
+        for (i, (offset, byte_count)) in offsets.iter().zip(byte_counts.iter()).enumerate() {
+            let raw = self.bytes(*offset as usize, *byte_count as usize)?;
+            let mut block = match compression {
+                1 => raw.to_vec(),
+                8 | 32946 => {
+                    let mut buf = Vec::new();
+                    ZlibDecoder::new(raw).read_to_end(&mut buf)?;
+                    buf
+                },
+                _ => return Err(invalid_data(&format!("unsupported compression {}", compression))),
+            };
+            block.resize(block_width * block_height * bytes_per_sample, 0);
+
+            let block_col = (i % blocks_across) * block_width;
+            let block_row = (i / blocks_across) * block_height;
+            for (r, row_bytes) in block.chunks_exact_mut(block_width * bytes_per_sample).enumerate() {
+                let values = self.decode_row(row_bytes, bytes_per_sample, sample_format, predictor);
+                let row = block_row + r;
+                if row >= height {
+                    break;
+                }
+                for (c, value) in values.into_iter().enumerate().take(width.saturating_sub(block_col)) {
+                    samples[row * width + block_col + c] = value;
+                }
+            }
+        }
+
+        // georeferencing: model tie point & pixel scale; pixel-is-area unless the raster type geo key says otherwise:
+        let tiepoint = self.values(33922)?;
+        let scale = self.values(33550)?;
+        if tiepoint.len() < 6 || scale.len() < 2 {
+            return Err(invalid_data("missing GeoTIFF tie point or pixel scale"));
+        }
+        let geokeys = self.values(34735)?;
+        let pixel_is_point = geokeys.chunks_exact(4).skip(1).any(|key| key[0] == 1025.0 && key[3] == 2.0);
+        let centre = if pixel_is_point { 0.0 } else { 0.5 };
+
+        Ok(ElevationGrid {
+            width,
+            height,
+            north: tiepoint[4] - (centre - tiepoint[1]) * scale[1],
+            west: tiepoint[3] + (centre - tiepoint[0]) * scale[0],
+            lat_step: scale[1],
+            lon_step: scale[0],
+            samples,
+            nodata: self.ascii(42113).and_then(|s| s.parse::<f32>().ok()),
+        })
+    }
+
+    fn decode_row(&self, row: &mut [u8], bytes_per_sample: usize, sample_format: u32, predictor: u32) -> Vec<f32> {
+        if predictor == 3 {
+            // floating point predictor: byte-wise differences over the row with the bytes of the samples split into planes:
+            for i in 1..row.len() {
+                row[i] = row[i].wrapping_add(row[i - 1]);
+            }
+            let n = row.len() / bytes_per_sample;
+            return (0..n)
+                .map(|k| {
+                    let b: Vec<u8> = (0..bytes_per_sample).map(|j| row[j * n + k]).collect();
+                    f32::from_be_bytes([b[0], b[1], b[2], b[3]])
+                })
+                .collect();
+        }
+
+        let mut values: Vec<i64> = Vec::with_capacity(row.len() / bytes_per_sample);
+        let mut floats: Vec<f32> = Vec::new();
+        for b in row.chunks_exact(bytes_per_sample) {
+            match (bytes_per_sample, sample_format) {
+                (2, 1) => values.push(if self.little_endian { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) } as i64),
+                (2, _) => values.push(if self.little_endian { i16::from_le_bytes([b[0], b[1]]) } else { i16::from_be_bytes([b[0], b[1]]) } as i64),
+                (_, 3) => {
+                    let b = [b[0], b[1], b[2], b[3]];
+                    floats.push(if self.little_endian { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) });
+                },
+                (_, 1) => values.push(if self.little_endian { u32::from_le_bytes([b[0], b[1], b[2], b[3]]) } else { u32::from_be_bytes([b[0], b[1], b[2], b[3]]) } as i64),
+                _ => values.push(if self.little_endian { i32::from_le_bytes([b[0], b[1], b[2], b[3]]) } else { i32::from_be_bytes([b[0], b[1], b[2], b[3]]) } as i64),
+            }
+        }
+        if sample_format == 3 {
+            return floats;
+        }
+
+        if predictor == 2 {
+            // horizontal differencing, wrapping at the sample size:
+            let modulus = 1i64 << (bytes_per_sample * 8);
+            for i in 1..values.len() {
+                values[i] = (values[i] + values[i - 1]).rem_euclid(modulus);
+            }
+            if sample_format == 2 {
+                for v in values.iter_mut() {
+                    if *v >= modulus / 2 {
+                        *v -= modulus;
+                    }
+                }
+            }
+        }
+
+        values.into_iter().map(|v| v as f32).collect()
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Terrain elevation from 1x1 deg DEM tiles in a local directory: SRTM .hgt (also gzipped) or GeoTIFF,
+/// e.g. N49E016.hgt or Copernicus_DSM_COG_10_N49_00_E016_00_DEM.tif.
+/// Tiles are loaded on demand and the most recently used ones are kept in memory - 4 by default, see set_cache_size().
+pub struct DemElevationProvider {
+    tile_paths: HashMap<(i32, i32), PathBuf>,   // by the south-west corner [deg]
+    tiles: HashMap<(i32, i32), Option<ElevationGrid>>,  // None for tiles which failed to load
+    lru: VecDeque<(i32, i32)>,  // least recently used first
+    cache_size: usize,
+}
+
+impl DemElevationProvider {
+    /// Indexes the tiles found in the directory.
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let name_re = Regex::new(r"([NS])(\d{2})(?:_00)?_?([EW])(\d{3})").unwrap();
+        let mut tile_paths = HashMap::new();
+
+        for entry in fs::read_dir(dir.as_ref())? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let lower = name.to_lowercase();
+            if !(lower.ends_with(".hgt") || lower.ends_with(".hgt.gz") || lower.ends_with(".tif") || lower.ends_with(".tiff")) {
+                continue;
+            }
+
+            if let Some(caps) = name_re.captures(&name) {
+                let lat: i32 = caps[2].parse().unwrap();
+                let lon: i32 = caps[4].parse().unwrap();
+                let lat = if &caps[1] == "S" { -lat } else { lat };
+                let lon = if &caps[3] == "W" { -lon } else { lon };
+                tile_paths.insert((lat, lon), path);
+            }
+        }
+        info!("Found {} terrain tiles in '{}'", tile_paths.len(), dir.as_ref().display());
+
+        Ok(Self { tile_paths, tiles: HashMap::new(), lru: VecDeque::new(), cache_size: DEFAULT_CACHE_SIZE })
+    }
+
+    /// Maximum number of tiles kept in memory. A 1" tile (e.g. Copernicus GLO-30) takes about 50 MB, a 3" one about 6 MB.
+    pub fn set_cache_size(&mut self, cache_size: usize) {
+        self.cache_size = cache_size.max(1);
+    }
+
+    pub fn num_tiles(&self) -> usize {
+        self.tile_paths.len()
+    }
+
+    fn load_tile(path: &Path, lat: i32, lon: i32) -> io::Result<ElevationGrid> {
+        let mut data = fs::read(path)?;
+        if data.starts_with(&[0x1f, 0x8b]) {
+            let mut buf = Vec::new();
+            GzDecoder::new(&data[..]).read_to_end(&mut buf)?;
+            data = buf;
+        }
+
+        if data.starts_with(b"II") || data.starts_with(b"MM") {
+            TiffReader::new(&data)?.read_grid()
+        } else {
+            read_hgt(&data, lat, lon)
+        }
+    }
+
+    fn tile(&mut self, key: (i32, i32)) -> Option<&ElevationGrid> {
+        if self.tiles.contains_key(&key) {
+            if let Some(pos) = self.lru.iter().position(|k| *k == key) {
+                self.lru.remove(pos);
+            }
+        } else {
+            let path = self.tile_paths.get(&key)?;
+            let tile = match Self::load_tile(path, key.0, key.1) {
+                Ok(tile) => Some(tile),
+                Err(e) => {
+                    warn!("Cannot load terrain tile '{}': {}", path.display(), e);
+                    None
+                },
+            };
+            self.tiles.insert(key, tile);
+
+            while self.lru.len() >= self.cache_size {
+                if let Some(old) = self.lru.pop_front() {
+                    self.tiles.remove(&old);
+                }
+            }
+        }
+        self.lru.push_back(key);
+
+        self.tiles.get(&key).and_then(|t| t.as_ref())
+    }
+}
+
+impl ElevationProvider for DemElevationProvider {
+    fn elevation(&mut self, lat: f64, lon: f64) -> Option<f64> {
+        let key = (lat.floor() as i32, lon.floor() as i32);
+
+        self.tile(key).and_then(|tile| tile.elevation(lat, lon))
+    }
+}
@@ -0,0 +1,676 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use crate::data_structures::{AircraftBeacon, AircraftType, Observer, DEG_PER_SEC_PER_ROT, MIN_TRACK_DISTANCE};
+use crate::geodesy::{destination_point, haversine_distance, initial_bearing};
+
+
+const MIN_CIRCLING_TURN_RATE: f64 = 5.0;    // [deg/s] abs; slower turns are not thermalling
+const CIRCLING_EXIT_TIME: i64 = 10;         // [s] of flying straight which ends the circling
+const MIN_CIRCLING_TURN: f64 = 360.0;       // [deg] a full turn is needed to call it circling
+const MAX_FIX_INTERVAL: i64 = 30;           // [s] longer gap ends the circling
+const MAX_HEADING_INTERVAL: i64 = 5;        // [s] over longer intervals the heading change is ambiguous
+const MAX_TURN_DISAGREEMENT: f64 = 120.0;   // [deg] heading change this far off the reported turn rate is aliased
+
+const MIN_THERMAL_CLIMB: f64 = 0.2;         // [m/s] circling with less climb is not a thermal
+const DEFAULT_MAX_AGE: i64 = 20 * 60;       // [s] thermals without a detection for longer are forgotten
+const DEFAULT_MERGE_RADIUS: f64 = 800.0;    // [m] detections this close to a thermal (moved by its drift) belong to it
+const DEFAULT_MAP_CELL_SIZE: f64 = 0.01;    // [deg] ~1 km
+const EXPIRE_INTERVAL: i64 = 10;            // [s]
+
+/// Horizontal movement, e.g. of a thermal with the wind.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drift {
+    pub direction: f64,     // [deg] where it moves to
+    pub speed: f64,         // [m/s]
+}
+
+impl Drift {
+    /// Vector average of the drifts.
+    pub fn average(drifts: &[Drift]) -> Option<Drift> {
+        if drifts.is_empty() {
+            return None;
+        }
+
+        let (east, north) = drifts.iter().fold((0.0, 0.0), |(e, n), d| {
+            let (sin, cos) = d.direction.to_radians().sin_cos();
+            (e + d.speed * sin, n + d.speed * cos)
+        });
+        let (east, north) = (east / drifts.len() as f64, north / drifts.len() as f64);
+
+        Some(Drift { direction: east.atan2(north).to_degrees().rem_euclid(360.0), speed: east.hypot(north) })
+    }
+}
+
+/// Fixes of one aircraft turning continuously in one direction. The full turns, position and altitude range are
+/// kept up to date fix by fix.
+#[derive(Debug, Clone)]
+pub struct CirclingSegment {
+    pub id: String,
+    pub fixes: Vec<AircraftBeacon>,
+    pub total_turn: f64,        // [deg] positive to the right
+    turn_starts: Vec<usize>,    // index of the first fix of each full turn and of the turn in progress
+    turn_start_total: f64,      // [deg] total turn at the start of the turn in progress
+    turn_centres: Vec<(i64, f64, f64)>,     // of the full turns
+    lat_sum: f64,
+    lon_sum: f64,
+    altitude_range: (i32, i32), // [m] MSL
+}
+
+impl CirclingSegment {
+    fn new(first: &AircraftBeacon) -> Self {
+        Self {
+            id: first.callsign(),
+            fixes: vec![first.clone()],
+            total_turn: 0.0,
+            turn_starts: vec![0],
+            turn_start_total: 0.0,
+            turn_centres: Vec::new(),
+            lat_sum: first.lat,
+            lon_sum: first.lon,
+            altitude_range: (first.altitude_msl, first.altitude_msl),
+        }
+    }
+
+    fn push(&mut self, beacon: &AircraftBeacon, turn: f64) {
+        self.total_turn += turn;
+        self.fixes.push(beacon.clone());
+        self.lat_sum += beacon.lat;
+        self.lon_sum += beacon.lon;
+        self.altitude_range = (self.altitude_range.0.min(beacon.altitude_msl), self.altitude_range.1.max(beacon.altitude_msl));
+
+        if (self.total_turn - self.turn_start_total).abs() >= 360.0 {
+            let start = self.turn_starts[self.turn_starts.len() - 1];
+            let turn = &self.fixes[start..self.fixes.len() - 1];
+            let (lat, lon) = Self::mean_position(turn);
+            let ts = turn.iter().map(|b| b.ts).sum::<i64>() / turn.len() as i64;
+            self.turn_centres.push((ts, lat, lon));
+
+            self.turn_starts.push(self.fixes.len() - 1);
+            self.turn_start_total = self.total_turn;
+        }
+    }
+
+    /// At least one full turn.
+    pub fn is_circling(&self) -> bool {
+        self.total_turn.abs() >= MIN_CIRCLING_TURN
+    }
+
+    pub fn start_ts(&self) -> i64 {
+        self.fixes.first().map(|b| b.ts).unwrap_or(0)
+    }
+
+    pub fn end_ts(&self) -> i64 {
+        self.fixes.last().map(|b| b.ts).unwrap_or(0)
+    }
+
+    /// [s]
+    pub fn duration(&self) -> i64 {
+        self.end_ts() - self.start_ts()
+    }
+
+    pub fn num_turns(&self) -> f64 {
+        self.total_turn.abs() / 360.0
+    }
+
+    /// Average climb rate over the segment [m/s].
+    pub fn climb_rate(&self) -> f64 {
+        match (self.fixes.first(), self.fixes.last()) {
+            (Some(first), Some(last)) if last.ts > first.ts => (last.altitude_msl - first.altitude_msl) as f64 / (last.ts - first.ts) as f64,
+            _ => 0.0,
+        }
+    }
+
+    /// Lowest and highest altitude (MSL) in the segment [m].
+    pub fn altitude_range(&self) -> (i32, i32) {
+        self.altitude_range
+    }
+
+    /// Mean position of the fixes (lat, lon).
+    pub fn centre(&self) -> (f64, f64) {
+        let n = self.fixes.len() as f64;
+
+        (self.lat_sum / n, self.lon_sum / n)
+    }
+
+    fn mean_position(fixes: &[AircraftBeacon]) -> (f64, f64) {
+        let n = fixes.len().max(1) as f64;
+        let lat = fixes.iter().map(|b| b.lat).sum::<f64>() / n;
+        let lon = fixes.iter().map(|b| b.lon).sum::<f64>() / n;
+
+        (lat, lon)
+    }
+
+    /// Fixes of the successive full turns (the last fix of a turn is the first one of the next).
+    pub fn turns(&self) -> Vec<&[AircraftBeacon]> {
+        self.turn_starts.windows(2).map(|w| &self.fixes[w[0]..=w[1]]).collect()
+    }
+
+    /// Fixes of the full turn the last fix has completed, if it has.
+    pub fn completed_turn(&self) -> Option<&[AircraftBeacon]> {
+        match self.turn_starts[..] {
+            [.., start, end] if end == self.fixes.len() - 1 => Some(&self.fixes[start..=end]),
+            _ => None,
+        }
+    }
+
+    /// Centres of the successive full turns as (ts, lat, lon).
+    pub fn turn_centres(&self) -> &[(i64, f64, f64)] {
+        &self.turn_centres
+    }
+
+    /// Movement of the circles from the first to the last full turn; None with less than two turns.
+    pub fn drift(&self) -> Option<Drift> {
+        let centres = self.turn_centres();
+        let (first, last) = (centres.first()?, centres.last()?);
+        if last.0 <= first.0 {
+            return None;
+        }
+
+        let distance = haversine_distance(first.1, first.2, last.1, last.2);
+        Some(Drift { direction: initial_bearing(first.1, first.2, last.1, last.2), speed: distance / (last.0 - first.0) as f64 })
+    }
+}
+
+struct CirclingState {
+    last: AircraftBeacon,
+    last_heading: Option<f64>,  // [deg]
+    segment: Option<CirclingSegment>,
+    straight_since: Option<i64>,
+}
+
+/// Finds the circling segments in the tracks of the aircraft: sustained turning in one direction (from the course
+/// changes, or the reported turn rate where the fixes are too sparse or the course change disagrees with it) of at
+/// least one full turn.
+pub struct CirclingDetector {
+    aircraft: HashMap<String, CirclingState>,
+}
+
+impl CirclingDetector {
+    pub fn new() -> Self {
+        Self { aircraft: HashMap::new() }
+    }
+
+    fn heading(last: &AircraftBeacon, beacon: &AircraftBeacon) -> Option<f64> {
+        if let Some(course) = beacon.course {
+            return Some(course as f64);
+        }
+
+        if haversine_distance(last.lat, last.lon, beacon.lat, beacon.lon) >= MIN_TRACK_DISTANCE {
+            Some(initial_bearing(last.lat, last.lon, beacon.lat, beacon.lon))
+        } else {
+            None
+        }
+    }
+
+    /// Processes the next fix of the aircraft. Returns the circling segment the fix has ended, if any.
+    pub fn process(&mut self, beacon: &AircraftBeacon) -> Option<CirclingSegment> {
+        let id = beacon.callsign();
+        let state = match self.aircraft.get_mut(&id) {
+            Some(state) => state,
+            None => {
+                self.aircraft.insert(id, CirclingState { last: beacon.clone(), last_heading: beacon.course.map(|c| c as f64), segment: None, straight_since: None });
+                return None;
+            },
+        };
+
+        let dt = beacon.ts - state.last.ts;
+        if dt <= 0 {
+            return None;
+        }
+
+        let heading = Self::heading(&state.last, beacon);
+        let heading_turn = match (state.last_heading, heading) {
+            (Some(h0), Some(h1)) if dt <= MAX_HEADING_INTERVAL => Some((h1 - h0 + 540.0).rem_euclid(360.0) - 180.0),
+            _ => None,
+        };
+        let reported_turn = beacon.turn_rate.map(|rot| rot * DEG_PER_SEC_PER_ROT * dt as f64);
+        let turn = match (heading_turn, reported_turn) {
+            // more than half a turn between the fixes looks like a turn the other way round:
+            (Some(heading_turn), Some(reported_turn)) if (heading_turn - reported_turn).abs() > MAX_TURN_DISAGREEMENT => Some(reported_turn),
+            (Some(heading_turn), _) => Some(heading_turn),
+            (None, reported_turn) => reported_turn,
+        };
+
+        let mut finished = None;
+        if dt > MAX_FIX_INTERVAL {
+            finished = state.segment.take();
+            state.straight_since = None;
+        } else if let Some(turn) = turn {
+            let turning = (turn / dt as f64).abs() >= MIN_CIRCLING_TURN_RATE;
+            let same_direction = state.segment.as_ref().map(|s| s.total_turn * turn >= 0.0).unwrap_or(true);
+
+            if turning && same_direction {
+                state.segment.get_or_insert_with(|| CirclingSegment::new(&state.last)).push(beacon, turn);
+                state.straight_since = None;
+            } else if turning {     // reversed the turn
+                finished = state.segment.take();
+                let mut segment = CirclingSegment::new(&state.last);
+                segment.push(beacon, turn);
+                state.segment = Some(segment);
+                state.straight_since = None;
+            } else if state.segment.is_some() {
+                let straight_since = *state.straight_since.get_or_insert(state.last.ts);
+                if beacon.ts - straight_since >= CIRCLING_EXIT_TIME {
+                    finished = state.segment.take();
+                    state.straight_since = None;
+                }
+            }
+        }
+
+        state.last = beacon.clone();
+        state.last_heading = heading.or(state.last_heading);
+
+        finished.filter(|s| s.is_circling())
+    }
+
+    /// The segment the aircraft is circling in right now (at least one full turn done).
+    pub fn current(&self, id: &str) -> Option<&CirclingSegment> {
+        self.aircraft.get(id).and_then(|s| s.segment.as_ref()).filter(|s| s.is_circling())
+    }
+
+    /// Forgets the aircraft not heard since `now_ts - MAX_FIX_INTERVAL`. Returns their unfinished circling segments.
+    pub fn expire(&mut self, now_ts: i64) -> Vec<CirclingSegment> {
+        let silent_ids: Vec<String> = self.aircraft.iter()
+            .filter(|(_, s)| s.last.ts < now_ts - MAX_FIX_INTERVAL)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        silent_ids.iter()
+            .filter_map(|id| self.aircraft.remove(id))
+            .filter_map(|s| s.segment)
+            .filter(|s| s.is_circling())
+            .collect()
+    }
+}
+
+impl Default for CirclingDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Thermal found by one aircraft circling in it.
+#[derive(Debug, Clone)]
+pub struct ThermalDetection {
+    pub aircraft_id: String,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub lat: f64,
+    pub lon: f64,
+    pub climb_rate: f64,    // [m/s] average
+    pub base: i32,          // [m] MSL, lowest altitude of the circling
+    pub top: i32,           // [m] MSL, highest altitude of the circling
+    pub drift: Option<Drift>,
+}
+
+impl ThermalDetection {
+    pub fn from_segment(segment: &CirclingSegment) -> Self {
+        let (lat, lon) = segment.centre();
+        let (base, top) = segment.altitude_range();
+
+        Self {
+            aircraft_id: segment.id.clone(),
+            start_ts: segment.start_ts(),
+            end_ts: segment.end_ts(),
+            lat,
+            lon,
+            climb_rate: segment.climb_rate(),
+            base,
+            top,
+            drift: segment.drift(),
+        }
+    }
+
+    /// [s]
+    pub fn duration(&self) -> i64 {
+        self.end_ts - self.start_ts
+    }
+}
+
+/// Thermal as used by one or more aircraft.
+#[derive(Debug, Clone)]
+pub struct Thermal {
+    pub id: u64,
+    pub lat: f64,           // centre found by the most recent detection
+    pub lon: f64,
+    pub climb_rate: f64,    // [m/s] average of the detections weighted by their duration
+    pub base: i32,          // [m] MSL
+    pub top: i32,           // [m] MSL
+    pub drift: Option<Drift>,
+    pub first_ts: i64,
+    pub last_ts: i64,       // end of the most recent detection
+    pub detections: Vec<ThermalDetection>,
+}
+
+impl Thermal {
+    fn new(id: u64, detection: ThermalDetection) -> Self {
+        let mut thermal = Self {
+            id,
+            lat: detection.lat,
+            lon: detection.lon,
+            climb_rate: 0.0,
+            base: 0,
+            top: 0,
+            drift: None,
+            first_ts: 0,
+            last_ts: 0,
+            detections: vec![detection],
+        };
+        thermal.update();
+
+        thermal
+    }
+
+    fn update(&mut self) {
+        let latest = match self.detections.iter().max_by_key(|d| d.end_ts) {
+            Some(latest) => latest,
+            None => return,
+        };
+        self.lat = latest.lat;
+        self.lon = latest.lon;
+        self.last_ts = latest.end_ts;
+        self.first_ts = self.detections.iter().map(|d| d.start_ts).min().unwrap_or(self.last_ts);
+        self.base = self.detections.iter().map(|d| d.base).min().unwrap_or(0);
+        self.top = self.detections.iter().map(|d| d.top).max().unwrap_or(0);
+
+        let duration: i64 = self.detections.iter().map(|d| d.duration().max(1)).sum();
+        self.climb_rate = self.detections.iter().map(|d| d.climb_rate * d.duration().max(1) as f64).sum::<f64>() / duration as f64;
+
+        let drifts: Vec<Drift> = self.detections.iter().filter_map(|d| d.drift).collect();
+        self.drift = Drift::average(&drifts);
+    }
+
+    /// Where the centre is expected at the given time, moved with the drift (lat, lon).
+    pub fn position_at(&self, ts: i64) -> (f64, f64) {
+        match self.drift {
+            Some(drift) if ts > self.last_ts => destination_point(self.lat, self.lon, drift.direction, drift.speed * (ts - self.last_ts) as f64),
+            _ => (self.lat, self.lon),
+        }
+    }
+
+    /// Time since the last detection [s].
+    pub fn age(&self, now_ts: i64) -> i64 {
+        now_ts - self.last_ts
+    }
+
+    /// Ids of the aircraft which circled in the thermal.
+    pub fn aircraft(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.detections.iter().map(|d| d.aircraft_id.clone()).collect();
+        ids.sort();
+        ids.dedup();
+
+        ids
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClimbCell {
+    pub lat: f64,           // centre of the cell
+    pub lon: f64,
+    pub max_climb_rate: f64,    // [m/s]
+    pub num_detections: u32,
+    climb_sum: f64,         // [m/s * s]
+    duration: i64,          // [s]
+}
+
+impl ClimbCell {
+    /// Average climb rate of the detections weighted by their duration [m/s].
+    pub fn climb_rate(&self) -> f64 {
+        self.climb_sum / self.duration.max(1) as f64
+    }
+}
+
+/// Climb rates of the thermal detections accumulated on a lat/lon grid.
+#[derive(Debug, Clone)]
+pub struct ClimbMap {
+    cell_size: f64,     // [deg]
+    cells: HashMap<(i32, i32), ClimbCell>,
+}
+
+impl ClimbMap {
+    pub const CSV_HEADER: &'static str = "lat;lon;climb_rate;max_climb_rate;detections";
+
+    pub fn new(cell_size: f64) -> Self {
+        Self { cell_size, cells: HashMap::new() }
+    }
+
+    pub fn cell_size(&self) -> f64 {
+        self.cell_size
+    }
+
+    pub fn add(&mut self, detection: &ThermalDetection) {
+        let key = ((detection.lat / self.cell_size).floor() as i32, (detection.lon / self.cell_size).floor() as i32);
+        let cell_size = self.cell_size;
+        let cell = self.cells.entry(key).or_insert_with(|| ClimbCell {
+            lat: (key.0 as f64 + 0.5) * cell_size,
+            lon: (key.1 as f64 + 0.5) * cell_size,
+            max_climb_rate: f64::MIN,
+            num_detections: 0,
+            climb_sum: 0.0,
+            duration: 0,
+        });
+
+        let duration = detection.duration().max(1);
+        cell.climb_sum += detection.climb_rate * duration as f64;
+        cell.duration += duration;
+        cell.max_climb_rate = cell.max_climb_rate.max(detection.climb_rate);
+        cell.num_detections += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    /// The cells with detections, from the south-west.
+    pub fn cells(&self) -> Vec<&ClimbCell> {
+        let mut keys: Vec<&(i32, i32)> = self.cells.keys().collect();
+        keys.sort();
+
+        keys.iter().map(|k| &self.cells[*k]).collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(Self::CSV_HEADER);
+        csv.push('\n');
+        for cell in self.cells() {
+            csv.push_str(&format!("{:.5};{:.5};{:.1};{:.1};{}\n", cell.lat, cell.lon, cell.climb_rate(), cell.max_climb_rate, cell.num_detections));
+        }
+
+        csv
+    }
+
+    pub fn to_json_str(&self) -> String {
+        let js: Vec<serde_json::Value> = self.cells().iter().map(|c| json!({
+            "lat": format!("{:.5}", c.lat),
+            "lon": format!("{:.5}", c.lon),
+            "climb_rate": format!("{:.1}", c.climb_rate()),
+            "max_climb_rate": format!("{:.1}", c.max_climb_rate),
+            "detections": c.num_detections,
+        })).collect();
+
+        serde_json::Value::Array(js).to_string()
+    }
+}
+
+fn is_soaring(aircraft_type: &AircraftType) -> bool {
+    matches!(aircraft_type, AircraftType::Glider | AircraftType::HangGlider | AircraftType::Paraglider)
+}
+
+/// Live thermal map: gliders (incl. hang gliders and paragliders) circling with a positive climb mark thermals.
+/// Detections of several aircraft close to each other (taking the drift of the thermal into account) are merged into
+/// one thermal. Thermals not confirmed for the max age are dropped; the finished detections are also accumulated
+/// in a gridded climb map.
+pub struct ThermalDetector {
+    circling: CirclingDetector,
+    thermals: Vec<Thermal>,
+    next_thermal_id: u64,
+    climb_map: ClimbMap,
+    max_age: i64,           // [s]
+    merge_radius: f64,      // [m]
+    clock_ts: i64,
+    last_expire_ts: i64,
+}
+
+impl ThermalDetector {
+    pub fn new() -> Self {
+        Self {
+            circling: CirclingDetector::new(),
+            thermals: Vec::new(),
+            next_thermal_id: 1,
+            climb_map: ClimbMap::new(DEFAULT_MAP_CELL_SIZE),
+            max_age: DEFAULT_MAX_AGE,
+            merge_radius: DEFAULT_MERGE_RADIUS,
+            clock_ts: 0,
+            last_expire_ts: 0,
+        }
+    }
+
+    /// Thermals without a detection for this long are dropped [s].
+    pub fn set_max_age(&mut self, max_age: i64) {
+        self.max_age = max_age;
+    }
+
+    /// Detections this close to a thermal belong to it [m].
+    pub fn set_merge_radius(&mut self, merge_radius: f64) {
+        self.merge_radius = merge_radius;
+    }
+
+    /// Cell size of the climb map [deg]; clears the map.
+    pub fn set_map_cell_size(&mut self, cell_size: f64) {
+        self.climb_map = ClimbMap::new(cell_size);
+    }
+
+    pub fn ingest(&mut self, beacon: AircraftBeacon) {
+        if beacon.ts > self.clock_ts {
+            self.clock_ts = beacon.ts;
+        }
+
+        if is_soaring(&beacon.aircraft_type) {
+            if let Some(segment) = self.circling.process(&beacon) {
+                self.finish(&segment);
+            }
+
+            // the live detection is updated with every full turn:
+            let current = self.circling.current(&beacon.callsign())
+                .filter(|s| s.completed_turn().is_some())
+                .map(ThermalDetection::from_segment);
+            if let Some(detection) = current {
+                if detection.climb_rate >= MIN_THERMAL_CLIMB {
+                    self.add_detection(detection);
+                }
+            }
+        }
+
+        if self.clock_ts >= self.last_expire_ts + EXPIRE_INTERVAL {
+            self.expire(self.clock_ts);
+        }
+    }
+
+    fn finish(&mut self, segment: &CirclingSegment) {
+        let detection = ThermalDetection::from_segment(segment);
+        if detection.climb_rate >= MIN_THERMAL_CLIMB {
+            self.climb_map.add(&detection);
+            self.add_detection(detection);
+        } else {    // circling in sink - withdraw its live detection
+            self.remove_detection(&detection.aircraft_id, detection.start_ts);
+        }
+    }
+
+    fn remove_detection(&mut self, aircraft_id: &str, start_ts: i64) {
+        for thermal in self.thermals.iter_mut() {
+            let len = thermal.detections.len();
+            thermal.detections.retain(|d| !(d.aircraft_id == aircraft_id && d.start_ts == start_ts));
+            if thermal.detections.len() != len {
+                thermal.update();
+            }
+        }
+        self.thermals.retain(|t| !t.detections.is_empty());
+    }
+
+    /// Adds the detection to the thermal it belongs to (replacing the previous state of the same circling) or
+    /// starts a new thermal.
+    fn add_detection(&mut self, detection: ThermalDetection) {
+        let same = |d: &ThermalDetection| d.aircraft_id == detection.aircraft_id && d.start_ts == detection.start_ts;
+        if let Some(thermal) = self.thermals.iter_mut().find(|t| t.detections.iter().any(same)) {
+            if let Some(d) = thermal.detections.iter_mut().find(|d| same(d)) {
+                *d = detection;
+            }
+            thermal.update();
+            return;
+        }
+
+        let nearest = self.thermals.iter_mut()
+            .map(|t| {
+                let (lat, lon) = t.position_at(detection.end_ts);
+                (haversine_distance(lat, lon, detection.lat, detection.lon), t)
+            })
+            .filter(|(dist, _)| *dist <= self.merge_radius)
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        match nearest {
+            Some((_, thermal)) => {
+                thermal.detections.push(detection);
+                thermal.update();
+            },
+            None => {
+                self.thermals.push(Thermal::new(self.next_thermal_id, detection));
+                self.next_thermal_id += 1;
+            },
+        }
+    }
+
+    /// Drops the thermals older than the max age and finishes the circling of aircraft no longer heard.
+    pub fn expire(&mut self, now_ts: i64) {
+        self.last_expire_ts = now_ts;
+
+        for segment in self.circling.expire(now_ts) {
+            self.finish(&segment);
+        }
+
+        let max_age = self.max_age;
+        self.thermals.retain(|t| t.age(now_ts) <= max_age);
+    }
+
+    /// Current thermals, strongest first.
+    pub fn thermals(&self) -> Vec<&Thermal> {
+        let mut thermals: Vec<&Thermal> = self.thermals.iter().filter(|t| t.age(self.clock_ts) <= self.max_age).collect();
+        thermals.sort_by(|a, b| b.climb_rate.total_cmp(&a.climb_rate));
+
+        thermals
+    }
+
+    /// Time of the most recent beacon seen [s].
+    pub fn clock_ts(&self) -> i64 {
+        self.clock_ts
+    }
+
+    pub fn climb_map(&self) -> &ClimbMap {
+        &self.climb_map
+    }
+
+    pub fn climb_map_mut(&mut self) -> &mut ClimbMap {
+        &mut self.climb_map
+    }
+}
+
+impl Default for ThermalDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for ThermalDetector {
+    fn notify(&mut self, beacon: AircraftBeacon) {
+        self.ingest(beacon);
+    }
+}
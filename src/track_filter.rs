@@ -0,0 +1,210 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::data_structures::{AircraftBeacon, AircraftType, Observer};
+use crate::geodesy::{haversine_distance, METERS_PER_DEG_LAT};
+
+
+const MAX_CLIMB_RATE: f64 = 50.0;       // [m/s] abs; faster altitude changes are spikes
+const MAX_REJECTED_IN_ROW: u32 = 3;     // then the aircraft is really elsewhere (or the last accepted fix was the bad one)
+const MIN_JUMP_DISTANCE: f64 = 200.0;   // [m] shorter moves are never rejected (GPS noise around a parked aircraft)
+const POSITION_NOISE: f64 = 10.0;       // [m] std dev of the received positions
+const ALTITUDE_NOISE: f64 = 10.0;       // [m] std dev of the received altitudes
+const HORIZONTAL_ACCELERATION: f64 = 2.0;   // [m/s^2] std dev of the process noise
+const VERTICAL_ACCELERATION: f64 = 1.0;     // [m/s^2]
+
+/// Fastest plausible ground speed of the aircraft type [km/h].
+fn max_speed(aircraft_type: &AircraftType) -> f64 {
+    match aircraft_type {
+        AircraftType::Paraglider | AircraftType::HangGlider | AircraftType::Baloon | AircraftType::Parachute => 150.0,
+        AircraftType::Glider => 400.0,
+        AircraftType::JetPlane | AircraftType::Unknown | AircraftType::Undefined => 1200.0,
+        _ => 600.0,
+    }
+}
+
+/// Constant velocity Kalman filter of one coordinate [m].
+#[derive(Debug, Clone)]
+struct Kalman1D {
+    position: f64,
+    velocity: f64,
+    cov: [[f64; 2]; 2],
+    measurement_var: f64,
+    acceleration_var: f64,
+}
+
+impl Kalman1D {
+    fn new(position: f64, measurement_noise: f64, acceleration_noise: f64) -> Self {
+        let measurement_var = measurement_noise * measurement_noise;
+        Self {
+            position,
+            velocity: 0.0,
+            cov: [[measurement_var, 0.0], [0.0, 100.0]],    // unknown velocity at start
+            measurement_var,
+            acceleration_var: acceleration_noise * acceleration_noise,
+        }
+    }
+
+    fn update(&mut self, measurement: f64, dt: f64) -> f64 {
+        // predict:
+        self.position += self.velocity * dt;
+        let [[p00, p01], [p10, p11]] = self.cov;
+        let q = self.acceleration_var;
+        let (dt2, dt3, dt4) = (dt * dt, dt * dt * dt, dt * dt * dt * dt);
+        let p00 = p00 + dt * (p10 + p01) + dt2 * p11 + q * dt4 / 4.0;
+        let p01 = p01 + dt * p11 + q * dt3 / 2.0;
+        let p10 = p10 + dt * p11 + q * dt3 / 2.0;
+        let p11 = p11 + q * dt2;
+
+        // correct:
+        let s = p00 + self.measurement_var;
+        let (k0, k1) = (p00 / s, p10 / s);
+        let residual = measurement - self.position;
+        self.position += k0 * residual;
+        self.velocity += k1 * residual;
+        self.cov = [[(1.0 - k0) * p00, (1.0 - k0) * p01], [p10 - k1 * p00, p11 - k1 * p01]];
+
+        self.position
+    }
+}
+
+struct AircraftTrackState {
+    last_accepted: AircraftBeacon,
+    rejected_in_row: u32,
+    origin: (f64, f64),     // (lat, lon) of the local plane of the Kalman filters
+    east: Kalman1D,
+    north: Kalman1D,
+    altitude: Kalman1D,
+}
+
+impl AircraftTrackState {
+    fn new(beacon: &AircraftBeacon) -> Self {
+        Self {
+            last_accepted: beacon.clone(),
+            rejected_in_row: 0,
+            origin: (beacon.lat, beacon.lon),
+            east: Kalman1D::new(0.0, POSITION_NOISE, HORIZONTAL_ACCELERATION),
+            north: Kalman1D::new(0.0, POSITION_NOISE, HORIZONTAL_ACCELERATION),
+            altitude: Kalman1D::new(beacon.altitude as f64, ALTITUDE_NOISE, VERTICAL_ACCELERATION),
+        }
+    }
+
+    fn meters_per_deg_lon(&self) -> f64 {
+        METERS_PER_DEG_LAT * self.origin.0.to_radians().cos().max(0.01)
+    }
+
+    /// Replaces the position & altitude by the filtered ones.
+    fn smooth(&mut self, beacon: &mut AircraftBeacon, dt: f64) {
+        let east = (beacon.lon - self.origin.1) * self.meters_per_deg_lon();
+        let north = (beacon.lat - self.origin.0) * METERS_PER_DEG_LAT;
+
+        let east = self.east.update(east, dt);
+        let north = self.north.update(north, dt);
+        let altitude = self.altitude.update(beacon.altitude as f64, dt);
+
+        let altitude_diff = altitude.round() as i32 - beacon.altitude;
+        beacon.lat = self.origin.0 + north / METERS_PER_DEG_LAT;
+        beacon.lon = self.origin.1 + east / self.meters_per_deg_lon();
+        beacon.altitude += altitude_diff;
+        beacon.altitude_msl += altitude_diff;
+    }
+}
+
+/// Opt-in cleaning of the tracks: fixes implying a physically implausible speed (for the aircraft type) or climb rate
+/// from the last accepted fix are flagged rejected, the accepted ones optionally smoothed by a Kalman filter
+/// (position & altitude). When several fixes in a row get rejected the aircraft is taken to really be at the new
+/// position and its track starts over from there.
+pub struct TrackFilter {
+    aircraft: HashMap<String, AircraftTrackState>,
+    smoothing: bool,
+    drop_rejected: bool,
+    beacon_listener: Option<Rc<RefCell<dyn Observer<AircraftBeacon>>>>,
+}
+
+impl TrackFilter {
+    pub fn new() -> Self {
+        Self { aircraft: HashMap::new(), smoothing: false, drop_rejected: false, beacon_listener: None }
+    }
+
+    /// Kalman smoothing of the accepted fixes (default off).
+    pub fn set_smoothing(&mut self, smoothing: bool) {
+        self.smoothing = smoothing;
+    }
+
+    /// Rejected fixes are not passed on at all instead of being flagged (default false).
+    pub fn set_drop_rejected(&mut self, drop_rejected: bool) {
+        self.drop_rejected = drop_rejected;
+    }
+
+    pub fn set_beacon_listener(&mut self, listener: impl Observer<AircraftBeacon> + 'static) {
+        self.beacon_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    fn is_plausible(last: &AircraftBeacon, beacon: &AircraftBeacon) -> bool {
+        let dt = (beacon.ts - last.ts).max(1) as f64;
+        let distance = haversine_distance(last.lat, last.lon, beacon.lat, beacon.lon);
+        let speed = distance / dt * 3.6;
+        let climb_rate = (beacon.altitude - last.altitude) as f64 / dt;
+
+        (distance < MIN_JUMP_DISTANCE || speed <= max_speed(&beacon.aircraft_type))
+            && climb_rate.abs() <= MAX_CLIMB_RATE
+    }
+
+    /// Flags the beacon rejected or (optionally) smooths it.
+    pub fn process(&mut self, beacon: &mut AircraftBeacon) {
+        let id = beacon.callsign();
+        let state = match self.aircraft.get_mut(&id) {
+            Some(state) => state,
+            None => {
+                self.aircraft.insert(id, AircraftTrackState::new(beacon));
+                return;
+            },
+        };
+
+        if beacon.ts <= state.last_accepted.ts {
+            return;     // duplicate or out of order - left as is
+        }
+
+        if !Self::is_plausible(&state.last_accepted, beacon) {
+            state.rejected_in_row += 1;
+            if state.rejected_in_row < MAX_REJECTED_IN_ROW {
+                beacon.rejected = true;
+                return;
+            }
+            *state = AircraftTrackState::new(beacon);   // start over
+            return;
+        }
+
+        let dt = (beacon.ts - state.last_accepted.ts) as f64;
+        state.rejected_in_row = 0;
+        state.last_accepted = beacon.clone();
+        if self.smoothing {
+            state.smooth(beacon, dt);
+        }
+    }
+
+    /// Forgets the aircraft, e.g. when the tracker reports it as lost.
+    pub fn remove(&mut self, id: &str) {
+        self.aircraft.remove(id);
+    }
+}
+
+impl Default for TrackFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for TrackFilter {
+    fn notify(&mut self, mut beacon: AircraftBeacon) {
+        self.process(&mut beacon);
+        if beacon.rejected && self.drop_rejected {
+            return;
+        }
+
+        if let Some(listener) = self.beacon_listener.as_mut() {
+            listener.borrow_mut().notify(beacon);
+        }
+    }
+}
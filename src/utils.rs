@@ -40,3 +40,44 @@ pub fn from_caps_int<'a>(caps: &'a Captures<'a>, i: usize, default: i64) -> i64
         None => default,
     }
 }
+
+/// Splits a CSV line on commas outside of quotes; the quotes are removed and the fields trimmed. A doubled quote
+/// stands for a quote, as does a quote within a quoted field not followed by a comma or the end of the line.
+pub fn split_csv_line(line: &str, quote: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == quote && in_quotes {
+            if chars.peek() == Some(&quote) {
+                field.push(quote);
+                chars.next();
+            } else if matches!(chars.clone().find(|c| !c.is_whitespace()), None | Some(',')) {
+                in_quotes = false;
+            } else {
+                field.push(c);      // e.g. an apostrophe in 'Pilot's club'
+            }
+        } else if c == quote && field.trim().is_empty() {
+            field.clear();
+            in_quotes = true;
+        } else if c == ',' && !in_quotes {
+            fields.push(std::mem::take(&mut field));
+        } else {
+            field.push(c);
+        }
+    }
+    fields.push(field);
+
+    fields.iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// Quotes the CSV field in double quotes (doubling those within) when it contains the separator, a quote or a line break.
+pub fn quote_csv_field(field: &str, separator: char) -> String {
+    if field.contains([separator, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use chrono::Utc;
+use log::debug;
+
+use crate::data_structures::{AddressType, AircraftBeacon, AircraftType, Observer};
+
+
+const MIN_ALTITUDE: i32 = -500;             // [m] Dead Sea shore is -430 m
+const MAX_TIMESTAMP_AGE: i64 = 10 * 60;     // [s] older than the arrival time
+const MAX_TIMESTAMP_AHEAD: i64 = 60;        // [s] newer than the arrival time
+
+/// Highest plausible (ground speed [km/h], altitude [m]) of the aircraft type.
+fn limits(aircraft_type: &AircraftType) -> (u32, i32) {
+    match aircraft_type {
+        AircraftType::Paraglider | AircraftType::HangGlider => (150, 10_000),
+        AircraftType::Parachute => (400, 12_000),
+        AircraftType::Glider => (400, 16_000),
+        AircraftType::Baloon | AircraftType::Airship => (200, 40_000),
+        AircraftType::Helicopter => (400, 8_000),
+        AircraftType::Obstacle => (0, 9_000),
+        AircraftType::Uav => (400, 20_000),
+        _ => (1200, 20_000),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    LatitudeOutOfRange(f64),
+    LongitudeOutOfRange(f64),
+    /// 0/0 - a failed position parse or a device without GPS fix.
+    NullPosition,
+    CourseOutOfRange(u64),
+    /// [km/h] with the maximum for the aircraft type
+    ImplausibleSpeed { speed: u32, max: u32 },
+    /// [m] with the range for the aircraft type
+    ImplausibleAltitude { altitude: i32, min: i32, max: i32 },
+    /// [s] by how much the beacon timestamp is older (positive) or newer (negative) than the arrival time
+    TimestampSkew(i64),
+    InvalidAddress(String),
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::LatitudeOutOfRange(lat) => write!(f, "latitude {} out of range", lat),
+            ValidationIssue::LongitudeOutOfRange(lon) => write!(f, "longitude {} out of range", lon),
+            ValidationIssue::NullPosition => write!(f, "null position"),
+            ValidationIssue::CourseOutOfRange(course) => write!(f, "course {} out of range", course),
+            ValidationIssue::ImplausibleSpeed { speed, max } => write!(f, "speed {} km/h above {} km/h", speed, max),
+            ValidationIssue::ImplausibleAltitude { altitude, min, max } => write!(f, "altitude {} m out of {}..{} m", altitude, min, max),
+            ValidationIssue::TimestampSkew(skew) => write!(f, "timestamp {} s off the arrival time", skew),
+            ValidationIssue::InvalidAddress(addr) => write!(f, "invalid address '{}'", addr),
+        }
+    }
+}
+
+/// What happens to beacons with validation issues.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValidationPolicy {
+    /// Not passed on.
+    Drop,
+    /// Passed on with the issues in beacon.validation_issues.
+    Flag,
+    /// Passed on unchanged.
+    Pass,
+}
+
+/// Checks the parsed beacons for values out of range or implausible for the aircraft type.
+pub struct BeaconValidator {
+    policy: ValidationPolicy,
+    check_timestamp: bool,
+    beacon_listener: Option<Rc<RefCell<dyn Observer<AircraftBeacon>>>>,
+}
+
+impl BeaconValidator {
+    pub fn new() -> Self {
+        Self { policy: ValidationPolicy::Flag, check_timestamp: true, beacon_listener: None }
+    }
+
+    /// Default Flag.
+    pub fn set_policy(&mut self, policy: ValidationPolicy) {
+        self.policy = policy;
+    }
+
+    /// Whether to compare the beacon timestamps with the arrival time (default true).
+    pub fn set_check_timestamp(&mut self, check_timestamp: bool) {
+        self.check_timestamp = check_timestamp;
+    }
+
+    pub fn set_beacon_listener(&mut self, listener: impl Observer<AircraftBeacon> + 'static) {
+        self.beacon_listener = Some(Rc::new(RefCell::new(listener)));
+    }
+
+    /// Hex address of 6 digits; SafeSky ids are not hex.
+    fn is_valid_address(beacon: &AircraftBeacon) -> bool {
+        if beacon.addr_type == AddressType::SafeSky {
+            return !beacon.addr.is_empty();
+        }
+
+        beacon.addr.len() == 6 && beacon.addr.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    /// All the issues of the beacon; the timestamp is compared with the arrival time [s] when given.
+    pub fn validate(beacon: &AircraftBeacon, arrival_ts: Option<i64>) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        if !(-90.0..=90.0).contains(&beacon.lat) {
+            issues.push(ValidationIssue::LatitudeOutOfRange(beacon.lat));
+        }
+        if !(-180.0..=180.0).contains(&beacon.lon) {
+            issues.push(ValidationIssue::LongitudeOutOfRange(beacon.lon));
+        }
+        if beacon.lat == 0.0 && beacon.lon == 0.0 {
+            issues.push(ValidationIssue::NullPosition);
+        }
+        if let Some(course) = beacon.course.filter(|c| *c > 360) {
+            issues.push(ValidationIssue::CourseOutOfRange(course));
+        }
+
+        let (max_speed, max_altitude) = limits(&beacon.aircraft_type);
+        if beacon.speed > max_speed {
+            issues.push(ValidationIssue::ImplausibleSpeed { speed: beacon.speed, max: max_speed });
+        }
+        if beacon.altitude < MIN_ALTITUDE || beacon.altitude > max_altitude {
+            issues.push(ValidationIssue::ImplausibleAltitude { altitude: beacon.altitude, min: MIN_ALTITUDE, max: max_altitude });
+        }
+
+        if let Some(arrival_ts) = arrival_ts {
+            let skew = arrival_ts - beacon.ts;
+            if !(-MAX_TIMESTAMP_AHEAD..=MAX_TIMESTAMP_AGE).contains(&skew) {
+                issues.push(ValidationIssue::TimestampSkew(skew));
+            }
+        }
+
+        if !Self::is_valid_address(beacon) {
+            issues.push(ValidationIssue::InvalidAddress(beacon.addr.clone()));
+        }
+
+        issues
+    }
+
+    /// Validates the beacon and applies the policy. Returns false when the beacon is to be dropped.
+    /// @param arrival_ts when the beacon was received [s]; None = now
+    pub fn process(&self, beacon: &mut AircraftBeacon, arrival_ts: Option<i64>) -> bool {
+        if self.policy == ValidationPolicy::Pass {
+            return true;
+        }
+
+        let arrival_ts = if self.check_timestamp { Some(arrival_ts.unwrap_or_else(|| Utc::now().timestamp())) } else { None };
+        let issues = Self::validate(beacon, arrival_ts);
+        if issues.is_empty() {
+            return true;
+        }
+
+        match self.policy {
+            ValidationPolicy::Drop => {
+                debug!("Dropping {}: {}", beacon.callsign(), issues.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", "));
+                false
+            },
+            _ => {
+                beacon.validation_issues = issues;
+                true
+            },
+        }
+    }
+}
+
+impl Default for BeaconValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for BeaconValidator {
+    fn notify(&mut self, mut beacon: AircraftBeacon) {
+        if !self.process(&mut beacon, None) {
+            return;
+        }
+
+        if let Some(listener) = self.beacon_listener.as_mut() {
+            listener.borrow_mut().notify(beacon);
+        }
+    }
+}
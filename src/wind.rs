@@ -0,0 +1,320 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::data_structures::{AircraftBeacon, Observer};
+use crate::geodesy::{haversine_distance, initial_bearing};
+use crate::thermals::{CirclingDetector, Drift};
+
+
+const MIN_FIXES_PER_TURN: usize = 6;        // fewer ground speed vectors do not define the circle
+const MAX_WIND_SPEED: f64 = 40.0;           // [m/s] stronger fitted wind is a bad fit
+const MAX_FIT_RESIDUAL: f64 = 3.0;          // [m/s] rms deviation of the ground speeds from the fitted circle
+const DEFAULT_BAND_HEIGHT: i32 = 500;       // [m]
+const DEFAULT_REGION_SIZE: f64 = 0.5;       // [deg]
+const DEFAULT_WINDOW: i64 = 30 * 60;        // [s]
+const EXPIRE_INTERVAL: i64 = 10;            // [s]
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wind {
+    pub direction: f64,     // [deg] where it blows from
+    pub speed: f64,         // [m/s]
+}
+
+impl Wind {
+    /// From the movement of the air (east, north) [m/s].
+    pub fn from_vector(east: f64, north: f64) -> Self {
+        Self { direction: (east.atan2(north).to_degrees() + 180.0).rem_euclid(360.0), speed: east.hypot(north) }
+    }
+
+    /// Movement of the air (east, north) [m/s].
+    pub fn to_vector(&self) -> (f64, f64) {
+        let (sin, cos) = self.direction.to_radians().sin_cos();
+        (-self.speed * sin, -self.speed * cos)
+    }
+
+    /// The wind drifting a circling aircraft (or a thermal) by the drift.
+    pub fn from_drift(drift: &Drift) -> Self {
+        Self { direction: (drift.direction + 180.0).rem_euclid(360.0), speed: drift.speed }
+    }
+
+    /// Vector average of the winds.
+    pub fn average(winds: &[Wind]) -> Option<Wind> {
+        if winds.is_empty() {
+            return None;
+        }
+
+        let (east, north) = winds.iter().map(|w| w.to_vector()).fold((0.0, 0.0), |(e, n), (we, wn)| (e + we, n + wn));
+
+        Some(Self::from_vector(east / winds.len() as f64, north / winds.len() as f64))
+    }
+}
+
+/// Wind found from one full turn of a circling aircraft.
+#[derive(Debug, Clone)]
+pub struct WindSample {
+    pub aircraft_id: String,
+    pub ts: i64,
+    pub lat: f64,
+    pub lon: f64,
+    pub altitude: i32,      // [m] MSL
+    pub wind: Wind,
+}
+
+/// Wind in one altitude band over one region aggregated over the time window.
+#[derive(Debug, Clone)]
+pub struct WindEstimate {
+    pub lat: f64,           // centre of the region
+    pub lon: f64,
+    pub altitude_base: i32, // [m] MSL, bottom of the band
+    pub altitude_top: i32,  // [m] MSL
+    pub wind: Wind,
+    pub num_samples: usize,
+    pub num_aircraft: usize,
+    pub last_ts: i64,
+}
+
+/// Ground velocities (east, north) [m/s] of the fixes; the reported course & speed or, where the course is missing,
+/// the move from the previous fix.
+fn ground_velocities(fixes: &[AircraftBeacon]) -> Vec<(f64, f64)> {
+    let mut velocities = Vec::new();
+    for (i, fix) in fixes.iter().enumerate() {
+        let (track, speed) = match fix.course {
+            Some(course) if fix.speed > 0 => (course as f64, fix.speed as f64 / 3.6),
+            _ if i > 0 && fix.ts > fixes[i - 1].ts => {
+                let prev = &fixes[i - 1];
+                let distance = haversine_distance(prev.lat, prev.lon, fix.lat, fix.lon);
+                (initial_bearing(prev.lat, prev.lon, fix.lat, fix.lon), distance / (fix.ts - prev.ts) as f64)
+            },
+            _ => continue,
+        };
+        let (sin, cos) = track.to_radians().sin_cos();
+        velocities.push((speed * sin, speed * cos));
+    }
+
+    velocities
+}
+
+/// Least squares (Kasa) fit of a circle to the points. Returns (centre_x, centre_y, radius).
+fn fit_circle(points: &[(f64, f64)]) -> Option<(f64, f64, f64)> {
+    // x^2 + y^2 + D x + E y + F = 0 -> normal equations of [x y 1] [D E F]' = -(x^2 + y^2)
+    let mut m = [[0.0; 3]; 3];
+    let mut v = [0.0; 3];
+    for &(x, y) in points {
+        let row = [x, y, 1.0];
+        let rhs = -(x * x + y * y);
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] += row[i] * row[j];
+            }
+            v[i] += row[i] * rhs;
+        }
+    }
+
+    let det = |m: &[[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let d = det(&m);
+    if d.abs() < 1e-9 {
+        return None;
+    }
+
+    // Cramer's rule:
+    let mut solution = [0.0; 3];
+    for (k, value) in solution.iter_mut().enumerate() {
+        let mut mk = m;
+        for i in 0..3 {
+            mk[i][k] = v[i];
+        }
+        *value = det(&mk) / d;
+    }
+
+    let (cx, cy) = (-solution[0] / 2.0, -solution[1] / 2.0);
+    let r2 = cx * cx + cy * cy - solution[2];
+    if r2 <= 0.0 {
+        return None;
+    }
+
+    Some((cx, cy, r2.sqrt()))
+}
+
+/// Wind from the fixes of one full turn: flying a constant airspeed the ground velocities lie on a circle shifted
+/// from the origin by the wind.
+pub fn wind_from_turn(turn: &[AircraftBeacon]) -> Option<Wind> {
+    let velocities = ground_velocities(turn);
+    if velocities.len() < MIN_FIXES_PER_TURN {
+        return None;
+    }
+
+    let (east, north, airspeed) = fit_circle(&velocities)?;
+    let wind_speed = east.hypot(north);
+    if wind_speed > MAX_WIND_SPEED || wind_speed >= airspeed {
+        return None;
+    }
+
+    let residual = (velocities.iter()
+        .map(|(x, y)| ((x - east).hypot(y - north) - airspeed).powi(2))
+        .sum::<f64>() / velocities.len() as f64).sqrt();
+    if residual > MAX_FIT_RESIDUAL {
+        return None;
+    }
+
+    Some(Wind::from_vector(east, north))
+}
+
+/// Wind sample at the mean position and altitude of the turn.
+fn sample_turn(aircraft_id: &str, turn: &[AircraftBeacon]) -> Option<WindSample> {
+    let wind = wind_from_turn(turn)?;
+    let n = turn.len() as f64;
+
+    Some(WindSample {
+        aircraft_id: aircraft_id.to_string(),
+        ts: turn[turn.len() - 1].ts,
+        lat: turn.iter().map(|b| b.lat).sum::<f64>() / n,
+        lon: turn.iter().map(|b| b.lon).sum::<f64>() / n,
+        altitude: (turn.iter().map(|b| b.altitude_msl as f64).sum::<f64>() / n).round() as i32,
+        wind,
+    })
+}
+
+/// Live wind profile from circling aircraft. Every full turn gives a wind sample at its altitude and position;
+/// the samples of the time window are averaged per altitude band and region (lat/lon grid cell).
+pub struct WindEstimator {
+    circling: CirclingDetector,
+    samples: VecDeque<WindSample>,
+    band_height: i32,   // [m]
+    region_size: f64,   // [deg]
+    window: i64,        // [s]
+    clock_ts: i64,
+    last_expire_ts: i64,
+}
+
+impl WindEstimator {
+    pub fn new() -> Self {
+        Self {
+            circling: CirclingDetector::new(),
+            samples: VecDeque::new(),
+            band_height: DEFAULT_BAND_HEIGHT,
+            region_size: DEFAULT_REGION_SIZE,
+            window: DEFAULT_WINDOW,
+            clock_ts: 0,
+            last_expire_ts: 0,
+        }
+    }
+
+    /// Height of the altitude bands [m].
+    pub fn set_band_height(&mut self, band_height: i32) {
+        self.band_height = band_height.max(1);
+    }
+
+    /// Edge of the regions [deg].
+    pub fn set_region_size(&mut self, region_size: f64) {
+        self.region_size = region_size;
+    }
+
+    /// Samples older than this are not used [s].
+    pub fn set_window(&mut self, window: i64) {
+        self.window = window;
+    }
+
+    pub fn ingest(&mut self, beacon: AircraftBeacon) {
+        if beacon.ts > self.clock_ts {
+            self.clock_ts = beacon.ts;
+        }
+
+        // each full turn is sampled by the fix completing it (a finished segment has no turns left):
+        self.circling.process(&beacon);
+        let sample = self.circling.current(&beacon.callsign())
+            .and_then(|segment| segment.completed_turn().and_then(|turn| sample_turn(&segment.id, turn)));
+        if let Some(sample) = sample {
+            self.samples.push_back(sample);
+        }
+
+        if self.clock_ts >= self.last_expire_ts + EXPIRE_INTERVAL {
+            self.expire(self.clock_ts);
+        }
+    }
+
+    /// Drops the samples older than the window and finishes the circling of aircraft no longer heard.
+    pub fn expire(&mut self, now_ts: i64) {
+        self.last_expire_ts = now_ts;
+
+        self.circling.expire(now_ts);
+
+        let window = self.window;
+        self.samples.retain(|s| s.ts >= now_ts - window);
+    }
+
+    /// Time of the most recent beacon seen [s].
+    pub fn clock_ts(&self) -> i64 {
+        self.clock_ts
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &WindSample> {
+        self.samples.iter().filter(move |s| s.ts >= self.clock_ts - self.window)
+    }
+
+    fn key(&self, lat: f64, lon: f64, altitude: i32) -> (i32, i32, i32) {
+        ((lat / self.region_size).floor() as i32, (lon / self.region_size).floor() as i32, altitude.div_euclid(self.band_height))
+    }
+
+    fn estimate(&self, key: (i32, i32, i32), samples: &[&WindSample]) -> Option<WindEstimate> {
+        let winds: Vec<Wind> = samples.iter().map(|s| s.wind).collect();
+        let mut aircraft: Vec<&str> = samples.iter().map(|s| s.aircraft_id.as_str()).collect();
+        aircraft.sort();
+        aircraft.dedup();
+
+        Some(WindEstimate {
+            lat: (key.0 as f64 + 0.5) * self.region_size,
+            lon: (key.1 as f64 + 0.5) * self.region_size,
+            altitude_base: key.2 * self.band_height,
+            altitude_top: (key.2 + 1) * self.band_height,
+            wind: Wind::average(&winds)?,
+            num_samples: samples.len(),
+            num_aircraft: aircraft.len(),
+            last_ts: samples.iter().map(|s| s.ts).max()?,
+        })
+    }
+
+    /// Wind in every region and altitude band with samples in the window.
+    pub fn estimates(&self) -> Vec<WindEstimate> {
+        let mut groups: HashMap<(i32, i32, i32), Vec<&WindSample>> = HashMap::new();
+        for sample in self.samples() {
+            groups.entry(self.key(sample.lat, sample.lon, sample.altitude)).or_default().push(sample);
+        }
+
+        let mut keys: Vec<&(i32, i32, i32)> = groups.keys().collect();
+        keys.sort();
+
+        keys.iter().filter_map(|k| self.estimate(**k, &groups[*k])).collect()
+    }
+
+    /// Wind at the position and altitude [m] MSL, if there are samples in its region and band.
+    pub fn wind_at(&self, lat: f64, lon: f64, altitude: i32) -> Option<WindEstimate> {
+        let key = self.key(lat, lon, altitude);
+        let samples: Vec<&WindSample> = self.samples().filter(|s| self.key(s.lat, s.lon, s.altitude) == key).collect();
+
+        self.estimate(key, &samples)
+    }
+
+    /// Winds in the altitude bands of the region of the position, lowest first.
+    pub fn profile(&self, lat: f64, lon: f64) -> Vec<WindEstimate> {
+        let (lat_idx, lon_idx, _) = self.key(lat, lon, 0);
+
+        self.estimates().into_iter()
+            .filter(|e| self.key(e.lat, e.lon, 0).0 == lat_idx && self.key(e.lat, e.lon, 0).1 == lon_idx)
+            .collect()
+    }
+}
+
+impl Default for WindEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer<AircraftBeacon> for WindEstimator {
+    fn notify(&mut self, beacon: AircraftBeacon) {
+        self.ingest(beacon);
+    }
+}
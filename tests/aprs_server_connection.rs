@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use ogn_client::aprs_server_connection::AprsServerConnection;
+use ogn_client::fake_aprs_server::FakeAprsServer;
+use ogn_client::line_source::LineSource;
+use ogn_client::MyLineListener;
+
+const FLR_LINE: &str = "FLRDDA5BA>APRS,qAS,LKKA:/074548h4821.61N/01708.55E'086/007/A=000617 !W51! id0ADDA5BA -019fpm +0.0rot 6.2dB 0e -1.0kHz gps4x5";
+const ICA_LINE: &str = "ICA484D20>APRS,qAS,LKKA:/074549h4915.02N/01623.40E^301/145/A=003284 !W83! id05484D20 +198fpm +0.0rot 9.2dB 0e +4.9kHz gps2x3";
+
+fn connect(server: &FakeAprsServer) -> AprsServerConnection {
+    let mut connection = AprsServerConnection::new(&server.address(), "tester").unwrap();
+    connection.set_aprs_filter(49.0, 16.0, 100);
+    LineSource::connect(&mut connection);
+
+    connection
+}
+
+/// Reads lines (including the server comments) until `count` of them are collected or the timeout expires.
+fn read_lines(connection: &mut AprsServerConnection, count: usize, timeout: Duration) -> Vec<String> {
+    let start = Instant::now();
+    let mut lines = Vec::new();
+    while lines.len() < count && start.elapsed() < timeout {
+        if let Some(line) = connection.next_line() {
+            lines.push(line);
+        }
+    }
+
+    lines
+}
+
+#[test]
+fn login_handshake_carries_username_and_filter() {
+    let server = FakeAprsServer::start().unwrap();
+    let mut connection = connect(&server);
+    read_lines(&mut connection, 2, Duration::from_secs(5));   // banner + logresp
+
+    let handshakes = server.handshakes();
+    assert_eq!(handshakes.len(), 1);
+    assert!(handshakes[0].starts_with("user tester pass -1 vers"));
+    assert!(handshakes[0].ends_with("filter r/49.0000/16.0000/100"));
+}
+
+#[test]
+fn scripted_lines_are_parsed_into_beacons() {
+    let server = FakeAprsServer::start().unwrap();
+    server.serve_lines(&[FLR_LINE, ICA_LINE]);
+    let mut connection = connect(&server);
+
+    let lines = read_lines(&mut connection, 4, Duration::from_secs(5));
+    let beacon_lines: Vec<&String> = lines.iter().filter(|line| !line.starts_with('#')).collect();
+    assert_eq!(beacon_lines, vec![FLR_LINE, ICA_LINE]);
+
+    let parser = MyLineListener::new();
+    let beacon = parser.parse_beacon_line(beacon_lines[1]).unwrap();
+    assert_eq!(beacon.prefix, "ICA");
+    assert_eq!(beacon.addr, "484D20");
+    assert_eq!(beacon.altitude, 1001);
+}
+
+#[test]
+fn heartbeats_are_passed_as_comments() {
+    let server = FakeAprsServer::start().unwrap();
+    server.set_heartbeat_interval(Some(Duration::from_millis(100)));
+    let mut connection = connect(&server);
+
+    let lines = read_lines(&mut connection, 5, Duration::from_secs(5));
+    assert!(lines.iter().filter(|line| line.starts_with("# aprsc")).count() >= 2);
+}
+
+#[test]
+fn reconnects_after_dropped_connection() {
+    let server = FakeAprsServer::start().unwrap();
+    server.serve_line(FLR_LINE);
+    let mut connection = connect(&server);
+    read_lines(&mut connection, 3, Duration::from_secs(5));
+
+    server.drop_connections();
+    let lines = read_lines(&mut connection, 3, Duration::from_secs(10));
+
+    assert!(server.wait_for_connections(2, Duration::from_secs(1)));
+    assert_eq!(server.handshakes().len(), 2);
+    assert_eq!(lines.last().unwrap(), FLR_LINE);    // the script is served again on the new connection
+}
+
+#[test]
+fn reconnects_after_half_open_connection() {
+    let server = FakeAprsServer::start().unwrap();
+    let mut connection = connect(&server);
+    read_lines(&mut connection, 2, Duration::from_secs(5));
+
+    server.set_half_open(true);
+    read_lines(&mut connection, 1, Duration::from_secs(12));    // the client gives up after its 10s read timeout
+    server.set_half_open(false);
+
+    assert!(server.wait_for_connections(2, Duration::from_secs(5)));
+}
+
+#[test]
+fn slow_link_delivers_all_lines() {
+    let server = FakeAprsServer::start().unwrap();
+    server.set_line_delay(Duration::from_millis(50));
+    server.set_read_delay(Duration::from_millis(50));
+    server.serve_lines(&[FLR_LINE, ICA_LINE, FLR_LINE]);
+    let mut connection = connect(&server);
+
+    let lines = read_lines(&mut connection, 5, Duration::from_secs(5));
+    assert_eq!(lines.iter().filter(|line| !line.starts_with('#')).count(), 3);
+}
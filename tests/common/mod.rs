@@ -0,0 +1,72 @@
+//! Helpers shared by the integration tests of the processing stages.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ogn_client::data_structures::{AddressType, AircraftBeacon, AircraftType, Observer};
+use ogn_client::geodesy::destination_point;
+
+
+/// Listener collecting everything it is notified of.
+pub struct Collector<T>(Rc<RefCell<Vec<T>>>);
+
+impl<T: Clone> Observer<T> for Collector<T> {
+    fn notify(&mut self, item: T) {
+        self.0.borrow_mut().push(item);
+    }
+}
+
+/// A collector to be set as a listener and the items it collects.
+pub fn collector<T>() -> (Collector<T>, Rc<RefCell<Vec<T>>>) {
+    let items = Rc::new(RefCell::new(Vec::new()));
+
+    (Collector(Rc::clone(&items)), items)
+}
+
+/// Glider flying level to the east at 100 km/h; the address type follows the prefix (FLR, ICA or OGN).
+pub fn beacon(ts: i64, callsign: &str, lat: f64, lon: f64, altitude: i32) -> AircraftBeacon {
+    let (prefix, addr) = callsign.split_at(3);
+    let addr_type = match prefix {
+        "ICA" => AddressType::Icao,
+        "OGN" => AddressType::Ogn,
+        _ => AddressType::Flarm,
+    };
+
+    AircraftBeacon::new(ts, prefix.to_string(), addr.to_string(), addr_type, lat, lon, altitude, None,
+        Some(90), 100, Some(0.0), Some(0.0), false, false, AircraftType::Glider, String::new(), None)
+}
+
+/// Fixes every `interval` [s] of a straight flight from the position at the course [deg] and speed [km/h].
+pub fn straight_track(callsign: &str, start_ts: i64, duration: i64, interval: i64, (lat, lon): (f64, f64), course: f64, speed: u32, altitude: i32) -> Vec<AircraftBeacon> {
+    (0..=duration / interval)
+        .map(|i| {
+            let t = i * interval;
+            let (lat, lon) = destination_point(lat, lon, course, speed as f64 / 3.6 * t as f64);
+            let mut b = beacon(start_ts + t, callsign, lat, lon, altitude);
+            b.course = Some(course.round() as u64 % 360);
+            b.speed = speed;
+            b
+        })
+        .collect()
+}
+
+/// Fixes every `interval` [s] of circling clockwise around the centre with the radius [m] and speed [km/h] while
+/// climbing at the climb rate [m/s]; without a reported turn rate.
+pub fn circling_track(callsign: &str, start_ts: i64, duration: i64, interval: i64, (lat, lon): (f64, f64), radius: f64, speed: u32, altitude: i32, climb_rate: f64) -> Vec<AircraftBeacon> {
+    let turn_rate = (speed as f64 / 3.6 / radius).to_degrees();    // [deg/s]
+
+    (0..=duration / interval)
+        .map(|i| {
+            let t = i * interval;
+            let angle = turn_rate * t as f64;   // from the centre, clockwise from north
+            let (lat, lon) = destination_point(lat, lon, angle, radius);
+            let mut b = beacon(start_ts + t, callsign, lat, lon, altitude + (climb_rate * t as f64).round() as i32);
+            b.course = Some(((angle + 90.0).round() as u64) % 360);
+            b.speed = speed;
+            b.climb_rate = Some(climb_rate);
+            b.turn_rate = None;
+            b
+        })
+        .collect()
+}
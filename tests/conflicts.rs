@@ -0,0 +1,93 @@
+mod common;
+
+use ogn_client::conflicts::{AlertLevel, ConflictDetector, ConflictEvent};
+use ogn_client::data_structures::AircraftBeacon;
+use ogn_client::geodesy::destination_point;
+
+use common::{collector, straight_track};
+
+/// Fixes of all the tracks in the order of their timestamps.
+fn merged(tracks: Vec<Vec<AircraftBeacon>>) -> Vec<AircraftBeacon> {
+    let mut beacons: Vec<AircraftBeacon> = tracks.into_iter().flatten().collect();
+    beacons.sort_by_key(|b| b.ts);
+
+    beacons
+}
+
+/// Two aircraft at 100 km/h flying head-on from 1.5 km apart, the second one `height_diff` [m] higher.
+fn head_on(duration: i64, height_diff: i32) -> Vec<AircraftBeacon> {
+    let (lat, lon) = destination_point(49.0, 16.0, 90.0, 1500.0);
+
+    merged(vec![
+        straight_track("FLRDDA5BA", 0, duration, 1, (49.0, 16.0), 90.0, 100, 1000),
+        straight_track("FLRDDA5BB", 0, duration, 1, (lat, lon), 270.0, 100, 1000 + height_diff),
+    ])
+}
+
+#[test]
+fn head_on_pair_raises_rising_alerts() {
+    let mut detector = ConflictDetector::new();
+    let (listener, events) = collector();
+    detector.set_event_listener(listener);
+
+    for beacon in head_on(20, 50) {
+        detector.ingest(beacon);
+    }
+
+    let levels: Vec<AlertLevel> = events.borrow().iter()
+        .filter_map(|e| match e {
+            ConflictEvent::Alert(alert) => Some(alert.level),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(levels, vec![AlertLevel::Low, AlertLevel::Important, AlertLevel::Urgent]);
+    assert_eq!(detector.alert_level("FLRDDA5BB", "FLRDDA5BA"), Some(AlertLevel::Urgent));
+    assert_eq!(detector.active_conflicts().len(), 1);
+}
+
+#[test]
+fn encounter_is_recorded_when_cleared() {
+    let mut detector = ConflictDetector::new();
+    let (listener, events) = collector();
+    detector.set_event_listener(listener);
+
+    for beacon in head_on(60, 50) {
+        detector.ingest(beacon);
+    }
+
+    assert!(detector.active_conflicts().is_empty());
+    assert_eq!(detector.encounters().len(), 1);
+    let encounter = &detector.encounters()[0];
+    assert_eq!((encounter.id1.as_str(), encounter.id2.as_str()), ("FLRDDA5BA", "FLRDDA5BB"));
+    assert_eq!(encounter.max_level, AlertLevel::Urgent);
+    assert!(encounter.min_distance < 100.0, "{} m", encounter.min_distance);
+    assert!(matches!(events.borrow().last(), Some(ConflictEvent::Cleared(_))));
+}
+
+#[test]
+fn vertically_separated_pair_is_no_conflict() {
+    let mut detector = ConflictDetector::new();
+    for beacon in head_on(60, 300) {
+        detector.ingest(beacon);
+    }
+
+    assert!(detector.encounters().is_empty());
+}
+
+#[test]
+fn aircraft_flying_together_are_no_conflict() {
+    let mut detector = ConflictDetector::new();
+    let (listener, events) = collector::<ConflictEvent>();
+    detector.set_event_listener(listener);
+
+    let (lat, lon) = destination_point(49.0, 16.0, 0.0, 60.0);
+    let tracks = vec![
+        straight_track("FLRDDA5BA", 0, 60, 1, (49.0, 16.0), 90.0, 120, 600),
+        straight_track("FLRDDA5BB", 0, 60, 1, (lat, lon), 90.0, 120, 620),
+    ];
+    for beacon in merged(tracks) {
+        detector.ingest(beacon);
+    }
+
+    assert!(events.borrow().is_empty());
+}
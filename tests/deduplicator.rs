@@ -0,0 +1,61 @@
+mod common;
+
+use ogn_client::data_structures::AircraftBeacon;
+use ogn_client::deduplicator::BeaconDeduplicator;
+
+use common::{beacon, collector};
+
+/// Copy of the transmission of FLRDDA5BA at the time heard by the receiver with the signal strength [dB].
+fn copy(ts: i64, lat: f64, receiver: &str, signal_strength: f64) -> AircraftBeacon {
+    let mut b = beacon(ts, "FLRDDA5BA", lat, 16.0, 1000);
+    b.receiver = receiver.to_string();
+    b.signal_strength = Some(signal_strength);
+    b
+}
+
+#[test]
+fn copies_are_merged_with_the_strongest_reception_first() {
+    let (listener, beacons) = collector();
+    let mut deduplicator = BeaconDeduplicator::new();
+    deduplicator.set_beacon_listener(listener);
+
+    deduplicator.ingest(copy(10, 49.0, "LKKA", 5.0));
+    deduplicator.ingest(copy(10, 49.0, "LKTB", 12.0));
+    deduplicator.ingest(copy(10, 49.0, "LKKA", 5.0));
+    assert!(beacons.borrow().is_empty());
+    deduplicator.ingest(copy(13, 49.0, "LKKA", 5.0));
+
+    let beacons = beacons.borrow();
+    assert_eq!(beacons.len(), 1);
+    assert_eq!(beacons[0].receiver, "LKTB");
+    let receivers: Vec<&str> = beacons[0].receptions.iter().map(|r| r.receiver.as_str()).collect();
+    assert_eq!(receivers, vec!["LKTB", "LKKA"]);
+}
+
+#[test]
+fn late_copies_of_emitted_transmissions_are_dropped() {
+    let (listener, beacons) = collector();
+    let mut deduplicator = BeaconDeduplicator::new();
+    deduplicator.set_beacon_listener(listener);
+
+    deduplicator.ingest(copy(10, 49.0, "LKKA", 5.0));
+    deduplicator.ingest(copy(13, 49.0, "LKKA", 5.0));
+    deduplicator.ingest(copy(10, 49.0, "LKTB", 12.0));
+    deduplicator.flush();
+
+    let timestamps: Vec<i64> = beacons.borrow().iter().map(|b| b.ts).collect();
+    assert_eq!(timestamps, vec![10, 13]);
+}
+
+#[test]
+fn aircraft_sharing_an_address_far_apart_are_kept_apart() {
+    let (listener, beacons) = collector();
+    let mut deduplicator = BeaconDeduplicator::new();
+    deduplicator.set_beacon_listener(listener);
+
+    deduplicator.ingest(copy(10, 49.0, "LKKA", 5.0));
+    deduplicator.ingest(copy(10, 49.5, "LKTB", 12.0));
+    deduplicator.flush();
+
+    assert_eq!(beacons.borrow().len(), 2);
+}
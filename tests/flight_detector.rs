@@ -0,0 +1,127 @@
+mod common;
+
+use ogn_client::airfields::{Airfield, AirfieldDatabase};
+use ogn_client::data_structures::{AircraftBeacon, AircraftType};
+use ogn_client::flight_detector::{FlightEvent, FlightPhase, FlightPhaseDetector};
+
+use common::{beacon, collector};
+
+const ID: &str = "FLRDDA5BA";
+
+/// Fix of the aircraft with the given speed [km/h], altitude [m] and agl [m] if known.
+fn fix(ts: i64, speed: u32, altitude: i32, agl: Option<i32>) -> AircraftBeacon {
+    let mut b = beacon(ts, ID, 49.0, 16.0, altitude);
+    b.speed = speed;
+    b.agl = agl;
+    b
+}
+
+fn event_names(events: &[FlightEvent]) -> Vec<&'static str> {
+    events.iter()
+        .map(|e| match e {
+            FlightEvent::TakeOff { .. } => "take-off",
+            FlightEvent::Landing { .. } => "landing",
+            FlightEvent::FlightCompleted { .. } => "completed",
+            FlightEvent::SignalLostInAir { .. } => "lost",
+        })
+        .collect()
+}
+
+#[test]
+fn take_off_and_landing_with_agl_complete_a_flight() {
+    let (listener, events) = collector();
+    let mut detector = FlightPhaseDetector::new();
+    detector.set_event_listener(listener);
+
+    for ts in 0..5 {
+        detector.ingest(fix(ts * 10, 0, 300, Some(0)));
+    }
+    for ts in 5..20 {
+        detector.ingest(fix(ts * 10, 100, 800, Some(500)));
+    }
+    assert_eq!(detector.phase(ID), Some(FlightPhase::Airborne));
+    for ts in 20..25 {
+        detector.ingest(fix(ts * 10, 0, 300, Some(0)));
+    }
+
+    assert_eq!(event_names(&events.borrow()), vec!["take-off", "landing", "completed"]);
+    let completed = events.borrow()[2].clone();
+    match completed {
+        FlightEvent::FlightCompleted { takeoff_ts, landing_ts, .. } => assert_eq!((takeoff_ts, landing_ts), (50, 200)),
+        e => panic!("unexpected {:?}", e),
+    }
+}
+
+#[test]
+fn slow_aircraft_at_altitude_without_agl_does_not_land() {
+    let (listener, events) = collector();
+    let mut detector = FlightPhaseDetector::new();
+    detector.set_event_listener(listener);
+
+    // a paraglider first heard in the air, then hanging in the wind at 2000 m:
+    for ts in 0..30 {
+        let mut b = fix(ts * 10, if ts < 5 { 40 } else { 5 }, 2000, None);
+        b.aircraft_type = AircraftType::Paraglider;
+        detector.ingest(b);
+    }
+    assert_eq!(detector.phase(ID), Some(FlightPhase::Airborne));
+
+    detector.check_gaps(10_000);
+    assert_eq!(event_names(&events.borrow()), vec!["lost"]);
+}
+
+#[test]
+fn landing_without_agl_is_decided_against_the_airfield_elevation() {
+    let (listener, events) = collector();
+    let mut detector = FlightPhaseDetector::new();
+    detector.set_airfields(AirfieldDatabase::new(vec![Airfield::new("LKTB", "Brno", 49.0, 16.0, 1200)]));
+    detector.set_event_listener(listener);
+
+    for ts in 0..10 {
+        detector.ingest(fix(ts * 10, 100, 1800, None));
+    }
+    for ts in 10..15 {
+        detector.ingest(fix(ts * 10, 0, 1210, None));
+    }
+
+    assert_eq!(event_names(&events.borrow()), vec!["landing"]);
+    assert_eq!(detector.phase(ID), Some(FlightPhase::OnGround));
+}
+
+#[test]
+fn landing_without_agl_is_decided_against_the_takeoff_elevation() {
+    let (listener, events) = collector();
+    let mut detector = FlightPhaseDetector::new();
+    detector.set_event_listener(listener);
+
+    for ts in 0..5 {
+        detector.ingest(fix(ts * 10, 0, 450, None));
+    }
+    for ts in 5..20 {
+        detector.ingest(fix(ts * 10, 100, 1200, None));
+    }
+    for ts in 20..25 {
+        detector.ingest(fix(ts * 10, 0, 455, None));
+    }
+
+    assert_eq!(event_names(&events.borrow()), vec!["take-off", "landing", "completed"]);
+}
+
+#[test]
+fn aircraft_lost_low_is_considered_landed() {
+    let (listener, events) = collector();
+    let mut detector = FlightPhaseDetector::new();
+    detector.set_event_listener(listener);
+
+    for ts in 0..10 {
+        detector.ingest(fix(ts * 10, 100, 600, Some(if ts < 5 { 400 } else { 80 })));
+    }
+    detector.check_gaps(10_000);
+
+    assert_eq!(event_names(&events.borrow()), vec!["landing"]);
+    let landing = events.borrow()[0].clone();
+    match landing {
+        FlightEvent::Landing { estimated, .. } => assert!(estimated),
+        e => panic!("unexpected {:?}", e),
+    }
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use ogn_client::data_structures::{AircraftBeacon, AircraftType};
+use ogn_client::gaggles::{GroupDetector, GroupEvent, GroupKind};
+use ogn_client::geodesy::destination_point;
+
+use common::{circling_track, collector, straight_track};
+
+/// Fixes of all the tracks in the order of their timestamps.
+fn merged(tracks: Vec<Vec<AircraftBeacon>>) -> Vec<AircraftBeacon> {
+    let mut beacons: Vec<AircraftBeacon> = tracks.into_iter().flatten().collect();
+    beacons.sort_by_key(|b| b.ts);
+
+    beacons
+}
+
+/// Start positions of aircraft flying side by side, `spacing` [m] apart to the north.
+fn abreast(n: usize, spacing: f64) -> Vec<(f64, f64)> {
+    (0..n).map(|i| destination_point(49.0, 16.0, 0.0, spacing * i as f64)).collect()
+}
+
+#[test]
+fn formation_is_announced_after_the_min_duration() {
+    let mut detector = GroupDetector::new();
+    let (listener, events) = collector();
+    detector.set_event_listener(listener);
+
+    let callsigns = ["FLRDDA5BA", "FLRDDA5BB", "FLRDDA5BC"];
+    let tracks = callsigns.iter().zip(abreast(3, 150.0))
+        .map(|(callsign, start)| straight_track(callsign, 0, 120, 2, start, 90.0, 150, 1000))
+        .collect();
+    for beacon in merged(tracks) {
+        detector.ingest(beacon);
+    }
+
+    let events = events.borrow();
+    assert_eq!(events.len(), 1);
+    match &events[0] {
+        GroupEvent::GroupFormed { kind, members, ts, .. } => {
+            assert_eq!(*kind, GroupKind::Formation);
+            assert_eq!(members, &callsigns.map(String::from).to_vec());
+            assert!(*ts >= 60);
+        },
+        event => panic!("unexpected {:?}", event),
+    }
+    assert_eq!(detector.group_of("FLRDDA5BB").map(|g| g.len()), Some(3));
+}
+
+#[test]
+fn aircraft_far_apart_make_no_group() {
+    let mut detector = GroupDetector::new();
+    let tracks = vec![
+        straight_track("FLRDDA5BA", 0, 120, 2, (49.0, 16.0), 90.0, 150, 1000),
+        straight_track("FLRDDA5BB", 0, 120, 2, (49.0, 16.0), 90.0, 150, 1500),
+        straight_track("FLRDDA5BC", 0, 120, 2, (49.1, 16.0), 90.0, 150, 1000),
+    ];
+    for beacon in merged(tracks) {
+        detector.ingest(beacon);
+    }
+
+    assert!(detector.groups().is_empty());
+}
+
+#[test]
+fn gliders_circling_together_make_a_gaggle() {
+    let mut detector = GroupDetector::new();
+    let tracks = vec![
+        circling_track("FLRDDA5BA", 0, 120, 2, (49.0, 16.0), 100.0, 100, 1000, 2.0),
+        circling_track("FLRDDA5BB", 0, 120, 2, (49.0, 16.0), 150.0, 100, 1100, 2.0),
+    ];
+    for beacon in merged(tracks) {
+        detector.ingest(beacon);
+    }
+
+    let groups = detector.groups();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].kind, GroupKind::Gaggle);
+}
+
+#[test]
+fn tug_with_glider_is_a_tow() {
+    let mut detector = GroupDetector::new();
+    let mut tug = straight_track("FLRDDA5BA", 0, 120, 2, (49.0, 16.0), 90.0, 120, 600);
+    for beacon in tug.iter_mut() {
+        beacon.aircraft_type = AircraftType::TowPlane;
+    }
+    let (lat, lon) = destination_point(49.0, 16.0, 270.0, 40.0);
+    let glider = straight_track("FLRDDA5BB", 0, 120, 2, (lat, lon), 90.0, 120, 600);
+    for beacon in merged(vec![tug, glider]) {
+        detector.ingest(beacon);
+    }
+
+    assert_eq!(detector.group_of("FLRDDA5BA").map(|g| g.kind), Some(GroupKind::Tow));
+}
+
+#[test]
+fn split_off_members_leave_and_form_their_own_group() {
+    let mut detector = GroupDetector::new();
+    let (listener, events) = collector();
+    detector.set_event_listener(listener);
+
+    let callsigns = ["FLRDDA5BA", "FLRDDA5BB", "FLRDDA5BC", "FLRDDA5BD"];
+    let together: Vec<Vec<AircraftBeacon>> = callsigns.iter().zip(abreast(4, 150.0))
+        .map(|(callsign, start)| straight_track(callsign, 0, 120, 2, start, 90.0, 150, 1000))
+        .collect();
+    // the last two turn north:
+    let apart = together.iter().enumerate()
+        .map(|(i, track)| {
+            let last = track.last().unwrap();
+            let course = if i < 2 { 90.0 } else { 0.0 };
+            straight_track(&last.callsign(), 122, 180, 2, (last.lat, last.lon), course, 150, 1000)
+        })
+        .collect();
+    for beacon in merged(together).into_iter().chain(merged(apart)) {
+        detector.ingest(beacon);
+    }
+
+    let events = events.borrow();
+    let formed: Vec<(u64, Vec<String>)> = events.iter()
+        .filter_map(|e| match e {
+            GroupEvent::GroupFormed { group_id, members, .. } => Some((*group_id, members.clone())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(formed.len(), 2);
+    assert_eq!(formed[0].1.len(), 4);
+    assert_eq!(formed[1].1, vec!["FLRDDA5BC".to_string(), "FLRDDA5BD".to_string()]);
+
+    let left: Vec<(u64, Vec<String>, Vec<String>)> = events.iter()
+        .filter_map(|e| match e {
+            GroupEvent::MembersChanged { group_id, left, members, .. } if !left.is_empty() => Some((*group_id, left.clone(), members.clone())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(left, vec![(formed[0].0, vec!["FLRDDA5BC".to_string(), "FLRDDA5BD".to_string()], vec!["FLRDDA5BA".to_string(), "FLRDDA5BB".to_string()])]);
+    assert!(!events.iter().any(|e| matches!(e, GroupEvent::GroupDissolved { .. })));
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use ogn_client::data_structures::AddressType;
+use ogn_client::geoid::{AltitudeReference, GeoidCorrection, GeoidModel};
+
+use common::beacon;
+
+/// Regional grid 49-50N 16-17E with 0.5 deg spacing, rows from the north.
+const GRD: &str = "49.0 50.0 16.0 17.0 0.5 0.5
+    46.0 47.0 48.0
+    45.0 46.0 47.0
+    44.0 45.0 46.0";
+
+#[test]
+fn separation_is_interpolated_inside_the_grid() {
+    let model = GeoidModel::from_grd_str(GRD).unwrap();
+    assert!(!model.is_global());
+
+    assert_eq!(model.separation(50.0, 16.0), Some(46.0));
+    assert_eq!(model.separation(49.0, 17.0), Some(46.0));
+    assert_eq!(model.separation(49.5, 16.5), Some(46.0));
+    assert_eq!(model.separation(49.75, 16.25), Some(46.0));
+    assert_eq!(model.separation(49.25, 16.75), Some(46.0));
+    assert_eq!(model.separation(49.75, 16.75), Some(47.0));
+}
+
+#[test]
+fn separation_outside_a_regional_grid_is_unknown() {
+    let model = GeoidModel::from_grd_str(GRD).unwrap();
+
+    assert_eq!(model.separation(50.1, 16.5), None);
+    assert_eq!(model.separation(49.5, 15.9), None);
+    assert_eq!(model.separation(49.5, 17.1), None);
+}
+
+#[test]
+fn invalid_grids_are_rejected() {
+    assert!(GeoidModel::from_grd_str("49.0 50.0 16.0 17.0 0.5").is_err());
+    assert!(GeoidModel::from_grd_str("50.0 49.0 16.0 17.0 0.5 0.5 1 2 3 4").is_err());
+    assert!(GeoidModel::from_grd_str("49.0 50.0 16.0 17.0 0.5 0.5 1 2 3").is_err());
+}
+
+#[test]
+fn ellipsoidal_altitudes_are_corrected() {
+    let mut correction = GeoidCorrection::new(GeoidModel::from_grd_str(GRD).unwrap());
+    correction.set_reference(AddressType::Flarm, AltitudeReference::Ellipsoid);
+
+    let mut flarm = beacon(0, "FLRDDA5BA", 49.75, 16.75, 1000);
+    correction.correct(&mut flarm);
+    assert_eq!((flarm.altitude, flarm.altitude_msl), (1000, 953));
+
+    let mut icao = beacon(0, "ICA4B4F2A", 49.75, 16.75, 1000);
+    correction.correct(&mut icao);
+    assert_eq!(icao.altitude_msl, 1000);
+
+    let mut outside = beacon(0, "FLRDDA5BA", 51.0, 16.75, 1000);
+    correction.correct(&mut outside);
+    assert_eq!(outside.altitude_msl, 1000);
+}
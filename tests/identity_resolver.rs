@@ -0,0 +1,89 @@
+mod common;
+
+use ogn_client::data_structures::AircraftType;
+use ogn_client::geodesy::destination_point;
+use ogn_client::identity_resolver::IdentityResolver;
+
+use common::{beacon, straight_track};
+
+#[test]
+fn same_address_from_two_networks_is_one_aircraft() {
+    let mut resolver = IdentityResolver::new();
+    for ts in 0..5 {
+        resolver.ingest(beacon(ts * 4, "FLR484D20", 49.0, 16.0 + ts as f64 * 0.001, 1000));
+        resolver.ingest(beacon(ts * 4, "ICA484D20", 49.0, 16.0 + ts as f64 * 0.001, 1000));
+    }
+
+    assert_eq!(resolver.logical_id("FLR484D20"), resolver.logical_id("ICA484D20"));
+    assert_eq!(resolver.iter().count(), 1);
+}
+
+#[test]
+fn different_addresses_are_not_linked_by_default() {
+    let mut resolver = IdentityResolver::new();
+    let flarm = straight_track("FLRDDA5BA", 0, 900, 4, (49.0, 16.0), 90.0, 100, 1000);
+    let ogn = straight_track("OGN123456", 0, 900, 4, (49.0, 16.0), 90.0, 100, 1000);
+    for (a, b) in flarm.into_iter().zip(ogn) {
+        resolver.ingest(a);
+        resolver.ingest(b);
+    }
+
+    assert_ne!(resolver.logical_id("FLRDDA5BA"), resolver.logical_id("OGN123456"));
+}
+
+/// Whether a tug of the type and its glider on the rope [m] get linked in a 15 minute aerotow.
+fn aerotow_linked(tug_type: AircraftType, rope: f64) -> bool {
+    let mut resolver = IdentityResolver::new();
+    resolver.set_colocation_linking(Some(600));
+
+    for tug in straight_track("ICA3D1234", 0, 900, 4, (49.0, 16.0), 90.0, 120, 1000) {
+        let (lat, lon) = destination_point(tug.lat, tug.lon, 270.0, rope);
+        let mut glider = beacon(tug.ts, "FLRDDA5BA", lat, lon, tug.altitude);
+        glider.speed = tug.speed;
+        glider.course = tug.course;
+
+        let mut tug = tug;
+        tug.aircraft_type = tug_type.clone();
+        resolver.ingest(tug);
+        resolver.ingest(glider);
+    }
+
+    resolver.logical_id("ICA3D1234") == resolver.logical_id("FLRDDA5BA")
+}
+
+#[test]
+fn tow_plane_and_glider_on_aerotow_are_not_linked() {
+    assert!(!aerotow_linked(AircraftType::TowPlane, 20.0));
+    assert!(!aerotow_linked(AircraftType::PistonPlane, 50.0));
+}
+
+#[test]
+fn colocated_ids_are_linked_when_enabled_and_unlinked_when_seen_apart() {
+    let mut resolver = IdentityResolver::new();
+    resolver.set_colocation_linking(Some(600));
+
+    let flarm = straight_track("FLRDDA5BA", 0, 700, 4, (49.0, 16.0), 90.0, 100, 1000);
+    let ogn = straight_track("OGN123456", 0, 700, 4, (49.0, 16.0), 90.0, 100, 1000);
+    for (a, b) in flarm.into_iter().zip(ogn) {
+        resolver.ingest(a);
+        resolver.ingest(b);
+    }
+    assert_eq!(resolver.logical_id("FLRDDA5BA"), resolver.logical_id("OGN123456"));
+
+    resolver.ingest(beacon(704, "FLRDDA5BA", 49.0, 16.3, 1000));
+    resolver.ingest(beacon(704, "OGN123456", 49.0, 16.0, 1000));
+    assert_ne!(resolver.logical_id("FLRDDA5BA"), resolver.logical_id("OGN123456"));
+    assert_eq!(resolver.iter().count(), 2);
+}
+
+#[test]
+fn explicit_link_survives_removal() {
+    let mut resolver = IdentityResolver::new();
+    resolver.link("FLRDDA5BA", "OGN123456");
+    resolver.ingest(beacon(0, "FLRDDA5BA", 49.0, 16.0, 1000));
+    resolver.remove("FLRDDA5BA");
+
+    resolver.ingest(beacon(10, "OGN123456", 49.5, 16.0, 1000));
+    resolver.ingest(beacon(10, "FLRDDA5BA", 49.5, 16.0, 1000));
+    assert_eq!(resolver.logical_id("FLRDDA5BA"), resolver.logical_id("OGN123456"));
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use ogn_client::data_structures::AircraftBeacon;
+use ogn_client::kinematics::KinematicsEstimator;
+
+use common::{circling_track, straight_track};
+
+/// Beacons as processed by the estimator.
+fn process(estimator: &mut KinematicsEstimator, beacons: Vec<AircraftBeacon>) -> Vec<AircraftBeacon> {
+    beacons.into_iter()
+        .map(|mut b| {
+            estimator.process(&mut b);
+            b
+        })
+        .collect()
+}
+
+#[test]
+fn missing_speed_and_course_are_derived() {
+    let mut estimator = KinematicsEstimator::new();
+    let track = straight_track("SKY3E5906", 0, 20, 4, (49.0, 16.0), 45.0, 120, 1000).into_iter()
+        .map(|mut b| {
+            b.speed = 0;
+            b.course = None;
+            b
+        })
+        .collect();
+
+    let last = process(&mut estimator, track).pop().unwrap();
+    assert!(last.derived.speed && last.derived.course);
+    assert!(last.speed.abs_diff(120) <= 1, "{} km/h", last.speed);
+    assert_eq!(last.course, Some(45));
+}
+
+#[test]
+fn implausible_course_is_replaced_and_plausible_one_kept() {
+    let mut estimator = KinematicsEstimator::new();
+    let mut track = straight_track("FLRDDA5BA", 0, 20, 4, (49.0, 16.0), 90.0, 100, 1000);
+    track[4].course = Some(270);
+    track[5].course = Some(95);
+
+    let track = process(&mut estimator, track);
+    assert_eq!(track[4].course, Some(90));
+    assert!(track[4].derived.course);
+    assert_eq!(track[5].course, Some(95));
+    assert!(!track[5].derived.course);
+}
+
+#[test]
+fn implausible_turn_rate_is_replaced() {
+    let mut estimator = KinematicsEstimator::new();
+    // 100 km/h on a 100 m radius = 15.9 deg/s, reported as flying straight:
+    let track = circling_track("FLRDDA5BA", 0, 20, 2, (49.0, 16.0), 100.0, 100, 1000, 0.0).into_iter()
+        .map(|mut b| {
+            b.turn_rate = Some(0.0);
+            b
+        })
+        .collect();
+
+    let last = process(&mut estimator, track).pop().unwrap();
+    assert!(last.derived.turn_rate);
+    let turn_rate = last.turn_rate.unwrap() * 3.0;
+    assert!((turn_rate - 15.9).abs() < 1.0, "{} deg/s", turn_rate);
+}
+
+#[test]
+fn fix_after_a_position_jump_is_the_base_of_the_next_derivation() {
+    let mut estimator = KinematicsEstimator::new();
+    let mut track = straight_track("FLRDDA5BA", 0, 8, 4, (49.0, 16.0), 0.0, 100, 1000);
+    track[1].lat += 0.1;   // 11 km in 4 s
+    track[2].lat += 0.1;
+    for b in track.iter_mut() {
+        b.speed = 0;
+    }
+
+    let track = process(&mut estimator, track);
+    assert!(!track[1].derived.speed);
+    assert!(track[2].derived.speed);
+    assert!(track[2].speed.abs_diff(100) <= 1, "{} km/h", track[2].speed);
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use ogn_client::airfields::{Airfield, AirfieldDatabase};
+use ogn_client::data_structures::{AircraftBeacon, AircraftType};
+use ogn_client::geodesy::destination_point;
+use ogn_client::logbook::{LaunchMethod, Logbook, LogbookEntry};
+
+use common::beacon;
+
+const FIELD: (f64, f64) = (49.0, 16.0);
+const FIELD_ELEVATION: i32 = 300;
+const GLIDER: &str = "FLRDDA5BA";
+const TUG: &str = "FLRDD1234";
+
+fn logbook() -> Logbook {
+    Logbook::new(AirfieldDatabase::new(vec![Airfield::new("LKTB", "Brno", FIELD.0, FIELD.1, FIELD_ELEVATION)]))
+}
+
+/// Fix `distance` [m] east of the airfield, `height` [m] above it, at the speed [km/h].
+fn fix(ts: i64, callsign: &str, aircraft_type: AircraftType, distance: f64, height: i32, speed: u32) -> AircraftBeacon {
+    let (lat, lon) = destination_point(FIELD.0, FIELD.1, 90.0, distance);
+    let mut b = beacon(ts, callsign, lat, lon, FIELD_ELEVATION + height);
+    b.aircraft_type = aircraft_type;
+    b.speed = speed;
+    b.climb_rate = Some(0.0);
+    b
+}
+
+/// Standing on the airfield from ts 0 to 40 and taking off at 44.
+fn ground_roll(callsign: &str, aircraft_type: AircraftType) -> Vec<AircraftBeacon> {
+    (0..=10).map(|i| fix(i * 4, callsign, aircraft_type.clone(), 0.0, 0, 0)).collect()
+}
+
+fn entry<'a>(logbook: &'a Logbook, id: &str) -> &'a LogbookEntry {
+    logbook.entries().iter().find(|e| e.aircraft_id == id).unwrap()
+}
+
+#[test]
+fn aerotow_pairs_the_glider_with_its_tug_and_finds_the_release() {
+    let mut logbook = logbook();
+    for (glider, tug) in ground_roll(GLIDER, AircraftType::Glider).into_iter().zip(ground_roll(TUG, AircraftType::TowPlane)) {
+        logbook.ingest(tug);
+        logbook.ingest(glider);
+    }
+
+    // climbing together at 3 m/s with the glider 50 m behind, then the glider turns away at 450 m:
+    for i in 11..=60 {
+        let ts = i * 4;
+        let t = (ts - 44) as f64;
+        let height = (3.0 * t) as i32;
+        let distance = 30.0 * t;
+        if height < 450 {
+            logbook.ingest(fix(ts, TUG, AircraftType::TowPlane, distance + 50.0, height, 108));
+            logbook.ingest(fix(ts, GLIDER, AircraftType::Glider, distance, height, 108));
+        } else {
+            logbook.ingest(fix(ts, TUG, AircraftType::TowPlane, distance + 50.0, 300, 150));
+            logbook.ingest(fix(ts, GLIDER, AircraftType::Glider, distance - 2000.0, height, 100));
+        }
+    }
+
+    let glider = entry(&logbook, GLIDER);
+    assert_eq!(glider.launch_method, LaunchMethod::Aerotow);
+    assert_eq!(glider.tow_partner.as_deref(), Some(TUG));
+    assert_eq!(glider.takeoff_airfield.as_deref(), Some("LKTB"));
+    let release_height = glider.release_height.unwrap();
+    assert!((440..=460).contains(&release_height), "release at {} m", release_height);
+
+    let tug = entry(&logbook, TUG);
+    assert_eq!(tug.launch_method, LaunchMethod::Towing);
+    assert_eq!(tug.tow_partner.as_deref(), Some(GLIDER));
+}
+
+#[test]
+fn glider_launch_without_tug_or_winch_is_unknown() {
+    let mut logbook = logbook();
+    for b in ground_roll(GLIDER, AircraftType::Glider) {
+        logbook.ingest(b);
+    }
+    for i in 11..=60 {
+        let ts = i * 4;
+        logbook.ingest(fix(ts, GLIDER, AircraftType::Glider, 30.0 * (ts - 44) as f64, (ts - 44) as i32, 100));
+    }
+
+    assert_eq!(entry(&logbook, GLIDER).launch_method, LaunchMethod::Unknown);
+}
+
+#[test]
+fn landing_completes_the_entry() {
+    let mut logbook = logbook();
+    for b in ground_roll(TUG, AircraftType::TowPlane) {
+        logbook.ingest(b);
+    }
+    for i in 11..=100 {
+        logbook.ingest(fix(i * 4, TUG, AircraftType::TowPlane, 2000.0, 500, 150));
+    }
+    for i in 101..=110 {
+        logbook.ingest(fix(i * 4, TUG, AircraftType::TowPlane, 0.0, 0, 0));
+    }
+
+    let tug = entry(&logbook, TUG);
+    assert_eq!(tug.launch_method, LaunchMethod::SelfLaunch);
+    assert_eq!((tug.takeoff_ts, tug.landing_ts), (44, Some(404)));
+    assert_eq!(tug.duration, Some(360));
+    assert_eq!(tug.landing_airfield.as_deref(), Some("LKTB"));
+}
+
+#[test]
+fn old_entries_are_dropped_after_the_retention_time() {
+    let mut logbook = logbook();
+    logbook.set_retention(3600);
+    for b in ground_roll(TUG, AircraftType::TowPlane) {
+        logbook.ingest(b);
+    }
+    for i in 11..=20 {
+        logbook.ingest(fix(i * 4, TUG, AircraftType::TowPlane, 2000.0, 500, 150));
+    }
+    assert_eq!(logbook.entries().len(), 1);
+
+    logbook.ingest(fix(7200, GLIDER, AircraftType::Glider, 0.0, 0, 0));
+    logbook.ingest(fix(7300, GLIDER, AircraftType::Glider, 0.0, 0, 0));
+    assert!(logbook.entries().is_empty());
+}
+
+#[test]
+fn csv_quotes_text_fields_containing_the_separator() {
+    let mut logbook = logbook();
+    for mut b in ground_roll(TUG, AircraftType::TowPlane) {
+        b.registration = String::from("OK-A;12");
+        logbook.ingest(b);
+    }
+    for i in 11..=20 {
+        let mut b = fix(i * 4, TUG, AircraftType::TowPlane, 2000.0, 500, 150);
+        b.registration = String::from("OK-A;12");
+        logbook.ingest(b);
+    }
+
+    let line = entry(&logbook, TUG).to_csv_line();
+    assert!(line.contains(";\"OK-A;12\";"), "{}", line);
+    assert_eq!(line.split(';').count(), LogbookEntry::CSV_HEADER.split(';').count() + 1);
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use ogn_client::privacy::{PrivacyFilter, PrivacyMode};
+
+use common::beacon;
+
+const LINE: &str = "FLRDDA5BA>APRS,qAS,LKKA:/074548h4959.99N/01708.55E'086/007/A=000617 !W51! id0ADDA5BA -019fpm +0.0rot 6.2dB";
+
+#[test]
+fn no_track_aircraft_are_dropped_and_others_passed() {
+    let mut filter = PrivacyFilter::new();
+    let mut no_track = beacon(0, "FLRDDA5BA", 49.0, 16.0, 1000);
+    no_track.do_not_track = true;
+
+    assert!(filter.process(no_track).is_empty());
+    let passed = filter.process(beacon(1, "FLRDD1234", 49.0, 16.0, 1000));
+    assert_eq!(passed.len(), 1);
+    assert_eq!(passed[0].addr, "DD1234");
+}
+
+#[test]
+fn stealth_aircraft_get_a_stable_pseudonym() {
+    let mut filter = PrivacyFilter::new();
+    let stealth = |ts| {
+        let mut b = beacon(ts, "FLRDDA5BA", 49.0, 16.0, 1000);
+        b.stealth = true;
+        b.registration = String::from("OK-1234");
+        b
+    };
+
+    let first = filter.process(stealth(0)).remove(0);
+    let second = filter.process(stealth(1)).remove(0);
+    assert_ne!(first.addr, "DDA5BA");
+    assert_eq!(first.addr, second.addr);
+    assert!(first.registration.is_empty());
+}
+
+#[test]
+fn coarsened_minutes_carry_into_the_degrees() {
+    let mut filter = PrivacyFilter::new();
+    filter.set_all_modes(&[PrivacyMode::Coarsen]);
+    filter.set_coarsen_precision(0.01);
+
+    let lines = filter.filter_line(LINE, &beacon(0, "FLRDDA5BA", 49.9998, 17.1425, 188));
+    assert_eq!(lines.len(), 1);
+    let (line, coarsened) = &lines[0];
+    assert!(line.contains("h5000.00N/01708.40E'"), "{}", line);
+    assert!(line.contains("!W00!"), "{}", line);
+    assert!((coarsened.lat - 50.0).abs() < 1e-9);
+}
+
+#[test]
+fn delayed_beacons_are_released_after_the_delay() {
+    let mut filter = PrivacyFilter::new();
+    filter.set_all_modes(&[PrivacyMode::Delay]);
+    filter.set_delay(60);
+
+    assert!(filter.process(beacon(0, "FLRDDA5BA", 49.0, 16.0, 1000)).is_empty());
+    assert!(filter.process(beacon(30, "FLRDDA5BA", 49.0, 16.0, 1000)).is_empty());
+    let released: Vec<i64> = filter.process(beacon(60, "FLRDDA5BA", 49.0, 16.0, 1000)).iter().map(|b| b.ts).collect();
+    assert_eq!(released, vec![0]);
+}
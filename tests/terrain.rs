@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use ogn_client::terrain::{DemElevationProvider, ElevationProvider};
+
+
+/// Empty directory for the tiles of the test.
+fn tile_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ogn_client_terrain_{}_{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+/// Little-endian single-strip GeoTIFF of the 1x1 deg square north-east of (lat, lon), samples at the corners
+/// (pixel-is-point), with the strip data as given.
+fn geotiff(lat: f64, lon: f64, width: u32, height: u32, bits_per_sample: u16, sample_format: u16, predictor: u16, strip: &[u8]) -> Vec<u8> {
+    let scale = [1.0 / (width - 1) as f64, 1.0 / (height - 1) as f64, 0.0];
+    let tiepoint = [0.0, 0.0, 0.0, lon, lat + 1.0, 0.0];
+    let geokeys: [u16; 8] = [1, 1, 0, 1, 1025, 0, 1, 2];
+    let doubles = |values: &[f64]| values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+    let shorts = |values: &[u16]| values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+
+    // (tag, type, count, value) - the value inline when it fits in 4 bytes, otherwise stored after the directory:
+    let entries: Vec<(u16, u16, u32, Vec<u8>)> = vec![
+        (256, 4, 1, width.to_le_bytes().to_vec()),
+        (257, 4, 1, height.to_le_bytes().to_vec()),
+        (258, 3, 1, shorts(&[bits_per_sample])),
+        (259, 3, 1, shorts(&[1])),
+        (273, 4, 1, Vec::new()),    // strip offset, filled in below
+        (278, 4, 1, height.to_le_bytes().to_vec()),
+        (279, 4, 1, (strip.len() as u32).to_le_bytes().to_vec()),
+        (317, 3, 1, shorts(&[predictor])),
+        (339, 3, 1, shorts(&[sample_format])),
+        (33550, 12, 3, doubles(&scale)),
+        (33922, 12, 6, doubles(&tiepoint)),
+        (34735, 3, 8, shorts(&geokeys)),
+    ];
+
+    let mut extra_pos = 8 + 2 + entries.len() * 12 + 4;
+    let mut directory = (entries.len() as u16).to_le_bytes().to_vec();
+    let mut extra: Vec<u8> = Vec::new();
+    for (tag, field_type, count, value) in &entries {
+        directory.extend(tag.to_le_bytes());
+        directory.extend(field_type.to_le_bytes());
+        directory.extend(count.to_le_bytes());
+        if value.len() > 4 {
+            directory.extend((extra_pos as u32).to_le_bytes());
+            extra_pos += value.len();
+            extra.extend(value);
+        } else {
+            let mut inline = value.clone();
+            inline.resize(4, 0);
+            directory.extend(inline);
+        }
+    }
+    directory.extend(0u32.to_le_bytes());   // no next directory
+
+    // the strip goes last:
+    let strip_offset_entry = 2 + 4 * 12 + 8;
+    directory[strip_offset_entry..strip_offset_entry + 4].copy_from_slice(&(extra_pos as u32).to_le_bytes());
+
+    let mut data = b"II".to_vec();
+    data.extend(42u16.to_le_bytes());
+    data.extend(8u32.to_le_bytes());
+    data.extend(directory);
+    data.extend(extra);
+    data.extend(strip);
+
+    data
+}
+
+/// Rows of 32 bit float samples encoded with the floating point predictor (byte planes, big-endian, differenced).
+fn float_predictor_strip(rows: &[Vec<f32>]) -> Vec<u8> {
+    rows.iter()
+        .flat_map(|row| {
+            let bytes: Vec<[u8; 4]> = row.iter().map(|v| v.to_be_bytes()).collect();
+            let planes: Vec<u8> = (0..4).flat_map(|j| bytes.iter().map(move |b| b[j])).collect();
+            let mut encoded = planes.clone();
+            for i in 1..planes.len() {
+                encoded[i] = planes[i].wrapping_sub(planes[i - 1]);
+            }
+            encoded
+        })
+        .collect()
+}
+
+fn assert_elevation(provider: &mut DemElevationProvider, lat: f64, lon: f64, expected: f64) {
+    let elevation = provider.elevation(lat, lon).unwrap();
+    assert!((elevation - expected).abs() < 0.01, "{} m at {} {}", elevation, lat, lon);
+}
+
+#[test]
+fn hgt_tile_is_interpolated() {
+    let dir = tile_dir("hgt");
+    let samples: Vec<u8> = [100i16, 200, 300, 400].iter().flat_map(|v| v.to_be_bytes()).collect();
+    fs::write(dir.join("N49E016.hgt"), samples).unwrap();
+
+    let mut provider = DemElevationProvider::new(&dir).unwrap();
+    assert_eq!(provider.num_tiles(), 1);
+    assert_elevation(&mut provider, 49.0, 16.0, 300.0);
+    assert_elevation(&mut provider, 49.75, 16.25, 175.0);
+    assert_elevation(&mut provider, 49.5, 16.5, 250.0);
+    assert!(provider.elevation(48.5, 16.5).is_none());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn void_hgt_samples_are_left_out() {
+    let dir = tile_dir("void");
+    let samples: Vec<u8> = [100i16, -32768, 300, 400].iter().flat_map(|v| v.to_be_bytes()).collect();
+    fs::write(dir.join("N49E016.hgt"), samples).unwrap();
+
+    let mut provider = DemElevationProvider::new(&dir).unwrap();
+    assert_elevation(&mut provider, 49.5, 16.5, 800.0 / 3.0);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn geotiff_with_horizontal_differencing_is_read() {
+    let dir = tile_dir("predictor2");
+    let strip: Vec<u8> = [100i16, 100, 300, 100].iter().flat_map(|v| v.to_le_bytes()).collect();  // 100 200; 300 400
+    fs::write(dir.join("Copernicus_DSM_COG_10_N49_00_E016_00_DEM.tif"), geotiff(49.0, 16.0, 2, 2, 16, 2, 2, &strip)).unwrap();
+
+    let mut provider = DemElevationProvider::new(&dir).unwrap();
+    assert_elevation(&mut provider, 49.0, 16.0, 300.0);
+    assert_elevation(&mut provider, 49.5, 16.5, 250.0);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn geotiff_with_floating_point_predictor_is_read() {
+    let dir = tile_dir("predictor3");
+    let strip = float_predictor_strip(&[vec![100.5, 200.5], vec![300.5, 400.5]]);
+    fs::write(dir.join("N49E016.tif"), geotiff(49.0, 16.0, 2, 2, 32, 3, 3, &strip)).unwrap();
+
+    let mut provider = DemElevationProvider::new(&dir).unwrap();
+    assert_elevation(&mut provider, 49.0, 16.0, 300.5);
+    assert_elevation(&mut provider, 49.5, 16.5, 250.5);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn floating_point_predictor_on_integer_samples_is_rejected() {
+    let dir = tile_dir("predictor3_int");
+    let strip: Vec<u8> = [100i16, 200, 300, 400].iter().flat_map(|v| v.to_le_bytes()).collect();
+    fs::write(dir.join("N49E016.tif"), geotiff(49.0, 16.0, 2, 2, 16, 2, 3, &strip)).unwrap();
+
+    let mut provider = DemElevationProvider::new(&dir).unwrap();
+    assert_eq!(provider.num_tiles(), 1);
+    assert!(provider.elevation(49.5, 16.5).is_none());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use ogn_client::thermals::{CirclingDetector, ThermalDetector};
+
+use common::{circling_track, straight_track};
+
+#[test]
+fn circling_is_detected_after_a_full_turn() {
+    let mut detector = CirclingDetector::new();
+    // 100 km/h on a 100 m radius: a turn in 23 s
+    let track = circling_track("FLRDDA5BA", 0, 60, 2, (49.0, 16.0), 100.0, 100, 1000, 2.0);
+
+    for beacon in track.iter().take_while(|b| b.ts < 20) {
+        detector.process(beacon);
+    }
+    assert!(detector.current("FLRDDA5BA").is_none());
+
+    for beacon in track.iter().skip_while(|b| b.ts < 20) {
+        detector.process(beacon);
+    }
+    let segment = detector.current("FLRDDA5BA").unwrap();
+    assert!(segment.num_turns() > 2.0, "{} turns", segment.num_turns());
+    assert!((segment.climb_rate() - 2.0).abs() < 0.1, "{} m/s", segment.climb_rate());
+}
+
+#[test]
+fn straight_flight_is_not_circling() {
+    let mut detector = CirclingDetector::new();
+    for beacon in straight_track("FLRDDA5BA", 0, 120, 2, (49.0, 16.0), 90.0, 100, 1000) {
+        assert!(detector.process(&beacon).is_none());
+    }
+    assert!(detector.current("FLRDDA5BA").is_none());
+}
+
+#[test]
+fn circling_segment_ends_after_flying_straight() {
+    let mut detector = CirclingDetector::new();
+    let mut track = circling_track("FLRDDA5BA", 0, 60, 2, (49.0, 16.0), 100.0, 100, 1000, 2.0);
+    let last = track.last().unwrap().clone();
+    track.extend(straight_track("FLRDDA5BA", 62, 30, 2, (last.lat, last.lon), last.course.unwrap() as f64, 100, 1120));
+
+    let finished: Vec<_> = track.iter().filter_map(|b| detector.process(b)).collect();
+    assert_eq!(finished.len(), 1);
+    assert!(finished[0].is_circling());
+    assert!(detector.current("FLRDDA5BA").is_none());
+}
+
+#[test]
+fn climbing_circles_make_a_thermal() {
+    let mut detector = ThermalDetector::new();
+    for beacon in circling_track("FLRDDA5BA", 0, 120, 2, (49.0, 16.0), 100.0, 100, 1000, 2.5) {
+        detector.ingest(beacon);
+    }
+    for beacon in circling_track("FLRDDA5BB", 30, 90, 2, (49.001, 16.0), 120.0, 100, 1200, 2.0) {
+        detector.ingest(beacon);
+    }
+
+    let thermals = detector.thermals();
+    assert_eq!(thermals.len(), 1);
+    assert!(thermals[0].climb_rate > 1.5, "{} m/s", thermals[0].climb_rate);
+    assert_eq!(thermals[0].aircraft(), vec!["FLRDDA5BA".to_string(), "FLRDDA5BB".to_string()]);
+}
+
+#[test]
+fn circling_in_sink_is_no_thermal() {
+    let mut detector = ThermalDetector::new();
+    for beacon in circling_track("FLRDDA5BA", 0, 120, 2, (49.0, 16.0), 100.0, 100, 1000, -1.0) {
+        detector.ingest(beacon);
+    }
+    detector.expire(300);
+
+    assert!(detector.thermals().is_empty());
+}
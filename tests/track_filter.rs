@@ -0,0 +1,71 @@
+mod common;
+
+use ogn_client::data_structures::AircraftBeacon;
+use ogn_client::geodesy::haversine_distance;
+use ogn_client::track_filter::TrackFilter;
+
+use common::straight_track;
+
+/// Beacons as processed by the filter.
+fn process(filter: &mut TrackFilter, beacons: Vec<AircraftBeacon>) -> Vec<AircraftBeacon> {
+    beacons.into_iter()
+        .map(|mut b| {
+            filter.process(&mut b);
+            b
+        })
+        .collect()
+}
+
+#[test]
+fn position_spike_is_rejected() {
+    let mut filter = TrackFilter::new();
+    let mut track = straight_track("FLRDDA5BA", 0, 40, 4, (49.0, 16.0), 90.0, 100, 1000);
+    track[5].lat += 0.05;
+
+    let rejected: Vec<bool> = process(&mut filter, track).iter().map(|b| b.rejected).collect();
+    assert_eq!(rejected.iter().filter(|r| **r).count(), 1);
+    assert!(rejected[5]);
+}
+
+#[test]
+fn altitude_spike_is_rejected() {
+    let mut filter = TrackFilter::new();
+    let mut track = straight_track("FLRDDA5BA", 0, 40, 4, (49.0, 16.0), 90.0, 100, 1000);
+    track[3].altitude += 500;
+
+    let track = process(&mut filter, track);
+    assert!(track[3].rejected);
+    assert!(!track[4].rejected);
+}
+
+#[test]
+fn track_starts_over_at_a_new_position_after_rejections_in_row() {
+    let mut filter = TrackFilter::new();
+    let mut track = straight_track("FLRDDA5BA", 0, 40, 4, (49.0, 16.0), 90.0, 100, 1000);
+    for b in track.iter_mut().skip(5) {
+        b.lat += 0.5;
+    }
+
+    let rejected: Vec<bool> = process(&mut filter, track).iter().map(|b| b.rejected).collect();
+    assert_eq!(rejected, vec![false, false, false, false, false, true, true, false, false, false, false]);
+}
+
+#[test]
+fn smoothing_reduces_the_position_noise() {
+    let mut filter = TrackFilter::new();
+    filter.set_smoothing(true);
+    let truth = straight_track("FLRDDA5BA", 0, 200, 2, (49.0, 16.0), 90.0, 100, 1000);
+    let noisy: Vec<AircraftBeacon> = truth.iter().enumerate()
+        .map(|(i, b)| {
+            let mut b = b.clone();
+            b.lat += if i % 2 == 0 { 0.0002 } else { -0.0002 };   // +-22 m
+            b
+        })
+        .collect();
+
+    let smoothed = process(&mut filter, noisy.clone());
+    let error = |track: &[AircraftBeacon]| track.iter().zip(truth.iter()).skip(20)
+        .map(|(b, t)| haversine_distance(b.lat, b.lon, t.lat, t.lon))
+        .sum::<f64>() / (truth.len() - 20) as f64;
+    assert!(error(&smoothed) < error(&noisy) / 2.0, "{} m vs {} m", error(&smoothed), error(&noisy));
+}
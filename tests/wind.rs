@@ -0,0 +1,79 @@
+mod common;
+
+use ogn_client::data_structures::AircraftBeacon;
+use ogn_client::geodesy::destination_point;
+use ogn_client::wind::{wind_from_turn, Wind, WindEstimator};
+
+use common::circling_track;
+
+/// The circling drifted by the wind (movement of the air east, north [m/s]) with the course & speed over ground.
+fn drifted(track: Vec<AircraftBeacon>, (east, north): (f64, f64)) -> Vec<AircraftBeacon> {
+    let start_ts = track[0].ts;
+
+    track.into_iter()
+        .map(|mut b| {
+            let t = (b.ts - start_ts) as f64;
+            let (lat, lon) = destination_point(b.lat, b.lon, 90.0, east * t);
+            let (lat, lon) = destination_point(lat, lon, 0.0, north * t);
+            let (sin, cos) = (b.course.unwrap() as f64).to_radians().sin_cos();
+            let airspeed = b.speed as f64 / 3.6;
+            let (ground_east, ground_north) = (airspeed * sin + east, airspeed * cos + north);
+            b.lat = lat;
+            b.lon = lon;
+            b.course = Some((ground_east.atan2(ground_north).to_degrees().rem_euclid(360.0).round() as u64) % 360);
+            b.speed = (ground_east.hypot(ground_north) * 3.6).round() as u32;
+            b
+        })
+        .collect()
+}
+
+#[test]
+fn wind_is_found_from_a_drifted_turn() {
+    // a westerly of 5 m/s moves the air to the east:
+    let turn = drifted(circling_track("FLRDDA5BA", 0, 24, 2, (49.0, 16.0), 100.0, 100, 1000, 1.5), (5.0, 0.0));
+
+    let wind = wind_from_turn(&turn).unwrap();
+    assert!((wind.direction - 270.0).abs() < 5.0, "from {} deg", wind.direction);
+    assert!((wind.speed - 5.0).abs() < 0.5, "{} m/s", wind.speed);
+}
+
+#[test]
+fn calm_turn_gives_no_wind() {
+    let turn = circling_track("FLRDDA5BA", 0, 24, 2, (49.0, 16.0), 100.0, 100, 1000, 1.5);
+
+    let wind = wind_from_turn(&turn).unwrap();
+    assert!(wind.speed < 0.5, "{} m/s", wind.speed);
+}
+
+#[test]
+fn too_few_fixes_give_no_wind() {
+    let turn = drifted(circling_track("FLRDDA5BA", 0, 24, 6, (49.0, 16.0), 100.0, 100, 1000, 1.5), (5.0, 0.0));
+
+    assert!(wind_from_turn(&turn).is_none());
+}
+
+#[test]
+fn wind_vector_round_trips() {
+    let wind = Wind::from_vector(-3.0, -4.0);
+    assert!((wind.direction - 36.87).abs() < 0.01);
+    assert!((wind.speed - 5.0).abs() < 1e-9);
+
+    let (east, north) = wind.to_vector();
+    assert!((east + 3.0).abs() < 1e-9 && (north + 4.0).abs() < 1e-9);
+}
+
+#[test]
+fn estimator_gives_the_wind_in_the_band_of_the_circling() {
+    let mut estimator = WindEstimator::new();
+    for beacon in drifted(circling_track("FLRDDA5BA", 0, 120, 2, (49.1, 16.1), 100.0, 100, 1100, 1.5), (0.0, -8.0)) {
+        estimator.ingest(beacon);
+    }
+
+    assert!(estimator.samples().count() >= 3);
+    let estimate = estimator.wind_at(49.1, 16.1, 1200).unwrap();
+    assert_eq!((estimate.altitude_base, estimate.altitude_top), (1000, 1500));
+    assert_eq!(estimate.num_aircraft, 1);
+    assert!(estimate.wind.direction < 5.0 || estimate.wind.direction > 355.0, "from {} deg", estimate.wind.direction);
+    assert!((estimate.wind.speed - 8.0).abs() < 1.0, "{} m/s", estimate.wind.speed);
+    assert!(estimator.wind_at(49.1, 16.1, 2200).is_none());
+}